@@ -0,0 +1,198 @@
+//! WebSocket room server that lets two browsers exchange session descriptions
+//! and ICE candidates instead of copying connection codes by hand.
+//!
+//! ```text
+//! cargo run -p signaling-server -- 127.0.0.1:9001
+//! ```
+
+mod rooms;
+mod websocket;
+
+use std::io::{self, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use rooms::{ClientId, ClientMessage, Rooms, ServerMessage};
+use websocket::Message;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:9001";
+
+fn main() {
+    let addr = std::env::args()
+        .nth(1)
+        .or_else(|| std::env::var("SIGNALING_ADDR").ok())
+        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Cannot listen on {}: {}", addr, err);
+            std::process::exit(1);
+        }
+    };
+    println!("Signaling server listening on ws://{}", addr);
+
+    run(listener, Arc::default());
+}
+
+fn run(listener: TcpListener, rooms: Arc<Mutex<Rooms>>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let rooms = rooms.clone();
+        thread::spawn(move || {
+            let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+            if let Err(err) = serve(stream, rooms) {
+                eprintln!("{}: {}", peer, err);
+            }
+        });
+    }
+}
+
+// One thread reads the client's frames; a second one writes whatever the
+// rooms send it, so a slow client never blocks the others.
+fn serve(stream: TcpStream, rooms: Arc<Mutex<Rooms>>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let writer = Arc::new(Mutex::new(stream.try_clone()?));
+    websocket::handshake(&mut reader, &mut *lock(&writer))?;
+
+    let (outbox, inbox) = mpsc::channel::<ServerMessage>();
+    let outgoing = writer.clone();
+    let writer_thread = thread::spawn(move || {
+        for message in inbox {
+            let text = serde_json::to_string(&message).expect("server messages always serialize");
+            if websocket::write_text(&mut *lock(&outgoing), &text).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut id: Option<ClientId> = None;
+    let result = loop {
+        let message = match websocket::read_message(&mut reader) {
+            Ok(message) => message,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break Ok(()),
+            Err(err) => break Err(err),
+        };
+
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Ping(payload) => match websocket::write_pong(&mut *lock(&writer), &payload) {
+                Ok(()) => continue,
+                Err(err) => break Err(err),
+            },
+            Message::Pong => continue,
+            Message::Close => {
+                let _ = websocket::write_close(&mut *lock(&writer));
+                break Ok(());
+            }
+        };
+
+        let reply = match (serde_json::from_str::<ClientMessage>(&text), id) {
+            (Ok(ClientMessage::Join { room }), None) => lock(&rooms)
+                .join(&room, outbox.clone())
+                .map(|joined| id = Some(joined)),
+            (Ok(ClientMessage::Join { .. }), Some(_)) => Err("already in a room".to_string()),
+            (Ok(ClientMessage::Signal { to, data }), Some(from)) => lock(&rooms).signal(from, to, data),
+            (Ok(ClientMessage::Signal { .. }), None) => Err("join a room first".to_string()),
+            (Err(err), _) => Err(format!("invalid message: {}", err)),
+        };
+        if let Err(message) = reply {
+            let _ = outbox.send(ServerMessage::Error { message });
+        }
+    };
+
+    if let Some(id) = id {
+        lock(&rooms).leave(id);
+    }
+    drop(outbox);
+    let _ = writer_thread.join();
+    let _ = stream.shutdown(std::net::Shutdown::Both);
+    result
+}
+
+// A panicked connection thread must not take the whole server down.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, Read, Write};
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    struct Client {
+        reader: BufReader<TcpStream>,
+        writer: TcpStream,
+    }
+
+    impl Client {
+        fn connect(addr: &str) -> Client {
+            let mut writer = TcpStream::connect(addr).unwrap();
+            writer
+                .write_all(b"GET / HTTP/1.1\r\nHost: test\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n")
+                .unwrap();
+            let mut reader = BufReader::new(writer.try_clone().unwrap());
+            let mut line = String::new();
+            while line != "\r\n" {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+            }
+            Client { reader, writer }
+        }
+
+        fn send(&mut self, message: Value) {
+            let frame = websocket::client_frame(0x1, true, message.to_string().as_bytes());
+            self.writer.write_all(&frame).unwrap();
+        }
+
+        fn receive(&mut self) -> Value {
+            let mut head = [0u8; 2];
+            self.reader.read_exact(&mut head).unwrap();
+            let len = match head[1] {
+                126 => {
+                    let mut len = [0u8; 2];
+                    self.reader.read_exact(&mut len).unwrap();
+                    u16::from_be_bytes(len) as usize
+                }
+                len => len as usize,
+            };
+            let mut payload = vec![0u8; len];
+            self.reader.read_exact(&mut payload).unwrap();
+            serde_json::from_slice(&payload).unwrap()
+        }
+    }
+
+    #[test]
+    fn two_clients_exchange_signals_through_a_room() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || run(listener, Arc::default()));
+
+        let mut host = Client::connect(&addr);
+        host.send(json!({ "type": "join", "room": "test" }));
+        let host_id = host.receive()["id"].clone();
+
+        let mut guest = Client::connect(&addr);
+        guest.send(json!({ "type": "join", "room": "test" }));
+        assert_eq!(guest.receive(), json!({ "type": "welcome", "id": guest_id(&host_id), "peers": [host_id] }));
+        assert_eq!(host.receive(), json!({ "type": "peer_joined", "id": guest_id(&host_id) }));
+
+        let offer = json!({ "kind": "description", "description": "v=0 ".repeat(100) });
+        host.send(json!({ "type": "signal", "to": guest_id(&host_id), "data": offer }));
+        assert_eq!(guest.receive(), json!({ "type": "signal", "from": host_id, "data": offer }));
+
+        drop(guest);
+        assert_eq!(host.receive(), json!({ "type": "peer_left", "id": guest_id(&host_id) }));
+    }
+
+    fn guest_id(host_id: &Value) -> u64 {
+        host_id.as_u64().unwrap() + 1
+    }
+}
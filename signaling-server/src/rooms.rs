@@ -0,0 +1,140 @@
+//! Rooms and the relay protocol. The server never looks inside `data`: it
+//! only knows who is in which room and passes signals between them. The
+//! browser side of this protocol lives in `src/webrtc_chat/signaling.rs`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::mpsc::Sender;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub type ClientId = u32;
+
+const MAX_ROOM_NAME_LEN: usize = 64;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Join { room: String },
+    Signal { to: ClientId, data: Value },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// Sent on joining, with everyone who was already in the room.
+    Welcome { id: ClientId, peers: Vec<ClientId> },
+    PeerJoined { id: ClientId },
+    PeerLeft { id: ClientId },
+    Signal { from: ClientId, data: Value },
+    Error { message: String },
+}
+
+/// Everyone connected, grouped by room. Each client is reached through the
+/// channel its connection thread drains.
+#[derive(Default)]
+pub struct Rooms {
+    next_id: ClientId,
+    rooms: HashMap<String, BTreeMap<ClientId, Sender<ServerMessage>>>,
+    membership: HashMap<ClientId, String>,
+}
+
+impl Rooms {
+    pub fn join(&mut self, room: &str, outbox: Sender<ServerMessage>) -> Result<ClientId, String> {
+        let room = room.trim();
+        if room.is_empty() || room.len() > MAX_ROOM_NAME_LEN {
+            return Err(format!("room names are 1 to {} bytes", MAX_ROOM_NAME_LEN));
+        }
+
+        self.next_id += 1;
+        let id = self.next_id;
+        let members = self.rooms.entry(room.to_string()).or_default();
+        let peers: Vec<ClientId> = members.keys().copied().collect();
+
+        for member in members.values() {
+            let _ = member.send(ServerMessage::PeerJoined { id });
+        }
+        let _ = outbox.send(ServerMessage::Welcome { id, peers });
+        members.insert(id, outbox);
+        self.membership.insert(id, room.to_string());
+        Ok(id)
+    }
+
+    /// Passes `data` on to `to`, if it shares a room with `from`.
+    pub fn signal(&self, from: ClientId, to: ClientId, data: Value) -> Result<(), String> {
+        let target = self
+            .membership
+            .get(&from)
+            .and_then(|room| self.rooms.get(room))
+            .and_then(|members| members.get(&to))
+            .ok_or_else(|| format!("peer {} is not in your room", to))?;
+        target
+            .send(ServerMessage::Signal { from, data })
+            .map_err(|_| format!("peer {} has left", to))
+    }
+
+    pub fn leave(&mut self, id: ClientId) {
+        let Some(room) = self.membership.remove(&id) else {
+            return;
+        };
+        let Some(members) = self.rooms.get_mut(&room) else {
+            return;
+        };
+        members.remove(&id);
+        for member in members.values() {
+            let _ = member.send(ServerMessage::PeerLeft { id });
+        }
+        if members.is_empty() {
+            self.rooms.remove(&room);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn drain(inbox: &Receiver<ServerMessage>) -> Vec<ServerMessage> {
+        inbox.try_iter().collect()
+    }
+
+    #[test]
+    fn members_see_each_other_and_signals_stay_in_the_room() {
+        let mut rooms = Rooms::default();
+        let (host_outbox, host_inbox) = channel();
+        let (guest_outbox, guest_inbox) = channel();
+        let (stranger_outbox, stranger_inbox) = channel();
+
+        let host = rooms.join("lobby", host_outbox).unwrap();
+        let guest = rooms.join(" lobby ", guest_outbox).unwrap();
+        let stranger = rooms.join("elsewhere", stranger_outbox).unwrap();
+
+        assert_eq!(
+            drain(&host_inbox),
+            vec![ServerMessage::Welcome { id: host, peers: vec![] }, ServerMessage::PeerJoined { id: guest }]
+        );
+        assert_eq!(drain(&guest_inbox), vec![ServerMessage::Welcome { id: guest, peers: vec![host] }]);
+
+        rooms.signal(host, guest, json!({ "kind": "description" })).unwrap();
+        assert_eq!(
+            drain(&guest_inbox),
+            vec![ServerMessage::Signal { from: host, data: json!({ "kind": "description" }) }]
+        );
+        assert!(rooms.signal(stranger, host, json!(null)).is_err());
+        assert_eq!(drain(&stranger_inbox).len(), 1);
+
+        rooms.leave(guest);
+        assert_eq!(drain(&host_inbox), vec![ServerMessage::PeerLeft { id: guest }]);
+        assert!(rooms.signal(host, guest, json!(null)).is_err());
+    }
+
+    #[test]
+    fn empty_room_names_are_refused() {
+        let (outbox, _inbox) = channel();
+        assert!(Rooms::default().join("  ", outbox).is_err());
+    }
+}
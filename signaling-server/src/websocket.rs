@@ -0,0 +1,231 @@
+//! Just enough of RFC 6455 for a signaling relay: the opening handshake and
+//! text, ping and close frames. Extensions and binary messages are refused.
+
+use std::io::{self, BufRead, Read, Write};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Signaling messages are an SDP blob at most; anything larger is abuse.
+pub const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Ping(Vec<u8>),
+    Pong,
+    Close,
+}
+
+/// `Sec-WebSocket-Accept` for a client's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = sha1_smol::Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(HANDSHAKE_GUID.as_bytes());
+    STANDARD.encode(sha1.digest().bytes())
+}
+
+/// Reads the HTTP upgrade request and answers it.
+pub fn handshake(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<()> {
+    let mut key = None;
+    let mut request_line = true;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("connection closed during the handshake"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if request_line {
+            request_line = false;
+            if !line.starts_with("GET ") {
+                return Err(invalid("not a GET request"));
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("sec-websocket-key")
+        {
+            key = Some(value.trim().to_string());
+        }
+    }
+
+    let Some(key) = key else {
+        writer.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(invalid("missing Sec-WebSocket-Key"));
+    };
+    write!(
+        writer,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    writer.flush()
+}
+
+/// Reads one message, joining fragmented frames.
+pub fn read_message(reader: &mut impl Read) -> io::Result<Message> {
+    let mut text = Vec::new();
+    loop {
+        let (fin, opcode, payload) = read_frame(reader)?;
+        match opcode {
+            OPCODE_TEXT | OPCODE_CONTINUATION => {
+                text.extend_from_slice(&payload);
+                if text.len() > MAX_MESSAGE_LEN {
+                    return Err(invalid("message too large"));
+                }
+                if fin {
+                    return String::from_utf8(text).map(Message::Text).map_err(|_| invalid("text is not UTF-8"));
+                }
+            }
+            OPCODE_PING => return Ok(Message::Ping(payload)),
+            OPCODE_PONG => return Ok(Message::Pong),
+            OPCODE_CLOSE => return Ok(Message::Close),
+            _ => return Err(invalid("unsupported opcode")),
+        }
+    }
+}
+
+pub fn write_text(writer: &mut impl Write, text: &str) -> io::Result<()> {
+    write_frame(writer, OPCODE_TEXT, text.as_bytes())
+}
+
+pub fn write_pong(writer: &mut impl Write, payload: &[u8]) -> io::Result<()> {
+    write_frame(writer, OPCODE_PONG, payload)
+}
+
+pub fn write_close(writer: &mut impl Write) -> io::Result<()> {
+    write_frame(writer, OPCODE_CLOSE, &[])
+}
+
+// Servers never mask their frames.
+fn write_frame(writer: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut header = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => header.push(len as u8),
+        len @ 126..=0xFFFF => {
+            header.push(126);
+            header.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            header.push(127);
+            header.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    writer.write_all(&header)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+// Client frames are always masked.
+fn read_frame(reader: &mut impl Read) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    reader.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0F;
+    if head[1] & 0x80 == 0 {
+        return Err(invalid("client frames must be masked"));
+    }
+
+    let len = match head[1] & 0x7F {
+        126 => {
+            let mut len = [0u8; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_be_bytes(len) as usize
+        }
+        127 => {
+            let mut len = [0u8; 8];
+            reader.read_exact(&mut len)?;
+            usize::try_from(u64::from_be_bytes(len)).unwrap_or(usize::MAX)
+        }
+        len => len as usize,
+    };
+    if len > MAX_MESSAGE_LEN {
+        return Err(invalid("frame too large"));
+    }
+
+    let mut mask = [0u8; 4];
+    reader.read_exact(&mut mask)?;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Frames a client would send, for tests.
+#[cfg(test)]
+pub fn client_frame(opcode: u8, fin: bool, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x37, 0xfa, 0x21, 0x3d];
+    let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+    match payload.len() {
+        len @ 0..=125 => frame.push(0x80 | len as u8),
+        len => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(index, byte)| byte ^ mask[index % 4]));
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    }
+
+    #[test]
+    fn handshake_answers_an_upgrade_request() {
+        let request = "GET /room HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                       sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let mut response = Vec::new();
+        handshake(&mut Cursor::new(request), &mut response).unwrap();
+
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[test]
+    fn fragmented_and_control_frames_are_read() {
+        let long = "x".repeat(300);
+        let mut input = client_frame(OPCODE_TEXT, false, b"hel");
+        input.extend(client_frame(OPCODE_CONTINUATION, true, b"lo"));
+        input.extend(client_frame(OPCODE_TEXT, true, long.as_bytes()));
+        input.extend(client_frame(OPCODE_PING, true, b"hi"));
+        input.extend(client_frame(OPCODE_CLOSE, true, b""));
+        let mut reader = Cursor::new(input);
+
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Text("hello".into()));
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Text(long));
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Ping(b"hi".to_vec()));
+        assert_eq!(read_message(&mut reader).unwrap(), Message::Close);
+    }
+
+    #[test]
+    fn unmasked_frames_are_refused() {
+        let mut reader = Cursor::new(vec![0x81, 0x02, b'h', b'i']);
+        assert!(read_message(&mut reader).is_err());
+    }
+}
@@ -0,0 +1,400 @@
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{File, HtmlInputElement};
+use yew::prelude::*;
+
+use crate::utils::file_sharing::FileHandler;
+use crate::webrtc_chat::chat_model::MessageSender;
+use crate::webrtc_chat::history::{self, Conversation, StoredMessage};
+use crate::webrtc_chat::transcript::{Transcript, TranscriptMessage};
+
+const SNIPPET_LEN: usize = 80;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub on_close: Callback<()>,
+}
+
+/// Lists saved WebRTC conversations, with search across every message and a
+/// read-only view of a single conversation or of an imported JSON transcript.
+pub struct ChatHistory {
+    conversations: Vec<Conversation>,
+    messages: Vec<StoredMessage>,
+    selected: Option<String>,
+    imported: Option<Transcript>, // shown only, never saved to the history
+    query: String,
+    status: Option<String>,
+}
+
+pub enum Msg {
+    Loaded(Vec<Conversation>, Vec<StoredMessage>),
+    LoadFailed(String),
+    UpdateQuery(String),
+    Open(String),
+    Back,
+    Delete(String),
+    Deleted(String),
+    Import(File),
+    Imported(Transcript),
+    ImportFailed(String),
+    Close,
+}
+
+impl Component for ChatHistory {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        spawn_local(async move {
+            let loaded = async {
+                Ok::<_, JsValue>((
+                    history::load_conversations().await?,
+                    history::load_all_messages().await?,
+                ))
+            }
+            .await;
+
+            match loaded {
+                Ok((conversations, messages)) => link.send_message(Msg::Loaded(conversations, messages)),
+                Err(err) => link.send_message(Msg::LoadFailed(format!("{:?}", err))),
+            }
+        });
+
+        Self {
+            conversations: vec![],
+            messages: vec![],
+            selected: None,
+            imported: None,
+            query: String::new(),
+            status: Some("Loading history...".to_string()),
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::Loaded(conversations, messages) => {
+                self.conversations = conversations;
+                self.messages = messages;
+                self.status = None;
+                true
+            }
+            Msg::LoadFailed(err) => {
+                self.status = Some(format!("Could not read the history: {}", err));
+                true
+            }
+            Msg::UpdateQuery(query) => {
+                self.query = query;
+                true
+            }
+            Msg::Open(conversation_id) => {
+                self.selected = Some(conversation_id);
+                true
+            }
+            Msg::Back => {
+                self.selected = None;
+                self.imported = None;
+                true
+            }
+            Msg::Delete(conversation_id) => {
+                let confirmed = web_sys::window()
+                    .and_then(|window| {
+                        window
+                            .confirm_with_message("Delete this conversation from this browser?")
+                            .ok()
+                    })
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match history::delete_conversation(&conversation_id).await {
+                        Ok(()) => link.send_message(Msg::Deleted(conversation_id)),
+                        Err(err) => link.send_message(Msg::LoadFailed(format!("{:?}", err))),
+                    }
+                });
+                false
+            }
+            Msg::Deleted(conversation_id) => {
+                self.conversations.retain(|c| c.id != conversation_id);
+                self.messages.retain(|m| m.conversation_id != conversation_id);
+                if self.selected.as_deref() == Some(conversation_id.as_str()) {
+                    self.selected = None;
+                }
+                true
+            }
+            Msg::Import(file) => {
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    let transcript = FileHandler::read_file(&file).await.and_then(|info| {
+                        let json = String::from_utf8(info.data).map_err(|_| "The file is not text".to_string())?;
+                        Transcript::from_json(&json)
+                    });
+
+                    match transcript {
+                        Ok(transcript) => link.send_message(Msg::Imported(transcript)),
+                        Err(err) => link.send_message(Msg::ImportFailed(err)),
+                    }
+                });
+                false
+            }
+            Msg::Imported(transcript) => {
+                self.imported = Some(transcript);
+                self.selected = None;
+                self.status = None;
+                true
+            }
+            Msg::ImportFailed(err) => {
+                self.status = Some(format!("Could not import the transcript: {}", err));
+                true
+            }
+            Msg::Close => {
+                ctx.props().on_close.emit(());
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let selected = self
+            .selected
+            .as_ref()
+            .and_then(|id| self.conversations.iter().find(|c| &c.id == id));
+
+        html! {
+            <div class="fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50">
+                <div class="bg-white rounded-2xl shadow-2xl max-w-2xl w-full mx-4 p-6 max-h-[85vh] flex flex-col">
+                    <div class="flex justify-between items-center mb-4">
+                        <h3 class="text-xl font-bold text-gray-800">
+                            {
+                                match (&self.imported, selected) {
+                                    (Some(transcript), _) => transcript.title.clone(),
+                                    (None, Some(conversation)) => conversation.title.clone(),
+                                    (None, None) => "Chat History".to_string(),
+                                }
+                            }
+                        </h3>
+                        <button
+                            class="text-gray-500 hover:text-gray-700 text-2xl"
+                            onclick={ctx.link().callback(|_| Msg::Close)}
+                        >
+                            {"×"}
+                        </button>
+                    </div>
+
+                    if let Some(status) = &self.status {
+                        <p class="text-sm text-gray-600 mb-4">{ status.clone() }</p>
+                    }
+
+                    {
+                        match (&self.imported, selected) {
+                            (Some(transcript), _) => self.render_transcript(ctx, transcript),
+                            (None, Some(conversation)) => self.render_conversation(ctx, conversation),
+                            (None, None) => self.render_conversation_list(ctx),
+                        }
+                    }
+                </div>
+            </div>
+        }
+    }
+}
+
+impl ChatHistory {
+    fn messages_of<'a>(&'a self, conversation_id: &'a str) -> impl Iterator<Item = &'a StoredMessage> + 'a {
+        self.messages
+            .iter()
+            .filter(move |message| message.conversation_id == conversation_id)
+    }
+
+    fn render_conversation_list(&self, ctx: &Context<Self>) -> Html {
+        let query = self.query.trim().to_lowercase();
+
+        let rows: Vec<Html> = self
+            .conversations
+            .iter()
+            .filter_map(|conversation| {
+                let matches: Vec<&StoredMessage> = self
+                    .messages_of(&conversation.id)
+                    .filter(|message| !query.is_empty() && message.content.to_lowercase().contains(&query))
+                    .collect();
+                let title_matches = conversation.title.to_lowercase().contains(&query);
+                if !query.is_empty() && !title_matches && matches.is_empty() {
+                    return None;
+                }
+
+                let open_id = conversation.id.clone();
+                let delete_id = conversation.id.clone();
+                Some(html! {
+                    <li class="border border-gray-200 rounded-lg p-3 flex justify-between items-start">
+                        <div class="min-w-0 flex-1">
+                            <div class="font-medium text-gray-800 truncate">{ conversation.title.clone() }</div>
+                            <div class="text-xs text-gray-500">
+                                { format!("{} · {} messages", format_time(conversation.last_activity), self.messages_of(&conversation.id).count()) }
+                            </div>
+                            if let Some(first) = matches.first() {
+                                <div class="text-sm text-gray-600 mt-1 truncate">
+                                    { format!("{} match(es): “{}”", matches.len(), snippet(&first.content)) }
+                                </div>
+                            }
+                        </div>
+                        <div class="flex space-x-2 ml-3">
+                            <button
+                                class="bg-blue-600 hover:bg-blue-700 text-white text-sm font-medium py-1 px-3 rounded-lg transition-colors"
+                                onclick={ctx.link().callback(move |_| Msg::Open(open_id.clone()))}
+                            >
+                                {"Open"}
+                            </button>
+                            <button
+                                class="bg-red-500 hover:bg-red-600 text-white text-sm font-medium py-1 px-3 rounded-lg transition-colors"
+                                onclick={ctx.link().callback(move |_| Msg::Delete(delete_id.clone()))}
+                            >
+                                {"Delete"}
+                            </button>
+                        </div>
+                    </li>
+                })
+            })
+            .collect();
+
+        let onimport = ctx.link().batch_callback(|e: Event| {
+            let input = e.target_unchecked_into::<HtmlInputElement>();
+            let file = input.files().and_then(|files| files.get(0));
+            // Lets the same file be picked again after a failed import
+            input.set_value("");
+            file.map(Msg::Import)
+        });
+
+        html! {
+            <>
+                <div class="flex space-x-2 mb-4">
+                    <input
+                        type="search"
+                        class="flex-1 border border-gray-300 rounded-lg px-3 py-2 text-sm focus:outline-none focus:ring-2 focus:ring-blue-500"
+                        placeholder="Search messages..."
+                        value={self.query.clone()}
+                        oninput={ctx.link().callback(|e: InputEvent| Msg::UpdateQuery(e.target_unchecked_into::<HtmlInputElement>().value()))}
+                    />
+                    <label
+                        class="bg-gray-200 hover:bg-gray-300 text-gray-700 text-sm font-medium py-2 px-3 rounded-lg transition-colors cursor-pointer"
+                        title="Open a transcript exported as JSON"
+                    >
+                        {"📥 Import JSON"}
+                        <input type="file" accept="application/json,.json" class="hidden" onchange={onimport} />
+                    </label>
+                </div>
+                if rows.is_empty() && self.status.is_none() {
+                    <p class="text-sm text-gray-500 text-center py-6">
+                        { if self.query.is_empty() { "No saved conversations yet" } else { "Nothing matches your search" } }
+                    </p>
+                }
+                <ul class="space-y-2 overflow-y-auto">{ for rows }</ul>
+            </>
+        }
+    }
+
+    fn render_conversation(&self, ctx: &Context<Self>, conversation: &Conversation) -> Html {
+        html! {
+            <>
+                <button
+                    class="self-start text-sm text-blue-600 hover:underline mb-3"
+                    onclick={ctx.link().callback(|_| Msg::Back)}
+                >
+                    {"← All conversations"}
+                </button>
+                <div class="overflow-y-auto space-y-2 bg-gray-50 rounded-lg p-3">
+                    {
+                        for self.messages_of(&conversation.id).map(|message| {
+                            let (name, class) = match message.sender {
+                                MessageSender::Me => ("Me", "ml-auto bg-blue-500 text-white"),
+                                MessageSender::Other => ("Friend", "bg-gray-200 text-gray-800"),
+                            };
+                            html! {
+                                <div class={classes!("max-w-md", "p-3", "rounded-2xl", class)}>
+                                    <div class="text-xs font-bold opacity-75">
+                                        { format!("{} · {}", name, format_time(message.timestamp)) }
+                                    </div>
+                                    if message.deleted {
+                                        <div class="italic opacity-75">{"🚫 This message was deleted"}</div>
+                                    } else {
+                                        <div>
+                                            { message.content.clone() }
+                                            if message.edited_at.is_some() {
+                                                <span class="text-xs opacity-75 ml-2">{"(edited)"}</span>
+                                            }
+                                        </div>
+                                    }
+                                </div>
+                            }
+                        })
+                    }
+                </div>
+            </>
+        }
+    }
+
+    fn render_transcript(&self, ctx: &Context<Self>, transcript: &Transcript) -> Html {
+        html! {
+            <>
+                <div class="flex justify-between items-center mb-3">
+                    <button
+                        class="text-sm text-blue-600 hover:underline"
+                        onclick={ctx.link().callback(|_| Msg::Back)}
+                    >
+                        {"← All conversations"}
+                    </button>
+                    <span class="text-xs text-gray-500">
+                        { format!("Imported transcript · exported {} · read only", format_time(transcript.exported_at)) }
+                    </span>
+                </div>
+                <div class="overflow-y-auto space-y-2 bg-gray-50 rounded-lg p-3">
+                    { for transcript.messages.iter().map(|message| render_transcript_message(transcript, message)) }
+                </div>
+            </>
+        }
+    }
+}
+
+fn render_transcript_message(transcript: &Transcript, message: &TranscriptMessage) -> Html {
+    let class = if message.from_me { "ml-auto bg-blue-500 text-white" } else { "bg-gray-200 text-gray-800" };
+
+    html! {
+        <div class={classes!("max-w-md", "p-3", "rounded-2xl", class)}>
+            <div class="text-xs font-bold opacity-75">
+                { format!("{} · {}", message.sender, format_time(message.timestamp)) }
+            </div>
+            if let Some(parent) = transcript.parent_of(message) {
+                <div class="text-xs opacity-75 border-l-2 border-current pl-2 my-1">
+                    { format!("↪ {}: {}", parent.sender, snippet(&parent.content)) }
+                </div>
+            }
+            if message.deleted {
+                <div class="italic opacity-75">{"🚫 This message was deleted"}</div>
+            } else {
+                if let Some(name) = &message.attachment {
+                    <div class="text-sm">{ format!("📎 {}", name) }</div>
+                }
+                <div class="whitespace-pre-wrap">
+                    { message.content.clone() }
+                    if message.edited {
+                        <span class="text-xs opacity-75 ml-2">{"(edited)"}</span>
+                    }
+                </div>
+            }
+        </div>
+    }
+}
+
+fn format_time(timestamp: u64) -> String {
+    let date = js_sys::Date::new(&JsValue::from_f64(timestamp as f64));
+    String::from(date.to_locale_string("en-US", &JsValue::UNDEFINED))
+}
+
+fn snippet(content: &str) -> String {
+    if content.chars().count() > SNIPPET_LEN {
+        format!("{}…", content.chars().take(SNIPPET_LEN).collect::<String>())
+    } else {
+        content.to_string()
+    }
+}
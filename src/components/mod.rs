@@ -3,4 +3,8 @@ pub mod login;
 pub mod callback;
 pub mod particle_simulation;
 pub mod navbar; 
-pub mod particle_system;
\ No newline at end of file
+pub mod particle_system;
+pub mod qr_scanner;
+pub mod chat_history;
+pub mod video_tile;
+pub mod whiteboard;
\ No newline at end of file
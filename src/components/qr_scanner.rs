@@ -0,0 +1,287 @@
+use gloo_timers::callback::Interval;
+use js_sys::{Object, Reflect};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    CanvasRenderingContext2d, File, HtmlCanvasElement, HtmlImageElement, HtmlInputElement,
+    HtmlVideoElement, MediaStream, MediaStreamConstraints, MediaStreamTrack, Url,
+};
+use yew::prelude::*;
+
+use crate::utils::file_sharing::FileHandler;
+use crate::utils::qr_code::QrCodeDecoder;
+
+const SCAN_INTERVAL_MS: u32 = 250;
+
+// Uploaded photos are scaled down to this many pixels on their longest side
+// before decoding; QR modules stay well above one pixel at this size.
+const MAX_IMAGE_DIMENSION: f64 = 1024.0;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub on_decoded: Callback<String>,
+    pub on_close: Callback<()>,
+}
+
+pub struct QrScanner {
+    video_ref: NodeRef,
+    canvas_ref: NodeRef,
+    stream: Option<MediaStream>,
+    _interval: Option<Interval>,
+    status: String,
+}
+
+pub enum Msg {
+    CameraReady(MediaStream),
+    CameraFailed(String),
+    ScanFrame,
+    ImageSelected(File),
+    ImageFailed(String),
+    Decoded(String),
+    Close,
+}
+
+impl Component for QrScanner {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            video_ref: NodeRef::default(),
+            canvas_ref: NodeRef::default(),
+            stream: None,
+            _interval: None,
+            status: "Starting camera...".to_string(),
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if first_render {
+            let link = ctx.link().clone();
+            spawn_local(async move {
+                match start_camera().await {
+                    Ok(stream) => link.send_message(Msg::CameraReady(stream)),
+                    Err(err) => link.send_message(Msg::CameraFailed(err)),
+                }
+            });
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::CameraReady(stream) => {
+                if let Some(video) = self.video_ref.cast::<HtmlVideoElement>() {
+                    video.set_src_object(Some(&stream));
+                    let _ = video.play();
+                }
+                self.stream = Some(stream);
+
+                let link = ctx.link().clone();
+                self._interval = Some(Interval::new(SCAN_INTERVAL_MS, move || {
+                    link.send_message(Msg::ScanFrame);
+                }));
+                self.status = "Point the camera at the QR code".to_string();
+                true
+            }
+            Msg::CameraFailed(err) => {
+                self.status = format!("Camera unavailable ({}). Upload a picture instead.", err);
+                true
+            }
+            Msg::ScanFrame => match self.scan_video_frame() {
+                Some(content) => {
+                    ctx.link().send_message(Msg::Decoded(content));
+                    false
+                }
+                None => false,
+            },
+            Msg::ImageSelected(file) => {
+                self.status = format!("Reading {}...", file.name());
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match decode_image_file(&file).await {
+                        Ok(content) => link.send_message(Msg::Decoded(content)),
+                        Err(err) => link.send_message(Msg::ImageFailed(err)),
+                    }
+                });
+                true
+            }
+            Msg::ImageFailed(err) => {
+                self.status = err;
+                true
+            }
+            Msg::Decoded(content) => {
+                self.stop_camera();
+                ctx.props().on_decoded.emit(content);
+                false
+            }
+            Msg::Close => {
+                self.stop_camera();
+                ctx.props().on_close.emit(());
+                false
+            }
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        self.stop_camera();
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="fixed inset-0 bg-black bg-opacity-50 flex items-center justify-center z-50">
+                <div class="bg-white rounded-2xl shadow-2xl max-w-md w-full mx-4 p-6">
+                    <div class="flex justify-between items-center mb-4">
+                        <h3 class="text-xl font-bold text-gray-800">{"Scan QR Code"}</h3>
+                        <button
+                            class="text-gray-500 hover:text-gray-700 text-2xl"
+                            onclick={ctx.link().callback(|_| Msg::Close)}
+                        >
+                            {"×"}
+                        </button>
+                    </div>
+
+                    <div class="relative bg-black rounded-lg overflow-hidden mb-4">
+                        <video
+                            ref={self.video_ref.clone()}
+                            class="w-full h-64 object-cover"
+                            autoplay=true
+                            muted=true
+                            playsinline=true
+                        />
+                        <div class="absolute inset-8 border-4 border-white border-opacity-70 rounded-lg pointer-events-none"></div>
+                    </div>
+                    <canvas ref={self.canvas_ref.clone()} class="hidden"></canvas>
+
+                    <p class="text-sm text-gray-600 text-center mb-4">{ self.status.clone() }</p>
+
+                    <label class="block w-full bg-gray-100 hover:bg-gray-200 text-gray-700 font-medium py-2 px-4 rounded-lg transition-colors text-center cursor-pointer mb-2">
+                        {"🖼️ Upload a picture of the code"}
+                        <input
+                            type="file"
+                            accept="image/*"
+                            class="hidden"
+                            onchange={ctx.link().batch_callback(|e: Event| {
+                                let input = e.target_unchecked_into::<HtmlInputElement>();
+                                input.files().and_then(|files| files.get(0)).map(Msg::ImageSelected)
+                            })}
+                        />
+                    </label>
+
+                    <button
+                        class="w-full bg-blue-600 hover:bg-blue-700 text-white font-medium py-2 px-4 rounded-lg transition-colors"
+                        onclick={ctx.link().callback(|_| Msg::Close)}
+                    >
+                        {"Cancel"}
+                    </button>
+                </div>
+            </div>
+        }
+    }
+}
+
+impl QrScanner {
+    fn scan_video_frame(&self) -> Option<String> {
+        let video = self.video_ref.cast::<HtmlVideoElement>()?;
+        let canvas = self.canvas_ref.cast::<HtmlCanvasElement>()?;
+        let (width, height) = (video.video_width(), video.video_height());
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        canvas.set_width(width);
+        canvas.set_height(height);
+        let context = get_context(&canvas).ok()?;
+        context
+            .draw_image_with_html_video_element(&video, 0.0, 0.0)
+            .ok()?;
+
+        decode_canvas(&context, width, height).ok()
+    }
+
+    fn stop_camera(&mut self) {
+        self._interval = None;
+        if let Some(stream) = self.stream.take() {
+            for track in stream.get_tracks().iter() {
+                track.unchecked_into::<MediaStreamTrack>().stop();
+            }
+        }
+    }
+}
+
+async fn start_camera() -> Result<MediaStream, String> {
+    let media_devices = web_sys::window()
+        .ok_or("no window")?
+        .navigator()
+        .media_devices()
+        .map_err(|_| "camera access is not supported")?;
+
+    // Prefer the rear camera on phones
+    let video_constraints = Object::new();
+    Reflect::set(&video_constraints, &"facingMode".into(), &"environment".into())
+        .map_err(|_| "invalid constraints")?;
+
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_video(&video_constraints);
+    constraints.set_audio(&JsValue::FALSE);
+
+    let promise = media_devices
+        .get_user_media_with_constraints(&constraints)
+        .map_err(|_| "camera access is not supported")?;
+
+    JsFuture::from(promise)
+        .await
+        .map(|stream| stream.unchecked_into::<MediaStream>())
+        .map_err(|_| "permission denied or no camera found".to_string())
+}
+
+async fn decode_image_file(file: &File) -> Result<String, String> {
+    // Screenshots of a shown code decode straight from their exact pixels
+    if file.type_() == "image/png" {
+        let png = FileHandler::read_file(file).await.map(|info| info.data);
+        if let Some(content) = png.ok().and_then(|data| QrCodeDecoder::decode_png(&data).ok()) {
+            return Ok(content);
+        }
+    }
+
+    let url = Url::create_object_url_with_blob(file).map_err(|_| "Failed to read the picture")?;
+    let image = HtmlImageElement::new().map_err(|_| "Failed to read the picture")?;
+    image.set_src(&url);
+    let loaded = JsFuture::from(image.decode()).await;
+    let _ = Url::revoke_object_url(&url);
+    loaded.map_err(|_| "That file is not a picture this browser can open")?;
+
+    let scale = (MAX_IMAGE_DIMENSION / image.natural_width().max(image.natural_height()) as f64).min(1.0);
+    let width = ((image.natural_width() as f64 * scale) as u32).max(1);
+    let height = ((image.natural_height() as f64 * scale) as u32).max(1);
+
+    let canvas = gloo_utils::document()
+        .create_element("canvas")
+        .map_err(|_| "Failed to create canvas")?
+        .unchecked_into::<HtmlCanvasElement>();
+    canvas.set_width(width);
+    canvas.set_height(height);
+
+    let context = get_context(&canvas)?;
+    context
+        .draw_image_with_html_image_element_and_dw_and_dh(&image, 0.0, 0.0, width as f64, height as f64)
+        .map_err(|_| "Failed to draw the picture")?;
+
+    decode_canvas(&context, width, height)
+}
+
+fn get_context(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d, String> {
+    canvas
+        .get_context("2d")
+        .ok()
+        .flatten()
+        .and_then(|context| context.dyn_into::<CanvasRenderingContext2d>().ok())
+        .ok_or_else(|| "Failed to get 2D context".to_string())
+}
+
+fn decode_canvas(context: &CanvasRenderingContext2d, width: u32, height: u32) -> Result<String, String> {
+    let image_data = context
+        .get_image_data(0.0, 0.0, width as f64, height as f64)
+        .map_err(|_| "Failed to read pixels")?;
+
+    QrCodeDecoder::decode_rgba(width as usize, height as usize, &image_data.data())
+}
@@ -0,0 +1,66 @@
+use web_sys::{HtmlVideoElement, MediaStream};
+use yew::prelude::*;
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub stream: Option<MediaStream>,
+    pub label: AttrValue,
+    /// Our own tile is always muted, or we would hear ourselves.
+    #[prop_or_default]
+    pub muted: bool,
+    /// Shown over the tile instead of the picture, e.g. when the camera is off.
+    #[prop_or_default]
+    pub placeholder: Option<AttrValue>,
+    #[prop_or_default]
+    pub class: Classes,
+}
+
+/// One participant's audio and video in a call.
+pub struct VideoTile {
+    video_ref: NodeRef,
+}
+
+impl Component for VideoTile {
+    type Message = ();
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self { video_ref: NodeRef::default() }
+    }
+
+    // `srcObject` has no HTML attribute, so it is set after every render
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        let Some(video) = self.video_ref.cast::<HtmlVideoElement>() else {
+            return;
+        };
+        video.set_muted(ctx.props().muted);
+        if video.src_object() != ctx.props().stream {
+            video.set_src_object(ctx.props().stream.as_ref());
+            let _ = video.play();
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let props = ctx.props();
+
+        html! {
+            <div class={classes!("relative", "bg-gray-900", "rounded-lg", "overflow-hidden", props.class.clone())}>
+                <video
+                    ref={self.video_ref.clone()}
+                    class="w-full h-full object-cover"
+                    autoplay=true
+                    playsinline=true
+                    muted={props.muted}
+                ></video>
+                if let Some(placeholder) = &props.placeholder {
+                    <div class="absolute inset-0 flex items-center justify-center bg-gray-800 text-white text-3xl">
+                        { placeholder.clone() }
+                    </div>
+                }
+                <div class="absolute bottom-1 left-2 text-xs text-white bg-black bg-opacity-50 rounded px-1">
+                    { props.label.clone() }
+                </div>
+            </div>
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use std::rc::Rc;
+
+use base64::{engine::general_purpose, Engine as _};
+use wasm_bindgen::JsCast;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+use yew::prelude::*;
+
+use crate::utils::file_sharing::{FileHandler, FileInfo};
+use crate::webrtc_chat::whiteboard::{
+    clamp_point, ellipse_box, Board, BoardOp, Item, Point, Shape, BOARD_HEIGHT, BOARD_WIDTH,
+    MAX_STROKE_POINTS,
+};
+
+const PALETTE: [u32; 6] = [0x111827, 0xef4444, 0xf59e0b, 0x22c55e, 0x3b82f6, 0xa855f7];
+const WIDTHS: [u8; 3] = [2, 4, 8];
+const ERASER_RADIUS: f64 = 8.0;
+
+// Pointer moves closer than this to the last point add nothing to a stroke
+const MIN_STROKE_STEP: f64 = 2.0;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tool {
+    Pen,
+    Line,
+    Rect,
+    Ellipse,
+    Eraser,
+}
+
+impl Tool {
+    const ALL: [Tool; 5] = [Tool::Pen, Tool::Line, Tool::Rect, Tool::Ellipse, Tool::Eraser];
+
+    fn icon(self) -> &'static str {
+        match self {
+            Tool::Pen => "✏️",
+            Tool::Line => "📏",
+            Tool::Rect => "▭",
+            Tool::Ellipse => "◯",
+            Tool::Eraser => "🧽",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Tool::Pen => "Pen",
+            Tool::Line => "Line",
+            Tool::Rect => "Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::Eraser => "Eraser",
+        }
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct Props {
+    pub board: Rc<Board>,
+    /// Participant id our own ops are recorded under, for undo.
+    pub author: AttrValue,
+    pub on_op: Callback<BoardOp>,
+    pub on_error: Callback<String>,
+}
+
+pub enum Msg {
+    SelectTool(Tool),
+    SelectColor(u32),
+    SelectWidth(u8),
+    PointerDown(Point),
+    PointerMove(Point),
+    PointerUp,
+    Undo,
+    Clear,
+    ExportPng,
+}
+
+/// What the pointer is drawing or erasing; nothing is sent until it lifts.
+struct Draft {
+    tool: Tool,
+    points: Vec<Point>,
+    erased: Vec<String>,
+}
+
+/// Shared canvas next to the chat. Finished strokes, shapes and erasures
+/// go out as `BoardOp`s; the board itself lives with the chat.
+pub struct Whiteboard {
+    canvas_ref: NodeRef,
+    tool: Tool,
+    color: u32,
+    width: u8,
+    draft: Option<Draft>,
+}
+
+impl Component for Whiteboard {
+    type Message = Msg;
+    type Properties = Props;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        Self {
+            canvas_ref: NodeRef::default(),
+            tool: Tool::Pen,
+            color: PALETTE[0],
+            width: WIDTHS[1],
+            draft: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            Msg::SelectTool(tool) => {
+                self.tool = tool;
+                true
+            }
+            Msg::SelectColor(color) => {
+                self.color = color;
+                if self.tool == Tool::Eraser {
+                    self.tool = Tool::Pen;
+                }
+                true
+            }
+            Msg::SelectWidth(width) => {
+                self.width = width;
+                true
+            }
+            Msg::PointerDown(point) => {
+                self.draft = Some(Draft { tool: self.tool, points: vec![point], erased: vec![] });
+                self.erase_at(ctx, point);
+                self.redraw(ctx);
+                false
+            }
+            Msg::PointerMove(point) => {
+                let Some(draft) = self.draft.as_mut() else {
+                    return false;
+                };
+                match draft.tool {
+                    Tool::Pen => {
+                        let last = draft.points[draft.points.len() - 1];
+                        let step = (point[0] as f64 - last[0] as f64).hypot(point[1] as f64 - last[1] as f64);
+                        if step < MIN_STROKE_STEP || draft.points.len() >= MAX_STROKE_POINTS {
+                            return false;
+                        }
+                        draft.points.push(point);
+                    }
+                    Tool::Line | Tool::Rect | Tool::Ellipse => {
+                        draft.points.truncate(1);
+                        draft.points.push(point);
+                    }
+                    Tool::Eraser => self.erase_at(ctx, point),
+                }
+                self.redraw(ctx);
+                false
+            }
+            Msg::PointerUp => {
+                let Some(draft) = self.draft.take() else {
+                    return false;
+                };
+                if let Some(op) = self.finish(draft) {
+                    ctx.props().on_op.emit(op);
+                }
+                self.redraw(ctx);
+                false
+            }
+            Msg::Undo => {
+                if let Some(id) = ctx.props().board.last_action_of(&ctx.props().author) {
+                    ctx.props().on_op.emit(BoardOp::Undo { id });
+                }
+                false
+            }
+            Msg::Clear => {
+                let items: Vec<String> = ctx.props().board.visible().map(|item| item.id.clone()).collect();
+                if !items.is_empty() {
+                    ctx.props().on_op.emit(BoardOp::Erase { id: new_id(), items });
+                }
+                false
+            }
+            Msg::ExportPng => {
+                if let Err(err) = self.export_png() {
+                    ctx.props().on_error.emit(err);
+                }
+                false
+            }
+        }
+    }
+
+    // The canvas keeps its pixels across renders, so it is repainted whenever
+    // the board or the tools change
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        self.redraw(ctx);
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let board_point = |canvas_ref: NodeRef| {
+            move |e: MouseEvent| {
+                let canvas = canvas_ref.cast::<HtmlCanvasElement>()?;
+                let scale = BOARD_WIDTH as f64 / canvas.client_width().max(1) as f64;
+                Some(clamp_point(e.offset_x() as f64 * scale, e.offset_y() as f64 * scale))
+            }
+        };
+        let on_down = {
+            let point = board_point(self.canvas_ref.clone());
+            ctx.link().batch_callback(move |e: MouseEvent| point(e).map(Msg::PointerDown))
+        };
+        let on_move = {
+            let point = board_point(self.canvas_ref.clone());
+            ctx.link().batch_callback(move |e: MouseEvent| point(e).map(Msg::PointerMove))
+        };
+        let has_own_action = ctx.props().board.last_action_of(&ctx.props().author).is_some();
+        let button = "px-2 py-1 rounded text-sm disabled:opacity-50";
+
+        html! {
+            <div class="flex flex-col flex-1 overflow-hidden bg-gray-50">
+                <div class="flex flex-wrap items-center gap-2 px-4 py-2 border-b border-gray-200 bg-white">
+                    {
+                        for Tool::ALL.iter().map(|&tool| html! {
+                            <button
+                                class={classes!(button, if self.tool == tool { "bg-blue-100 ring-2 ring-blue-400" } else { "hover:bg-gray-100" })}
+                                title={tool.label()}
+                                onclick={ctx.link().callback(move |_| Msg::SelectTool(tool))}
+                            >
+                                { tool.icon() }
+                            </button>
+                        })
+                    }
+                    <span class="w-px h-6 bg-gray-300"></span>
+                    {
+                        for PALETTE.iter().map(|&color| html! {
+                            <button
+                                class={classes!("w-6", "h-6", "rounded-full", if self.color == color { "ring-2 ring-offset-1 ring-gray-500" } else { "" })}
+                                style={format!("background: {}", css_color(color))}
+                                title="Color"
+                                onclick={ctx.link().callback(move |_| Msg::SelectColor(color))}
+                            />
+                        })
+                    }
+                    <span class="w-px h-6 bg-gray-300"></span>
+                    {
+                        for WIDTHS.iter().map(|&width| html! {
+                            <button
+                                class={classes!(button, if self.width == width { "bg-blue-100 ring-2 ring-blue-400" } else { "hover:bg-gray-100" })}
+                                title={format!("{} px", width)}
+                                onclick={ctx.link().callback(move |_| Msg::SelectWidth(width))}
+                            >
+                                <span class="block bg-gray-800 rounded-full" style={format!("width: {0}px; height: {0}px", width + 2)}></span>
+                            </button>
+                        })
+                    }
+                    <span class="flex-1"></span>
+                    <button
+                        class={classes!(button, "hover:bg-gray-100")}
+                        title="Undo your last change"
+                        disabled={!has_own_action}
+                        onclick={ctx.link().callback(|_| Msg::Undo)}
+                    >
+                        {"↶ Undo"}
+                    </button>
+                    <button
+                        class={classes!(button, "hover:bg-gray-100")}
+                        title="Erase everything; undo brings it back"
+                        onclick={ctx.link().callback(|_| Msg::Clear)}
+                    >
+                        {"🗑️ Clear"}
+                    </button>
+                    <button
+                        class={classes!(button, "hover:bg-gray-100")}
+                        title="Download as PNG"
+                        onclick={ctx.link().callback(|_| Msg::ExportPng)}
+                    >
+                        {"⬇ PNG"}
+                    </button>
+                </div>
+                <div class="flex-1 overflow-auto p-4">
+                    <canvas
+                        ref={self.canvas_ref.clone()}
+                        width={BOARD_WIDTH.to_string()}
+                        height={BOARD_HEIGHT.to_string()}
+                        class={classes!("w-full", "max-w-5xl", "mx-auto", "block", "bg-white", "rounded-lg", "shadow",
+                            if self.tool == Tool::Eraser { "cursor-cell" } else { "cursor-crosshair" })}
+                        onmousedown={on_down}
+                        onmousemove={on_move}
+                        onmouseup={ctx.link().callback(|_| Msg::PointerUp)}
+                        onmouseleave={ctx.link().callback(|_| Msg::PointerUp)}
+                    />
+                </div>
+            </div>
+        }
+    }
+}
+
+impl Whiteboard {
+    fn erase_at(&mut self, ctx: &Context<Self>, point: Point) {
+        let Some(draft) = self.draft.as_mut().filter(|draft| draft.tool == Tool::Eraser) else {
+            return;
+        };
+        for id in ctx.props().board.hits(point, ERASER_RADIUS) {
+            if !draft.erased.contains(&id) {
+                draft.erased.push(id);
+            }
+        }
+    }
+
+    fn finish(&self, draft: Draft) -> Option<BoardOp> {
+        let from = draft.points[0];
+        let to = *draft.points.last()?;
+        let shape = match draft.tool {
+            Tool::Pen => Shape::Stroke { points: draft.points },
+            Tool::Line if from != to => Shape::Line { from, to },
+            Tool::Rect if from != to => Shape::Rect { from, to },
+            Tool::Ellipse if from != to => Shape::Ellipse { from, to },
+            Tool::Eraser if !draft.erased.is_empty() => {
+                return Some(BoardOp::Erase { id: new_id(), items: draft.erased });
+            }
+            _ => return None,
+        };
+        Some(BoardOp::Add { item: Item { id: new_id(), shape, color: self.color, width: self.width } })
+    }
+
+    // The draft is drawn over the board, and what the eraser touched is left out
+    fn redraw(&self, ctx: &Context<Self>) {
+        let Some(context) = self.context() else {
+            return;
+        };
+        context.set_fill_style_str("#ffffff");
+        context.fill_rect(0.0, 0.0, BOARD_WIDTH as f64, BOARD_HEIGHT as f64);
+
+        let erased = self.draft.as_ref().map(|draft| draft.erased.as_slice()).unwrap_or_default();
+        for item in ctx.props().board.visible().filter(|item| !erased.contains(&item.id)) {
+            draw_item(&context, item);
+        }
+
+        if let Some(draft) = &self.draft
+            && let Some(op) = self.finish(Draft { tool: draft.tool, points: draft.points.clone(), erased: vec![] })
+            && let BoardOp::Add { item } = op
+        {
+            draw_item(&context, &item);
+        }
+    }
+
+    fn context(&self) -> Option<CanvasRenderingContext2d> {
+        self.canvas_ref
+            .cast::<HtmlCanvasElement>()?
+            .get_context("2d")
+            .ok()??
+            .dyn_into::<CanvasRenderingContext2d>()
+            .ok()
+    }
+
+    fn export_png(&self) -> Result<(), String> {
+        let canvas = self
+            .canvas_ref
+            .cast::<HtmlCanvasElement>()
+            .ok_or("The whiteboard is not shown")?;
+        let data_url = canvas.to_data_url().map_err(|_| "Failed to export the whiteboard")?;
+        let encoded = data_url.split_once(',').map(|(_, data)| data).unwrap_or_default();
+        let data = general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|err| format!("Failed to export the whiteboard: {}", err))?;
+
+        FileHandler::save_as(&FileInfo {
+            name: "whiteboard.png".to_string(),
+            size: data.len() as u64,
+            file_type: "image/png".to_string(),
+            data,
+        })
+    }
+}
+
+fn draw_item(context: &CanvasRenderingContext2d, item: &Item) {
+    context.set_stroke_style_str(&css_color(item.color));
+    context.set_line_width(item.width as f64);
+    context.set_line_cap("round");
+    context.set_line_join("round");
+    context.begin_path();
+
+    match &item.shape {
+        Shape::Stroke { points } => {
+            let Some([x, y]) = points.first() else {
+                return;
+            };
+            context.move_to(*x as f64, *y as f64);
+            // A lone point still needs a segment for the round cap to show
+            let rest = if points.len() == 1 { &points[..] } else { &points[1..] };
+            for [x, y] in rest {
+                context.line_to(*x as f64, *y as f64);
+            }
+        }
+        Shape::Line { from, to } => {
+            context.move_to(from[0] as f64, from[1] as f64);
+            context.line_to(to[0] as f64, to[1] as f64);
+        }
+        Shape::Rect { from, to } => {
+            let (x, y) = (from[0].min(to[0]) as f64, from[1].min(to[1]) as f64);
+            context.rect(x, y, from[0].abs_diff(to[0]) as f64, from[1].abs_diff(to[1]) as f64);
+        }
+        Shape::Ellipse { from, to } => {
+            let (cx, cy, rx, ry) = ellipse_box(*from, *to);
+            let _ = context.ellipse(cx, cy, rx, ry, 0.0, 0.0, std::f64::consts::TAU);
+        }
+    }
+
+    context.stroke();
+}
+
+fn css_color(color: u32) -> String {
+    format!("#{:06x}", color & 0xff_ffff)
+}
+
+fn new_id() -> String {
+    format!("{:08x}{:08x}", js_sys::Date::now() as u32, rand::random::<u32>())
+}
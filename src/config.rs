@@ -24,6 +24,34 @@ pub struct GitHubAuth {
     pub user_api_url: String,
 }
 
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub credential: Option<String>,
+}
+
+impl IceServer {
+    pub fn is_turn(&self) -> bool {
+        self.urls
+            .iter()
+            .any(|url| url.starts_with("turn:") || url.starts_with("turns:"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebRtcDefaults {
+    pub ice_servers: Vec<IceServer>,
+    #[serde(default)]
+    pub relay_only: bool,
+    /// WebSocket URL of a signaling server, offered as an alternative to
+    /// copying connection codes.
+    #[serde(default)]
+    pub signaling_url: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub pomodoro_defaults: PomodoroDefaults,
@@ -31,4 +59,5 @@ pub struct Config {
     pub tutorial_data_url: String,
     pub alarm_sound_path: String,
     pub github_auth: GitHubAuth,
+    pub webrtc_defaults: WebRtcDefaults,
 }
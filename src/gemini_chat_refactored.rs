@@ -1,14 +1,17 @@
 // Refactored Gemini Chat with MCP function call patterns and SSE support for WASM
 use yew::prelude::*;
 use yew::AttrValue;
-use web_sys::{HtmlInputElement, KeyboardEvent, EventSource, MessageEvent};
+use web_sys::{AbortController, AbortSignal, HtmlInputElement, KeyboardEvent, ReadableStreamDefaultReader};
 use serde::{Deserialize, Serialize};
 use gloo_net::http::Request;
 use gloo_console::log;
 use pulldown_cmark::{Parser, Options, html};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use wasm_bindgen::prelude::*;
+use std::rc::Rc;
+use js_sys::{Reflect, Uint8Array};
 use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
 
 // Core message and conversation types
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -116,9 +119,10 @@ pub struct GenerationConfig {
     pub response_modalities: Vec<String>,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Content {
     pub role: String,
+    #[serde(default)]
     pub parts: Vec<Part>,
 }
 
@@ -153,43 +157,81 @@ pub struct InlineData {
     pub data: String,
 }
 
+// Streamed chunks may carry only usage metadata, or a candidate without content
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct GeminiResponse {
+    #[serde(default)]
     pub candidates: Vec<Candidate>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Candidate {
+    #[serde(default)]
     pub content: Content,
 }
 
-// SSE Client for WASM-compatible streaming
+// SSE Client for WASM-compatible streaming. `EventSource` can only GET, so
+// the events are read from a fetch response body; aborting the fetch stops
+// the stream and whatever tool calls the same signal was given to.
 pub struct SseClient {
-    event_source: Option<EventSource>,
+    controller: Option<AbortController>,
 }
 
 impl SseClient {
     pub fn new() -> Self {
         Self {
-            event_source: None,
+            controller: None,
         }
     }
 
-    pub fn connect(&mut self, url: &str, on_message: Closure<dyn FnMut(MessageEvent)>) -> Result<(), String> {
-        let event_source = EventSource::new(url)
-            .map_err(|e| format!("Failed to create EventSource: {:?}", e))?;
-        
-        event_source.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
-        
-        self.event_source = Some(event_source);
-        Ok(())
+    // Aborts any earlier stream and returns the signal for the next one
+    pub fn start(&mut self) -> Result<AbortSignal, String> {
+        self.close();
+        let controller = AbortController::new()
+            .map_err(|e| format!("Failed to create AbortController: {:?}", e))?;
+        let signal = controller.signal();
+        self.controller = Some(controller);
+        Ok(signal)
     }
 
     pub fn close(&mut self) {
-        if let Some(event_source) = &self.event_source {
-            event_source.close();
+        if let Some(controller) = self.controller.take() {
+            controller.abort();
         }
-        self.event_source = None;
+    }
+}
+
+// Splits a `text/event-stream` body into the data of each event. Bytes may
+// be cut anywhere, even inside a character, so only whole lines are decoded.
+#[derive(Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                // A blank line ends the event
+                if !self.data.is_empty() {
+                    events.push(self.data.join("\n"));
+                    self.data.clear();
+                }
+            } else if let Some(value) = line.strip_prefix("data:") {
+                self.data.push(value.strip_prefix(' ').unwrap_or(value).to_string());
+            }
+            // Comments, `event:`, `id:` and `retry:` lines are not used by Gemini
+        }
+
+        events
     }
 }
 
@@ -203,6 +245,14 @@ pub fn app() -> Html {
     let show_settings = use_state(|| false);
     let tool_registry = use_state(|| ToolRegistry::new());
     let pending_tool_calls = use_state(|| Vec::<ToolCall>::new());
+    let streaming_text = use_state(String::new);
+    let sse_client = use_mut_ref(SseClient::new);
+
+    // Nothing keeps streaming into a chat that is gone
+    {
+        let sse_client = sse_client.clone();
+        use_effect_with((), move |_| move || sse_client.borrow_mut().close());
+    }
 
     // Enhanced send message with function calling support
     let send_message = {
@@ -212,6 +262,8 @@ pub fn app() -> Html {
         let api_key = api_key.clone();
         let tool_registry = tool_registry.clone();
         let pending_tool_calls = pending_tool_calls.clone();
+        let streaming_text = streaming_text.clone();
+        let sse_client = sse_client.clone();
         
         Callback::from(move |_| {
             let messages = messages.clone();
@@ -220,10 +272,19 @@ pub fn app() -> Html {
             let api_key = api_key.clone();
             let tool_registry = tool_registry.clone();
             let pending_tool_calls = pending_tool_calls.clone();
+            let streaming_text = streaming_text.clone();
             
-            if input_value.is_empty() || api_key.is_empty() {
+            if input_value.is_empty() || api_key.is_empty() || *is_loading {
                 return;
             }
+
+            let signal = match sse_client.borrow_mut().start() {
+                Ok(signal) => signal,
+                Err(err) => {
+                    log!("[SSE]", err);
+                    return;
+                }
+            };
             
             let user_message = Message {
                 id: format!("user_{}", js_sys::Date::now()),
@@ -244,9 +305,20 @@ pub fn app() -> Html {
             
             wasm_bindgen_futures::spawn_local(async move {
                 log!("[MCP] Starting conversation with function calling support");
+
+                // Kept here as well, since the state handle cannot be read back
+                let partial_text = Rc::new(RefCell::new(String::new()));
+                let on_text = {
+                    let streaming_text = streaming_text.clone();
+                    let partial_text = partial_text.clone();
+                    move |text: &str| {
+                        *partial_text.borrow_mut() = text.to_string();
+                        streaming_text.set(text.to_string());
+                    }
+                };
                 
                 // Process conversation with tool support
-                match process_conversation_with_tools(&new_messages, &api_key, &tool_registry).await {
+                match process_conversation_with_tools(&new_messages, &api_key, &tool_registry, &signal, &on_text).await {
                     Ok((response, tool_calls, tool_results, image_data)) => {
                         log!("[MCP] Creating AI message with image_data: {}", image_data.is_some());
                         if let Some(ref img_data) = image_data {
@@ -269,6 +341,27 @@ pub fn app() -> Html {
                             updated_messages
                         });
                     }
+                    Err(_) if signal.aborted() => {
+                        log!("[MCP] Stopped by the user");
+                        let partial = partial_text.borrow().clone();
+                        if !partial.is_empty() {
+                            let stopped_message = Message {
+                                id: format!("ai_{}", js_sys::Date::now()),
+                                content: format!("{}\n\n*Stopped*", partial),
+                                is_user: false,
+                                timestamp: format_timestamp(),
+                                image_data: None,
+                                tool_calls: None,
+                                tool_results: None,
+                            };
+
+                            messages.set({
+                                let mut updated_messages = new_messages;
+                                updated_messages.push(stopped_message);
+                                updated_messages
+                            });
+                        }
+                    }
                     Err(err) => {
                         let error_message = Message {
                             id: format!("error_{}", js_sys::Date::now()),
@@ -288,11 +381,20 @@ pub fn app() -> Html {
                     }
                 }
                 
+                streaming_text.set(String::new());
                 is_loading.set(false);
             });
         })
     };
 
+    let stop_streaming = {
+        let sse_client = sse_client.clone();
+        Callback::from(move |_| {
+            log!("[SSE] Aborting the response stream");
+            sse_client.borrow_mut().close();
+        })
+    };
+
     // Standard event handlers
     let on_input_change = {
         let input_value = input_value.clone();
@@ -494,7 +596,16 @@ pub fn app() -> Html {
                         }
                     }).collect::<Html>()}
                     
-                    if *is_loading {
+                    if *is_loading && !streaming_text.is_empty() {
+                        <div class="flex justify-start">
+                            <div class="bg-white text-gray-900 border border-gray-200 max-w-xs lg:max-w-md px-4 py-2 rounded-2xl shadow-sm">
+                                <div class="text-sm prose prose-sm max-w-none">
+                                    {Html::from_html_unchecked(AttrValue::from(markdown_to_html(&streaming_text)))}
+                                </div>
+                                <span class="inline-block w-2 h-4 bg-gray-400 animate-pulse"></span>
+                            </div>
+                        </div>
+                    } else if *is_loading {
                         <div class="flex justify-start">
                             <div class="bg-white text-gray-900 border border-gray-200 max-w-xs lg:max-w-md px-4 py-2 rounded-2xl shadow-sm">
                                 <div class="flex items-center space-x-2">
@@ -530,13 +641,22 @@ pub fn app() -> Html {
                                 rows="1"
                             />
                         </div>
-                        <button
-                            onclick={ move |_| send_message.emit(()) }
-                            disabled={input_value.is_empty() || api_key.is_empty() || *is_loading}
-                            class="px-6 py-3 bg-gradient-to-r from-blue-500 to-purple-600 text-white rounded-xl hover:from-blue-600 hover:to-purple-700 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:ring-offset-2 disabled:opacity-50 disabled:cursor-not-allowed transition-all duration-200 font-medium shadow-lg hover:shadow-xl"
-                        >
-                            {"Send"}
-                        </button>
+                        if *is_loading {
+                            <button
+                                onclick={stop_streaming}
+                                class="px-6 py-3 bg-red-500 text-white rounded-xl hover:bg-red-600 focus:outline-none focus:ring-2 focus:ring-red-500 focus:ring-offset-2 transition-all duration-200 font-medium shadow-lg hover:shadow-xl"
+                            >
+                                {"■ Stop"}
+                            </button>
+                        } else {
+                            <button
+                                onclick={ move |_| send_message.emit(()) }
+                                disabled={input_value.is_empty() || api_key.is_empty()}
+                                class="px-6 py-3 bg-gradient-to-r from-blue-500 to-purple-600 text-white rounded-xl hover:from-blue-600 hover:to-purple-700 focus:outline-none focus:ring-2 focus:ring-blue-500 focus:ring-offset-2 disabled:opacity-50 disabled:cursor-not-allowed transition-all duration-200 font-medium shadow-lg hover:shadow-xl"
+                            >
+                                {"Send"}
+                            </button>
+                        }
                     </div>
                     <p class="text-xs text-gray-500 mt-2">{"Press Enter to send, Shift+Enter for new line • Function calling enabled"}</p>
                 </div>
@@ -550,6 +670,8 @@ async fn process_conversation_with_tools(
     messages: &[Message],
     api_key: &str,
     tool_registry: &ToolRegistry,
+    signal: &AbortSignal,
+    on_text: &dyn Fn(&str),
 ) -> Result<(String, Option<Vec<ToolCall>>, Option<Vec<ToolResult>>, Option<String>), String> {
     log!("[MCP] Processing conversation with {} messages", messages.len());
     
@@ -575,7 +697,7 @@ async fn process_conversation_with_tools(
         log!("[MCP] Iteration {} - Making API call", iteration);
         
         // Call Gemini API
-        let response = call_gemini_api_with_tools(&contents, api_key, &gemini_tools).await?;
+        let response = call_gemini_api_with_tools(&contents, api_key, &gemini_tools, signal, on_text).await?;
         
         // Check if response contains function calls
         if let Some(candidate) = response.candidates.first() {
@@ -617,7 +739,7 @@ async fn process_conversation_with_tools(
                     log!("[MCP] Executing tool: {}", function_name.clone());
                     
                     // Execute the tool
-                    let (tool_result, tool_image_data) = execute_tool_with_image(&tool_call, tool_registry, api_key).await;
+                    let (tool_result, tool_image_data) = execute_tool_with_image(&tool_call, tool_registry, api_key, signal).await;
                     
                     // If tool generated an image, store it for the final response
                     if let Some(image_data) = tool_image_data {
@@ -678,13 +800,13 @@ async fn process_conversation_with_tools(
 }
 
 // Execute a tool call and return both result and any generated image data
-async fn execute_tool_with_image(tool_call: &ToolCall, tool_registry: &ToolRegistry, api_key: &str) -> (ToolResult, Option<String>) {
+async fn execute_tool_with_image(tool_call: &ToolCall, tool_registry: &ToolRegistry, api_key: &str, signal: &AbortSignal) -> (ToolResult, Option<String>) {
     log!("[TOOL] Executing: {} with args: {}", tool_call.name.clone(), tool_call.arguments.to_string());
     
     match tool_call.name.as_str() {
         "generate_image" => {
             if let Some(prompt) = tool_call.arguments.get("prompt").and_then(|v| v.as_str()) {
-                match call_gemini_image_api(prompt, api_key).await {
+                match call_gemini_image_api(prompt, api_key, signal).await {
                     Ok(Some(image_data)) => {
                         log!("[TOOL] Image generation successful, returning {} bytes", image_data.len());
                         let tool_result = ToolResult {
@@ -732,11 +854,11 @@ async fn execute_tool_with_image(tool_call: &ToolCall, tool_registry: &ToolRegis
 }
 
 // Call Gemini API for image generation (referenced from original gemini_chat.rs)
-async fn call_gemini_image_api(prompt: &str, api_key: &str) -> Result<Option<String>, String> {
+async fn call_gemini_image_api(prompt: &str, api_key: &str, signal: &AbortSignal) -> Result<Option<String>, String> {
     log!("[IMAGE_API] Starting image generation for prompt: {}", prompt);
     
     let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash-preview-image-generation:generateContent?key={}",
+        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.0-flash-preview-image-generation:streamGenerateContent?alt=sse&key={}",
         api_key
     );
     
@@ -765,26 +887,10 @@ async fn call_gemini_image_api(prompt: &str, api_key: &str) -> Result<Option<Str
     
     log!("[IMAGE_API] Sending request to Gemini image generation API...");
     
-    let response = Request::post(&url)
-        .header("Content-Type", "application/json")
-        .json(&request_body)
-        .map_err(|e| format!("Failed to create image generation request: {}", e))?
-        .send()
+    // The image arrives whole in one event; its caption is not shown
+    let gemini_response = stream_gemini(&url, &request_body, signal, &|_| {})
         .await
-        .map_err(|e| format!("Image generation request failed: {}", e))?;
-    
-    log!("[IMAGE_API] Response received - Status: {}", response.status());
-    
-    if !response.ok() {
-        let status = response.status();
-        log!("[IMAGE_API] Image generation failed with status: {}", status);
-        return Err(format!("Image generation failed with status: {}", status));
-    }
-    
-    let gemini_response: GeminiResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse image generation response: {}", e))?;
+        .map_err(|e| format!("Image generation failed: {}", e))?;
     
     log!("[IMAGE_API] Response parsed successfully - Candidates count: {}", gemini_response.candidates.len());
     
@@ -852,9 +958,11 @@ async fn call_gemini_api_with_tools(
     contents: &[Content],
     api_key: &str,
     tools: &[GeminiTool],
+    signal: &AbortSignal,
+    on_text: &dyn Fn(&str),
 ) -> Result<GeminiResponse, String> {
     let url = format!(
-        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-lite-preview-06-17:generateContent?key={}",
+        "https://generativelanguage.googleapis.com/v1beta/models/gemini-2.5-flash-lite-preview-06-17:streamGenerateContent?alt=sse&key={}",
         api_key
     );
     
@@ -866,9 +974,22 @@ async fn call_gemini_api_with_tools(
     
     log!("[API] Calling Gemini with {} tools", tools.len());
     
-    let response = Request::post(&url)
+    stream_gemini(&url, &request_body, signal, on_text).await
+}
+
+// Posts to a `streamGenerateContent?alt=sse` endpoint and reads the events
+// as they arrive, merging their parts into one response. `on_text` is given
+// the reply's text so far after every event.
+async fn stream_gemini(
+    url: &str,
+    request_body: &GeminiRequest,
+    signal: &AbortSignal,
+    on_text: &dyn Fn(&str),
+) -> Result<GeminiResponse, String> {
+    let response = Request::post(url)
         .header("Content-Type", "application/json")
-        .json(&request_body)
+        .abort_signal(Some(signal))
+        .json(request_body)
         .map_err(|e| format!("Failed to create request: {}", e))?
         .send()
         .await
@@ -877,11 +998,66 @@ async fn call_gemini_api_with_tools(
     if !response.ok() {
         return Err(format!("API request failed with status: {}", response.status()));
     }
-    
-    response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse response: {}", e))
+
+    let reader: ReadableStreamDefaultReader = response
+        .body()
+        .ok_or("Response has no body")?
+        .get_reader()
+        .unchecked_into();
+    let mut parser = SseParser::default();
+    let mut candidates: Vec<Candidate> = Vec::new();
+
+    loop {
+        let chunk = JsFuture::from(reader.read())
+            .await
+            .map_err(|e| format!("Response stream failed: {:?}", e))?;
+        let done = Reflect::get(&chunk, &"done".into())
+            .ok()
+            .and_then(|done| done.as_bool())
+            .unwrap_or(true);
+        if done {
+            break;
+        }
+        let value = Reflect::get(&chunk, &"value".into())
+            .map_err(|e| format!("Response stream failed: {:?}", e))?;
+
+        for data in parser.push(&Uint8Array::new(&value).to_vec()) {
+            let event: GeminiResponse = serde_json::from_str(&data)
+                .map_err(|e| format!("Failed to parse response: {}", e))?;
+            let Some(candidate) = event.candidates.into_iter().next() else {
+                continue;
+            };
+            match candidates.first_mut() {
+                Some(merged) => merge_parts(&mut merged.content.parts, candidate.content.parts),
+                None => candidates.push(candidate),
+            }
+            on_text(&response_text(&candidates[0].content.parts));
+        }
+    }
+
+    Ok(GeminiResponse { candidates })
+}
+
+// Text streams in as many small parts; they are joined so a response reads
+// like the non-streaming one. Function calls and images stay separate parts.
+fn merge_parts(parts: &mut Vec<Part>, new_parts: Vec<Part>) {
+    for part in new_parts {
+        let is_text = |part: &Part| {
+            part.text.is_some() && part.inline_data.is_none() && part.function_call.is_none() && part.function_response.is_none()
+        };
+        match parts.last_mut() {
+            Some(last) if is_text(last) && is_text(&part) => {
+                if let (Some(text), Some(more)) = (last.text.as_mut(), part.text) {
+                    text.push_str(&more);
+                }
+            }
+            _ => parts.push(part),
+        }
+    }
+}
+
+fn response_text(parts: &[Part]) -> String {
+    parts.iter().filter_map(|part| part.text.as_deref()).collect()
 }
 
 // Utility functions
@@ -904,4 +1080,46 @@ fn markdown_to_html(markdown: &str) -> String {
     let mut html_output = String::new();
     html::push_html(&mut html_output, parser);
     html_output
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_part(text: &str) -> Part {
+        Part { text: Some(text.to_string()), inline_data: None, function_call: None, function_response: None }
+    }
+
+    #[test]
+    fn sse_events_survive_arbitrary_chunking() {
+        let body = "data: {\"a\": 1}\r\n\r\n: keep-alive\n\ndata: one\ndata: two\n\ndata: é\n\n";
+        let bytes = body.as_bytes();
+
+        // Cut at every byte, including inside the two-byte é
+        let mut parser = SseParser::default();
+        let events: Vec<String> = bytes.chunks(1).flat_map(|chunk| parser.push(chunk)).collect();
+
+        assert_eq!(events, ["{\"a\": 1}", "one\ntwo", "é"]);
+        assert!(parser.push(b"data: unfinished").is_empty());
+    }
+
+    #[test]
+    fn streamed_text_parts_are_joined() {
+        let call = Part {
+            text: None,
+            inline_data: None,
+            function_call: Some(serde_json::json!({"name": "generate_image"})),
+            function_response: None,
+        };
+
+        let mut parts = vec![text_part("Hel")];
+        merge_parts(&mut parts, vec![text_part("lo")]);
+        merge_parts(&mut parts, vec![call.clone(), text_part("!")]);
+
+        assert_eq!(parts, [text_part("Hello"), call, text_part("!")]);
+        assert_eq!(response_text(&parts), "Hello!");
+
+        let usage_only: GeminiResponse = serde_json::from_str("{\"usageMetadata\": {}}").unwrap();
+        assert!(usage_only.candidates.is_empty());
+    }
+}
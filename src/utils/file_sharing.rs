@@ -1,6 +1,6 @@
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{File, FileReader, Blob, Url};
+use web_sys::{File, FileReader, Blob, BlobPropertyBag, HtmlAnchorElement, Url};
 use wasm_bindgen_futures::JsFuture;
 use yew::Callback;
 
@@ -29,12 +29,6 @@ impl FileInfo {
     pub fn is_image(&self) -> bool {
         self.file_type.starts_with("image/")
     }
-    
-    pub fn get_data_url(&self) -> String {
-        use base64::{Engine as _, engine::general_purpose};
-        let base64_data = general_purpose::STANDARD.encode(&self.data);
-        format!("data:{};base64,{}", self.file_type, base64_data)
-    }
 }
 
 pub struct FileHandler;
@@ -89,17 +83,48 @@ impl FileHandler {
         let array = js_sys::Array::new();
         array.push(&uint8_array);
         
-        let blob = Blob::new_with_u8_array_sequence(&array)
+        // Typed, so audio and video elements can play the URL directly
+        let options = BlobPropertyBag::new();
+        options.set_type(&file_info.file_type);
+        let blob = Blob::new_with_u8_array_sequence_and_options(&array, &options)
             .map_err(|_| "Failed to create blob".to_string())?;
         
         Url::create_object_url_with_blob(&blob)
             .map_err(|_| "Failed to create object URL".to_string())
     }
     
+    /// Hand a file to the browser's downloads, as if the user clicked a link to it
+    pub fn save_as(file_info: &FileInfo) -> Result<(), String> {
+        let url = Self::create_download_url(file_info)?;
+        let document = web_sys::window()
+            .and_then(|window| window.document())
+            .ok_or("No document to download from")?;
+        let link: HtmlAnchorElement = document
+            .create_element("a")
+            .map_err(|_| "Failed to create download link")?
+            .dyn_into()
+            .map_err(|_| "Failed to create download link")?;
+
+        link.set_href(&url);
+        link.set_download(&file_info.name);
+        link.click();
+
+        // The download has to start before the URL goes away
+        gloo_timers::callback::Timeout::new(1_000, move || {
+            let _ = Url::revoke_object_url(&url);
+        })
+        .forget();
+        Ok(())
+    }
+    
     /// Validate file size and type
     pub fn validate_file(file: &File, max_size_mb: u64) -> Result<(), String> {
         let max_size_bytes = max_size_mb * 1024 * 1024;
         
+        if file.size() == 0.0 {
+            return Err(format!("File \"{}\" is empty", file.name()));
+        }
+        
         if file.size() as u64 > max_size_bytes {
             return Err(format!(
                 "File too large. Maximum size is {} MB, but file is {:.1} MB",
@@ -5,6 +5,8 @@ use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 
 pub struct QrCodeGenerator;
 
+pub struct QrCodeDecoder;
+
 impl QrCodeGenerator {
     /// Generate QR code as a data URL that can be displayed in an img tag
     pub fn generate_qr_code_data_url(data: &str) -> Result<String, String> {
@@ -118,4 +120,91 @@ impl QrCodeGenerator {
         
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// Generated codes are drawn without a quiet zone and camera frames may crop
+// tightly, so every image is framed with white before grid detection.
+const QUIET_ZONE: usize = 16;
+
+impl QrCodeDecoder {
+    /// Decode the first QR code found in a greyscale image (one byte per pixel)
+    pub fn decode_luma(width: usize, height: usize, luma: &[u8]) -> Result<String, String> {
+        if luma.len() < width * height {
+            return Err("Image data is shorter than its dimensions".to_string());
+        }
+
+        let mut prepared = rqrr::PreparedImage::prepare_from_greyscale(
+            width + 2 * QUIET_ZONE,
+            height + 2 * QUIET_ZONE,
+            |x, y| {
+                let inside = (QUIET_ZONE..QUIET_ZONE + width).contains(&x)
+                    && (QUIET_ZONE..QUIET_ZONE + height).contains(&y);
+                if inside {
+                    luma[(y - QUIET_ZONE) * width + (x - QUIET_ZONE)]
+                } else {
+                    255
+                }
+            },
+        );
+
+        let grids = prepared.detect_grids();
+        let grid = grids.first().ok_or("No QR code found in image")?;
+
+        grid.decode()
+            .map(|(_meta, content)| content)
+            .map_err(|e| format!("Failed to decode QR code: {:?}", e))
+    }
+
+    /// Decode from RGBA pixels, as returned by a canvas `getImageData`
+    pub fn decode_rgba(width: usize, height: usize, rgba: &[u8]) -> Result<String, String> {
+        let luma: Vec<u8> = rgba
+            .chunks_exact(4)
+            .map(|px| ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000) as u8)
+            .collect();
+
+        Self::decode_luma(width, height, &luma)
+    }
+
+    /// Decode a PNG, e.g. one produced by `generate_qr_code_data_url`
+    pub fn decode_png(png_data: &[u8]) -> Result<String, String> {
+        let image = image::load_from_memory_with_format(png_data, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to read PNG: {:?}", e))?
+            .to_luma8();
+
+        Self::decode_luma(image.width() as usize, image.height() as usize, image.as_raw())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine as _, engine::general_purpose};
+
+    fn decode_data_url(data_url: &str) -> Result<String, String> {
+        let base64_data = data_url.strip_prefix("data:image/png;base64,").unwrap();
+        QrCodeDecoder::decode_png(&general_purpose::STANDARD.decode(base64_data).unwrap())
+    }
+
+    #[test]
+    fn decodes_generated_qr_codes() {
+        let code = "c1.eNqrVkrOz0nVUbJSSkksSVWyUlBKTc7PTcxTqAUAqpAKaA";
+        let data_url = QrCodeGenerator::generate_qr_code_data_url(code).unwrap();
+
+        assert_eq!(decode_data_url(&data_url).unwrap(), code);
+    }
+
+    #[test]
+    fn decodes_long_connection_codes() {
+        let code = "c1.".to_string() + &"AbCdEfGh0123456789-_".repeat(30);
+        let data_url = QrCodeGenerator::generate_qr_code_data_url(&code).unwrap();
+
+        assert_eq!(decode_data_url(&data_url).unwrap(), code);
+    }
+
+    #[test]
+    fn reports_images_without_a_code() {
+        let blank = vec![255u8; 64 * 64];
+
+        assert!(QrCodeDecoder::decode_luma(64, 64, &blank).is_err());
+    }
+}
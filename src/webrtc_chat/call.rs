@@ -0,0 +1,166 @@
+use gloo::events::EventListener;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{MediaStream, MediaStreamConstraints, MediaStreamTrack};
+
+use crate::webrtc_chat::room::PeerId;
+use crate::webrtc_chat::voice;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallPhase {
+    /// The peer invited us and we have not answered yet.
+    Incoming,
+    /// We invited the peer and wait for them to accept.
+    Outgoing,
+    Active,
+}
+
+/// An audio or video call with one peer, carried on the peer connection the
+/// chat already uses. Calls are one-to-one: a room has no media relay.
+pub struct Call {
+    pub peer_id: PeerId,
+    pub phase: CallPhase,
+    pub video: bool,
+    pub local_stream: Option<MediaStream>,
+    pub screen_track: Option<MediaStreamTrack>,
+    pub muted: bool,
+    pub camera_off: bool,
+    pub remote_muted: bool,
+    pub remote_camera_off: bool,
+    /// Whether our tracks are attached to the peer connection.
+    pub sending: bool,
+    _screen_ended: Option<EventListener>,
+}
+
+impl Call {
+    pub fn new(peer_id: PeerId, phase: CallPhase, video: bool) -> Call {
+        Call {
+            peer_id,
+            phase,
+            video,
+            local_stream: None,
+            screen_track: None,
+            muted: false,
+            camera_off: false,
+            remote_muted: false,
+            remote_camera_off: false,
+            sending: false,
+            _screen_ended: None,
+        }
+    }
+
+    /// Whether the peer knows about the call: they invited us, or our
+    /// invitation went out once the microphone was ready.
+    pub fn is_announced(&self) -> bool {
+        self.phase != CallPhase::Outgoing || self.local_stream.is_some()
+    }
+
+    /// What we send: our microphone plus either the shared screen or the
+    /// camera.
+    pub fn outgoing_stream(&self) -> Result<MediaStream, JsValue> {
+        let outgoing = MediaStream::new()?;
+        let Some(local_stream) = &self.local_stream else {
+            return Ok(outgoing);
+        };
+
+        for track in local_stream.get_audio_tracks().iter() {
+            outgoing.add_track(track.unchecked_ref());
+        }
+        match &self.screen_track {
+            Some(screen_track) => outgoing.add_track(screen_track),
+            None => {
+                for track in local_stream.get_video_tracks().iter() {
+                    outgoing.add_track(track.unchecked_ref());
+                }
+            }
+        }
+        Ok(outgoing)
+    }
+
+    /// Sharing the screen swaps the camera track for the screen; `on_ended`
+    /// fires when the user stops sharing from the browser's own controls.
+    pub fn share_screen(&mut self, track: MediaStreamTrack, on_ended: impl FnMut(&web_sys::Event) + 'static) {
+        self.stop_screen_share();
+        self._screen_ended = Some(EventListener::new(&track, "ended", on_ended));
+        self.screen_track = Some(track);
+    }
+
+    pub fn stop_screen_share(&mut self) {
+        self._screen_ended = None;
+        if let Some(track) = self.screen_track.take() {
+            track.stop();
+        }
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+        self.set_enabled(|stream| stream.get_audio_tracks(), !muted);
+    }
+
+    pub fn set_camera_off(&mut self, camera_off: bool) {
+        self.camera_off = camera_off;
+        self.set_enabled(|stream| stream.get_video_tracks(), !camera_off);
+    }
+
+    /// Releases the microphone, camera and screen.
+    pub fn stop(&mut self) {
+        self.stop_screen_share();
+        if let Some(stream) = self.local_stream.take() {
+            voice::stop_tracks(&stream);
+        }
+    }
+
+    // Disabled tracks keep flowing as silence or black frames, so muting
+    // needs no renegotiation.
+    fn set_enabled(&self, tracks: impl Fn(&MediaStream) -> js_sys::Array, enabled: bool) {
+        if let Some(stream) = &self.local_stream {
+            for track in tracks(stream).iter() {
+                track.unchecked_into::<MediaStreamTrack>().set_enabled(enabled);
+            }
+        }
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+pub async fn open_media(video: bool) -> Result<MediaStream, String> {
+    let media_devices = web_sys::window()
+        .ok_or("no window")?
+        .navigator()
+        .media_devices()
+        .map_err(|_| "calls are not supported in this browser")?;
+
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_audio(&JsValue::TRUE);
+    constraints.set_video(&JsValue::from_bool(video));
+
+    let promise = media_devices
+        .get_user_media_with_constraints(&constraints)
+        .map_err(|_| "calls are not supported in this browser")?;
+
+    JsFuture::from(promise)
+        .await
+        .map(|stream| stream.unchecked_into::<MediaStream>())
+        .map_err(|_| "permission denied or no microphone/camera found".to_string())
+}
+
+pub async fn open_screen() -> Result<MediaStream, String> {
+    let media_devices = web_sys::window()
+        .ok_or("no window")?
+        .navigator()
+        .media_devices()
+        .map_err(|_| "screen sharing is not supported in this browser")?;
+
+    let promise = media_devices
+        .get_display_media()
+        .map_err(|_| "screen sharing is not supported in this browser")?;
+
+    JsFuture::from(promise)
+        .await
+        .map(|stream| stream.unchecked_into::<MediaStream>())
+        .map_err(|_| "screen sharing was cancelled".to_string())
+}
@@ -1,21 +1,54 @@
 use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::rc::Rc;
 use std::str;
 
-use base64;
 use serde::{Deserialize, Serialize};
 
-use web_sys::{console, Element, HtmlInputElement, InputEvent, RtcDataChannelState};
+use gloo::events::EventListener;
+use gloo_timers::callback::{Interval, Timeout};
+use web_sys::{
+    console, DragEvent, Element, File, FileList, HtmlInputElement, InputEvent, MediaStream,
+    RtcDataChannelState, RtcIceConnectionState,
+};
 
-use wasm_bindgen::JsCast;
+use wasm_bindgen::{JsCast, JsValue};
 use wasm_bindgen_futures::spawn_local;
 
-use yew::{html, html::NodeRef, Context, Component, Html, KeyboardEvent, TargetCast};
+use yew::{
+    classes, html, html::NodeRef, AttrValue, Context, Component, Html, KeyboardEvent, TargetCast,
+};
 
-use crate::webrtc_chat::web_rtc_manager::{ConnectionState, IceCandidate, NetworkManager, State};
+use crate::components::chat_history::ChatHistory;
+use crate::components::qr_scanner::QrScanner;
+use crate::components::video_tile::VideoTile;
+use crate::components::whiteboard::Whiteboard;
+use crate::config::{Config, IceServer, WebRtcDefaults};
+use crate::utils::file_sharing::{FileHandler, FileInfo};
+use crate::webrtc_chat::call::{self, Call, CallPhase};
+use crate::webrtc_chat::connection_code;
+use crate::webrtc_chat::crypto::SecureChannel;
+use crate::webrtc_chat::diagnostics::{self, ConnectionStats, StatsHistory};
+use crate::webrtc_chat::file_transfer::{FileTransfer, MAX_FILE_SIZE_MB};
+use crate::webrtc_chat::history::{self, Conversation, StoredMessage};
+use crate::webrtc_chat::markdown;
+use crate::webrtc_chat::protocol::{self, CallAction, Frame, ReceiptKind};
+use crate::webrtc_chat::reconnect::{Reconnect, MAX_RECONNECT_ATTEMPTS};
+use crate::webrtc_chat::send_queue::MAX_QUEUED_FRAMES;
+use crate::webrtc_chat::step_timeout::{expected_wait, StepTimeout, WaitKind, MAX_STEP_ATTEMPTS};
+use crate::webrtc_chat::transcript::{Transcript, TranscriptFormat, TranscriptMessage};
+use crate::webrtc_chat::voice::{self, VoiceClip, VoiceInfo, VoiceRecorder};
+use crate::webrtc_chat::whiteboard::{Board, BoardOp};
+use crate::webrtc_chat::room::{
+    self, Participant, ParticipantStatus, PeerId, RoomPeer, HOST_PARTICIPANT_ID,
+};
+use crate::webrtc_chat::signaling::{
+    self, ClientId, SignalData, SignaledConnection, SignalingClient, SignalingEvent, SignalingSession,
+};
+use crate::webrtc_chat::web_rtc_manager::{ConnectionState, IceCandidate, NetworkManager, State, WebRtcError};
 use crate::utils::qr_code::QrCodeGenerator;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub enum MessageSender {
     Me,
     Other,
@@ -27,6 +60,13 @@ pub struct Message {
     content: String,
     timestamp: u64,
     id: String,
+    attachment: Option<String>, // id of the FileTransfer shown in this bubble
+    author: Option<String>,     // participant id, for messages from others
+    receipt: Option<ReceiptKind>, // best receipt so far, for messages we sent
+    edited_at: Option<u64>,
+    deleted: bool,
+    reactions: BTreeMap<String, BTreeSet<String>>, // emoji -> participant ids
+    reply_to: Option<String>,                       // id of the parent message
 }
 
 #[derive(Clone, Debug)]
@@ -37,20 +77,155 @@ pub enum ConnectionStep {
     SharingCode,
     WaitingForConnection,
     WaitingForAnswer, // Host waiting for client's answer
+    SignalingRoom,    // In a signaling server room, waiting for the other side
     Connected,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum IceServerField {
+    Urls,
+    Username,
+    Credential,
+}
+
+/// Someone else in the conversation who is currently typing.
 #[derive(Clone, Debug)]
 pub struct TypingState {
-    is_typing: bool,
+    author: String, // participant id
     last_activity: u64,
 }
 
+// Typing frames are sent at most this often while keys keep coming...
+const TYPING_THROTTLE_MS: u64 = 2_000;
+// ...and a stop is sent after this long without a key press.
+const TYPING_IDLE_MS: u32 = 3_000;
+// A remote indicator disappears on its own if the stop frame never arrives.
+const TYPING_EXPIRY_MS: u32 = 5_000;
+
+const TOAST_MS: u32 = 6_000;
+
+/// A failure shown above the chat until it times out or is dismissed.
+#[derive(Clone, Debug)]
+pub struct Toast {
+    id: u32,
+    text: String,
+}
+
+const QUOTE_LEN: usize = 60;
+
+const REACTION_EMOJI: [&str; 6] = ["👍", "❤️", "😂", "😮", "😢", "🎉"];
+
 impl Message {
     pub fn new(content: String, sender: MessageSender) -> Message {
         let timestamp = js_sys::Date::now() as u64;
-        let id = format!("msg_{}", timestamp);
-        Message { content, sender, timestamp, id }
+        // Edits and reactions point at this id, so it must not collide with
+        // a message someone else sent in the same millisecond
+        let id = format!("msg_{}_{:08x}", timestamp, rand::random::<u32>());
+        Message {
+            content,
+            sender,
+            timestamp,
+            id,
+            attachment: None,
+            author: None,
+            receipt: None,
+            edited_at: None,
+            deleted: false,
+            reactions: BTreeMap::new(),
+            reply_to: None,
+        }
+    }
+
+    pub fn with_attachment(transfer: &FileTransfer, sender: MessageSender) -> Message {
+        Message {
+            attachment: Some(transfer.id.clone()),
+            ..Message::new(transfer.info.name.clone(), sender)
+        }
+    }
+
+    pub fn from_stored(stored: StoredMessage) -> Message {
+        Message {
+            sender: stored.sender,
+            content: stored.content,
+            timestamp: stored.timestamp,
+            id: stored.id,
+            attachment: stored.attachment,
+            author: stored.author,
+            receipt: None,
+            edited_at: stored.edited_at,
+            deleted: stored.deleted,
+            reactions: stored.reactions,
+            reply_to: stored.reply_to,
+        }
+    }
+
+    pub fn to_stored(&self, conversation_id: &str) -> StoredMessage {
+        StoredMessage {
+            conversation_id: conversation_id.to_string(),
+            id: self.id.clone(),
+            sender: self.sender.clone(),
+            timestamp: self.timestamp,
+            content: self.content.clone(),
+            edited_at: self.edited_at,
+            deleted: self.deleted,
+            reply_to: self.reply_to.clone(),
+            author: self.author.clone(),
+            reactions: self.reactions.clone(),
+            attachment: self.attachment.clone(),
+        }
+    }
+
+    pub fn to_frame(&self) -> Frame {
+        Frame::Chat {
+            id: self.id.clone(),
+            content: self.content.clone(),
+            timestamp: self.timestamp,
+            author: self.author.clone(),
+            reply_to: self.reply_to.clone(),
+        }
+    }
+
+    /// Applies an edit unless the message is deleted or already carries a
+    /// newer one. Ties on `edited_at` go to the greater text, so every side
+    /// settles on the same content whatever order the edits arrive in.
+    pub fn apply_edit(&mut self, content: String, edited_at: u64) -> bool {
+        let is_newer = Some((edited_at, &content)) > self.edited_at.map(|at| (at, &self.content));
+        if self.deleted || self.attachment.is_some() || !is_newer {
+            return false;
+        }
+
+        self.content = content;
+        self.edited_at = Some(edited_at);
+        true
+    }
+
+    pub fn apply_delete(&mut self) -> bool {
+        if self.deleted {
+            return false;
+        }
+
+        self.deleted = true;
+        self.content = String::new();
+        self.attachment = None;
+        self.reactions.clear();
+        true
+    }
+
+    pub fn apply_reaction(&mut self, emoji: &str, reactor: &str, add: bool) -> bool {
+        if self.deleted {
+            return false;
+        }
+
+        if add {
+            self.reactions.entry(emoji.to_string()).or_default().insert(reactor.to_string())
+        } else {
+            let removed = self
+                .reactions
+                .get_mut(emoji)
+                .is_some_and(|reactors| reactors.remove(reactor));
+            self.reactions.retain(|_, reactors| !reactors.is_empty());
+            removed
+        }
     }
 }
 
@@ -60,31 +235,97 @@ pub struct ConnectionString {
     pub offer: String, // TODO : convert as JsValue using Json.Parse
 }
 pub struct ChatModel<T: NetworkManager + 'static> {
-    web_rtc_manager: Rc<RefCell<T>>,
+    web_rtc_manager: Rc<RefCell<T>>, // connection the wizard is currently pairing
+    pairing_peer_id: PeerId,
+    next_peer_id: PeerId,
+    peers: Vec<RoomPeer<T>>,
+    roster: Vec<Participant>,
+    my_participant_id: Option<String>,
+    nickname: String,
+    local_identity: String,
+    conversation_id: Option<String>, // identity of the first peer, once known
+    known_authors: BTreeMap<String, String>, // participant id -> identity, on the host
+    show_history: bool,
+    show_export_menu: bool,
+    show_whiteboard: bool,
+    board: Rc<Board>,
+    board_unseen: bool, // remote changes since the whiteboard was last shown
     messages: Vec<Message>,
     value: String,
     chat_value: String,
     node_ref: NodeRef,
     connection_step: ConnectionStep,
-    typing_state: TypingState,
+    typing: Vec<TypingState>,
     connection_code: Option<String>,
-    last_typing_time: u64,
+    last_typing_time: u64, // when we last sent a typing frame, 0 once stopped
+    _typing_idle: Option<Timeout>,
+    unread: Vec<(PeerId, String)>, // messages we have not sent a read receipt for
+    editing: Option<String>,       // id of our message the input currently edits
+    replying_to: Option<String>,   // id of the message the next one replies to
+    thread: Option<String>,        // id of the message whose replies are open
+    _visibility_listener: EventListener,
     qr_code_data_url: Option<String>,
     show_qr_modal: bool,
+    transfers: Vec<FileTransfer>,
+    paused_transfers: Vec<String>, // ids waiting for the send queues to drain
+    is_dragging_file: bool,
+    ice_settings: WebRtcDefaults,
+    show_ice_settings: bool,
+    show_qr_scanner: bool,
+    recording_since: Option<f64>, // set while the record button is held
+    voice_recorder: Option<VoiceRecorder>,
+    _recording_tick: Option<Interval>,
+    call: Option<Call>,
+    stats: BTreeMap<PeerId, StatsHistory>,
+    _stats_poll: Option<Interval>, // set while the diagnostics panel is open
+    reconnect: Option<Reconnect>,
+    _reconnect_timeout: Option<Timeout>,
+    _reconnect_countdown: Option<Interval>,
+    step_timeout: Option<StepTimeout>, // deadline of the wizard step being waited on
+    _step_tick: Option<Interval>,
+    pasted_offer: String, // kept so a stalled answer can be generated again
+    signaling: Option<SignalingSession<T>>,
+    signaling_room: String,
+    signaling_error: Option<String>,
+    toasts: Vec<Toast>,
+    next_toast_id: u32,
 }
 
 #[derive(Clone, Debug)]
 pub enum Msg {
     StartAsServer,
     ConnectToServer,
-    UpdateWebRTCState(State),
+    UpdateWebRTCState(PeerId, State),
     Disconnect,
     Send,
     NewMessage(Message),
+    ReceiveFrame(PeerId, Frame),
+    ReceiveSecureFrame(PeerId, Frame),
+    SendSecureFrame(PeerId, Frame), // from the connection itself, e.g. to renegotiate
+    SecureSessionReady(PeerId),
+    MarkVerified(PeerId),
+    HistoryLoaded(Vec<StoredMessage>),
+    OpenHistory,
+    CloseHistory,
+    ToggleExportMenu,
+    ShowWhiteboard(bool),
+    DrawOnBoard(BoardOp),
+    WhiteboardFailed(String),
+    ExportTranscript(TranscriptFormat),
     UpdateInputValue(String),
     UpdateInputChatValue(String),
     OnKeyUp(KeyboardEvent),
+    StartEdit(String),
+    CancelEdit,
+    DeleteMessage(String),
+    ToggleReaction(String, String),
+    ReplyTo(String),
+    CancelReply,
+    ScrollToMessage(String),
+    OpenThread(String),
+    CloseThread,
     CopyToClipboard,
+    CopyCode(String),
     ValidateOffer,
     ResetWebRTC,
     // New connection wizard messages
@@ -95,9 +336,70 @@ pub enum Msg {
     // Typing indicator messages
     StartTyping,
     StopTyping,
-    UpdateTypingState(bool),
+    UpdateTypingState(String, bool),
+    ExpireTyping,
+    VisibilityChanged,
     // QR Code and File Sharing messages
     CloseQRModal,
+    OpenQrScanner,
+    CloseQrScanner,
+    QrCodeScanned(String),
+    SelectFiles(Vec<File>),
+    SetDraggingFile(bool),
+    FileRead(FileInfo),
+    SendNextChunk(String),
+    SendQueueDrained(PeerId),
+    FileTransferFailed(String),
+    // Voice messages
+    StartRecording,
+    RecordingStarted(MediaStream), // the microphone, once the user allowed it
+    RecordFrom(MediaStream),       // any audio stream, e.g. a synthetic one in tests
+    RecordingTick,
+    StopRecording,
+    VoiceRecorded(Result<VoiceClip, String>),
+    VoiceFailed(String),
+
+    StartCall(bool), // with video
+    AcceptCall,
+    DeclineCall,
+    HangUp,
+    LocalMediaOpened(MediaStream), // microphone and camera, once the user allowed them
+    CallWith(MediaStream),         // any stream, e.g. a synthetic one in tests
+    RemoteMedia(PeerId, MediaStream),
+    ToggleMute,
+    ToggleCamera,
+    StartScreenShare,
+    ScreenShareOpened(MediaStream),
+    StopScreenShare,
+    CallFailed(String),
+
+    ToggleDiagnostics,
+    PollStats,
+    StatsReceived(PeerId, ConnectionStats),
+    // Per-session ICE server overrides
+    ToggleIceSettings,
+    AddIceServer,
+    RemoveIceServer(usize),
+    UpdateIceServer(usize, IceServerField, String),
+    SetRelayOnly(bool),
+    ResetIceSettings,
+    ReconnectAttempt,
+    ReconnectTick,
+    RePair,
+    StepTick,
+    RetryStep,
+    InviteParticipant,
+    UpdateNickname(String),
+
+    UpdateSignalingUrl(String),
+    UpdateSignalingRoom(String),
+    JoinSignalingRoom(bool), // as host
+    SignalingEvent(SignalingEvent),
+    SendSignal(ClientId, SignalData),
+    LeaveSignalingRoom,
+
+    ConnectionError(PeerId, WebRtcError),
+    DismissToast(u32),
 }
 
 // UI done from: https://codepen.io/sajadhsm/pen/odaBdd
@@ -107,21 +409,66 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
     type Properties = ();
 
     fn create(ctx: &Context<Self>) -> Self {
+        let link = ctx.link().clone();
+        let visibility_listener = EventListener::new(&gloo_utils::document(), "visibilitychange", move |_| {
+            link.send_message(Msg::VisibilityChanged)
+        });
+
         ChatModel {
-            web_rtc_manager: T::new(ctx.link()),
+            web_rtc_manager: T::new(ctx.link(), 1),
+            pairing_peer_id: 1,
+            next_peer_id: 2,
+            peers: vec![],
+            roster: vec![],
+            my_participant_id: None,
+            nickname: room::random_nickname(),
+            local_identity: history::local_identity(),
+            conversation_id: None,
+            known_authors: BTreeMap::new(),
+            show_history: false,
+            show_export_menu: false,
+            show_whiteboard: false,
+            board: Rc::new(Board::default()),
+            board_unseen: false,
             messages: vec![],
             value: "".into(),
             chat_value: "".into(),
             node_ref: NodeRef::default(),
             connection_step: ConnectionStep::Welcome,
-            typing_state: TypingState {
-                is_typing: false,
-                last_activity: 0,
-            },
+            typing: vec![],
             connection_code: None,
             last_typing_time: 0,
+            _typing_idle: None,
+            unread: vec![],
+            editing: None,
+            replying_to: None,
+            thread: None,
+            _visibility_listener: visibility_listener,
             qr_code_data_url: None,
             show_qr_modal: false,
+            transfers: vec![],
+            paused_transfers: vec![],
+            is_dragging_file: false,
+            ice_settings: default_ice_settings(),
+            show_ice_settings: false,
+            show_qr_scanner: false,
+            recording_since: None,
+            voice_recorder: None,
+            _recording_tick: None,
+            call: None,
+            stats: BTreeMap::new(),
+            _stats_poll: None,
+            reconnect: None,
+            _reconnect_timeout: None,
+            _reconnect_countdown: None,
+            step_timeout: None,
+            _step_tick: None,
+            pasted_offer: String::new(),
+            signaling: None,
+            signaling_room: signaling::random_room_name(),
+            signaling_error: None,
+            toasts: vec![],
+            next_toast_id: 1,
         }
     }
 
@@ -129,39 +476,42 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
     // fn update(&mut self, msg: Self::Message) -> ShouldRender {
         match msg {
             Msg::StartAsServer => {
-                self.web_rtc_manager
-                    .borrow_mut()
-                    .set_state(State::Server(ConnectionState::new()));
-                T::start_web_rtc(self.web_rtc_manager.clone())
-                    .expect("Failed to start WebRTC manager");
+                if let Err(err) = self.start_connection(State::Server(ConnectionState::new())) {
+                    self.show_toast(ctx, err.to_string());
+                }
 
                 true
             }
 
             Msg::ConnectToServer => {
-                self.web_rtc_manager
-                    .borrow_mut()
-                    .set_state(State::Client(ConnectionState::new()));
-                T::start_web_rtc(self.web_rtc_manager.clone())
-                    .expect("Failed to start WebRTC manager");
+                if let Err(err) = self.start_connection(State::Client(ConnectionState::new())) {
+                    self.show_toast(ctx, err.to_string());
+                }
 
                 true
             }
 
-            Msg::UpdateWebRTCState(web_rtc_state) => {
-                self.value = "".into();
+            Msg::UpdateWebRTCState(peer_id, web_rtc_state) => {
                 let debug = get_debug_state_string(&web_rtc_state);
                 console::log_1(&debug.into());
 
+                // Only the connection being paired drives the wizard
+                if peer_id == self.pairing_peer_id {
+                self.value = "".into();
+
                 // Handle state transitions based on WebRTC state changes
                 match (&self.connection_step, &web_rtc_state) {
                     // Auto-generate connection code when ICE gathering is complete for server
                     (ConnectionStep::GeneratingCode, State::Server(connection_state)) => {
                         if connection_state.ice_gathering_state.is_some() {
                             // ICE gathering is ready, generate the connection code
-                            let encoded = self.get_serialized_offer_and_candidates();
-                            self.connection_code = Some(encoded);
-                            self.connection_step = ConnectionStep::SharingCode;
+                            match self.get_serialized_offer_and_candidates() {
+                                Ok(encoded) => {
+                                    self.connection_code = Some(encoded);
+                                    self.connection_step = ConnectionStep::SharingCode;
+                                }
+                                Err(err) => self.show_toast(ctx, err.to_string()),
+                            }
                         }
                     }
                     // Transition to chat interface when data channel opens
@@ -174,16 +524,25 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
                     }
                     _ => {}
                 }
+                }
+
+                self.track_peer(ctx, peer_id, &web_rtc_state);
 
                 true
             }
 
             Msg::ResetWebRTC => {
-                self.web_rtc_manager = T::new(ctx.link());
-                self.messages = vec![];
-                self.chat_value = "".into();
+                self.new_pairing_manager(ctx);
                 self.value = "".into();
 
+                // A failed invitation must not tear down the rest of the room
+                if self.peers.is_empty() {
+                    self.messages = vec![];
+                    self.chat_value = "".into();
+                } else {
+                    self.connection_step = ConnectionStep::Connected;
+                }
+
                 true
             }
 
@@ -208,15 +567,8 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
                     (State::Server(_connection_state), ConnectionStep::WaitingForAnswer) => {
                         let result = T::validate_answer(self.web_rtc_manager.clone(), &self.value);
 
-                        if result.is_err() {
-                            web_sys::Window::alert_with_message(
-                                &web_sys::window().unwrap(),
-                                &format!(
-                                    "Cannot use answer. Failure reason: {:?}",
-                                    result.err().unwrap()
-                                ),
-                            )
-                            .expect("alert should work");
+                        if let Err(err) = result {
+                            self.show_toast(ctx, err.to_string());
                         } else {
                             // Answer validated successfully, connection should be establishing
                             // We'll wait for the data channel to open via UpdateWebRTCState
@@ -226,38 +578,20 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
                     // Client validating host's offer
                     (_, ConnectionStep::WaitingForConnection) => {
                         // For clients, we need to initialize WebRTC first before validating the offer
-                        // Set the client state and start WebRTC
-                        self.web_rtc_manager
-                            .borrow_mut()
-                            .set_state(State::Client(ConnectionState::new()));
+                        let start_result =
+                            self.start_connection(State::Client(ConnectionState::new()));
                         
-                        // Initialize WebRTC connection for the client
-                        let start_result = T::start_web_rtc(self.web_rtc_manager.clone());
-                        
-                        if start_result.is_err() {
-                            web_sys::Window::alert_with_message(
-                                &web_sys::window().unwrap(),
-                                &format!(
-                                    "Failed to start WebRTC connection: {:?}",
-                                    start_result.err().unwrap()
-                                ),
-                            )
-                            .expect("alert should work");
+                        if let Err(err) = start_result {
+                            self.show_toast(ctx, err.to_string());
                             return true;
                         }
 
                         // Now validate the offer with the initialized connection
+                        self.pasted_offer = self.value.clone();
                         let result = T::validate_offer(self.web_rtc_manager.clone(), &self.value);
 
-                        if result.is_err() {
-                            web_sys::Window::alert_with_message(
-                                &web_sys::window().unwrap(),
-                                &format!(
-                                    "Cannot use offer. Failure reason: {:?}",
-                                    result.err().unwrap()
-                                ),
-                            )
-                            .expect("alert should work");
+                        if let Err(err) = result {
+                            self.show_toast(ctx, err.to_string());
                         } else {
                             // Offer validated successfully, now we need to wait for the answer to be generated
                             // The validate_offer function generates an answer internally
@@ -267,11 +601,7 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
                     }
                     // Fallback - shouldn't happen but handle gracefully
                     _ => {
-                        web_sys::Window::alert_with_message(
-                            &web_sys::window().unwrap(),
-                            "Invalid connection state for validation",
-                        )
-                        .expect("alert should work");
+                        self.show_toast(ctx, "Invalid connection state for validation".to_string());
                     }
                 };
 
@@ -279,16 +609,334 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
             }
 
             Msg::NewMessage(message) => {
-                self.messages.push(message);
+                self.add_message(message);
+                self.scroll_top();
+
+                true
+            }
+
+            Msg::ReceiveFrame(peer_id, frame) => {
+                let peer = self.peers.iter().find(|peer| peer.id == peer_id);
+                match (frame, peer) {
+                    (Frame::KeyExchange { public_key }, Some(peer)) => {
+                        peer.secure.accept_public_key(public_key)
+                    }
+                    (Frame::Encrypted { seq, iv, data }, Some(peer)) => peer.secure.receive(seq, iv, data),
+                    // Everything else must arrive sealed, or a relay in the middle could forge it
+                    (other, _) => {
+                        console::log_1(&format!("Dropping unencrypted frame: {:?}", other).into());
+                    }
+                }
+
+                false
+            }
+
+            Msg::SendSecureFrame(peer_id, frame) => {
+                match self.peers.iter().find(|peer| peer.id == peer_id) {
+                    Some(peer) => peer.send(&frame),
+                    None => console::log_1(&format!("No secure link to send {:?} on", frame).into()),
+                }
+                false
+            }
+
+            Msg::SecureSessionReady(_peer_id) => true,
+
+            Msg::HistoryLoaded(stored) => {
+                let earlier: Vec<Message> = stored
+                    .into_iter()
+                    .filter(|stored| !self.messages.iter().any(|message| message.id == stored.id))
+                    .map(Message::from_stored)
+                    .collect();
+
+                self.messages.extend(earlier);
+                self.messages.sort_by_key(|message| message.timestamp);
+                self.adopt_returning_authors();
+
+                // Anything exchanged before the peer identified itself
+                self.save_messages(&self.messages);
                 self.scroll_top();
+                true
+            }
+
+            Msg::OpenHistory => {
+                self.show_history = true;
+                true
+            }
+
+            Msg::CloseHistory => {
+                self.show_history = false;
+                true
+            }
+
+            Msg::ToggleExportMenu => {
+                self.show_export_menu = !self.show_export_menu;
+                true
+            }
+
+            Msg::ShowWhiteboard(show) => {
+                self.show_whiteboard = show;
+                if show {
+                    self.board_unseen = false;
+                }
+                true
+            }
+
+            Msg::DrawOnBoard(op) => {
+                let me = self.my_reactor_id();
+                if Rc::make_mut(&mut self.board).apply(&me, op.clone()) {
+                    self.broadcast(&Frame::Board { op, author: None });
+                } else if self.board.is_full() && !matches!(op, BoardOp::Undo { .. }) {
+                    self.show_toast(ctx, "The whiteboard is full".to_string());
+                }
+                true
+            }
+
+            Msg::WhiteboardFailed(err) => {
+                self.show_toast(ctx, err);
+                true
+            }
+
+            Msg::ExportTranscript(format) => {
+                self.show_export_menu = false;
+
+                let transcript = self.transcript();
+                let data = transcript.render(format).into_bytes();
+                let file_info = FileInfo {
+                    name: transcript.file_name(format),
+                    size: data.len() as u64,
+                    file_type: format.mime_type().to_string(),
+                    data,
+                };
+                if let Err(err) = FileHandler::save_as(&file_info) {
+                    self.show_toast(ctx, err);
+                }
+                true
+            }
+
+            Msg::ToggleDiagnostics => {
+                self.stats = BTreeMap::new();
+                if self._stats_poll.take().is_none() {
+                    let link = ctx.link().clone();
+                    self._stats_poll = Some(Interval::new(diagnostics::STATS_POLL_MS, move || {
+                        link.send_message(Msg::PollStats)
+                    }));
+                    ctx.link().send_message(Msg::PollStats);
+                }
+                true
+            }
+
+            Msg::PollStats => {
+                for (_, manager) in self.stats_targets() {
+                    T::request_stats(manager);
+                }
+                false
+            }
+
+            Msg::StatsReceived(peer_id, stats) => {
+                if self._stats_poll.is_none() {
+                    return false;
+                }
+                self.stats.entry(peer_id).or_default().push(stats);
+                true
+            }
 
+            Msg::MarkVerified(peer_id) => {
+                if let Some(peer) = self.peers.iter_mut().find(|peer| peer.id == peer_id) {
+                    peer.verified = true;
+                }
                 true
             }
 
+            Msg::ReceiveSecureFrame(peer_id, frame) => {
+                match frame {
+                    Frame::Chat { id, content, timestamp, author, reply_to } => {
+                        let message = Message {
+                            sender: MessageSender::Other,
+                            content,
+                            timestamp,
+                            id: id.clone(),
+                            attachment: None,
+                            author: Some(self.author_of(peer_id, author)),
+                            receipt: None,
+                            edited_at: None,
+                            deleted: false,
+                            reactions: BTreeMap::new(),
+                            reply_to,
+                        };
+                        self.relay(peer_id, &message.to_frame());
+
+                        if let Some(author) = &message.author {
+                            self.typing.retain(|typing| &typing.author != author);
+                        }
+                        if let Some(peer) = self.peers.iter().find(|peer| peer.id == peer_id) {
+                            peer.send(&Frame::Receipt { message_id: id.clone(), kind: ReceiptKind::Delivered });
+                        }
+                        self.unread.push((peer_id, id));
+                        self.send_read_receipts();
+
+                        ctx.link().send_message(Msg::NewMessage(message));
+                    }
+                    Frame::Receipt { message_id, kind } => {
+                        self.accept_receipt(peer_id, message_id, kind);
+                    }
+                    Frame::Edit { message_id, content, edited_at, author } => {
+                        let author = self.author_of(peer_id, author);
+                        self.relay(peer_id, &Frame::Edit {
+                            message_id: message_id.clone(),
+                            content: content.clone(),
+                            edited_at,
+                            author: Some(author.clone()),
+                        });
+                        return self.update_message(&message_id, |message| {
+                            message.author.as_deref() == Some(author.as_str())
+                                && message.apply_edit(content, edited_at)
+                        });
+                    }
+                    Frame::Delete { message_id, author } => {
+                        let author = self.author_of(peer_id, author);
+                        self.relay(peer_id, &Frame::Delete {
+                            message_id: message_id.clone(),
+                            author: Some(author.clone()),
+                        });
+                        return self.update_message(&message_id, |message| {
+                            message.author.as_deref() == Some(author.as_str()) && message.apply_delete()
+                        });
+                    }
+                    Frame::Reaction { message_id, emoji, add, author } => {
+                        let author = self.author_of(peer_id, author);
+                        self.relay(peer_id, &Frame::Reaction {
+                            message_id: message_id.clone(),
+                            emoji: emoji.clone(),
+                            add,
+                            author: Some(author.clone()),
+                        });
+                        return self.update_message(&message_id, |message| {
+                            message.apply_reaction(&emoji, &author, add)
+                        });
+                    }
+                    Frame::Board { op, author } => {
+                        let author = self.author_of(peer_id, author);
+                        self.relay(peer_id, &Frame::Board { op: op.clone(), author: Some(author.clone()) });
+                        let changed = Rc::make_mut(&mut self.board).apply(&author, op);
+                        self.board_unseen |= changed && !self.show_whiteboard;
+                        return changed;
+                    }
+                    Frame::FileManifest { transfer_id, name, size, file_type, chunk_count, voice } => {
+                        let mut transfer =
+                            match FileTransfer::incoming(transfer_id, name, size, file_type, chunk_count) {
+                                Ok(transfer) => transfer,
+                                Err(err) => {
+                                    ctx.link().send_message(Msg::FileTransferFailed(err));
+                                    return false;
+                                }
+                            };
+                        transfer.voice = voice;
+                        // Guests only have the host as a peer, so only the host relays
+                        self.relay(peer_id, &transfer.manifest());
+                        ctx.link().send_message(Msg::NewMessage(Message::with_attachment(
+                            &transfer,
+                            MessageSender::Other,
+                        )));
+                        self.transfers.push(transfer);
+                    }
+                    Frame::FileChunk { transfer_id, index, data } => {
+                        let result = match self.transfers.iter_mut().find(|t| t.id == transfer_id) {
+                            Some(transfer) => transfer.accept_chunk(index, data.clone()),
+                            None => Err(format!("Chunk for unknown transfer {}", transfer_id)),
+                        };
+                        // Only chunks of a manifest that was accepted are passed on
+                        if result.is_ok() {
+                            self.relay(peer_id, &Frame::FileChunk { transfer_id, index, data });
+                        }
+
+                        if let Err(err) = result {
+                            ctx.link().send_message(Msg::FileTransferFailed(err));
+                        }
+
+                        return true;
+                    }
+                    Frame::Typing { is_typing, author } => {
+                        let author = self.author_of(peer_id, author);
+                        self.relay(peer_id, &Frame::Typing { is_typing, author: Some(author.clone()) });
+                        ctx.link().send_message(Msg::UpdateTypingState(author, is_typing));
+                    }
+                    Frame::Call { action } => {
+                        return self.receive_call_action(peer_id, action);
+                    }
+                    Frame::Description { description } => {
+                        if let Some(peer) = self.peers.iter().find(|peer| peer.id == peer_id) {
+                            T::receive_renegotiation(peer.manager.clone(), SignalData::Description { description });
+                        }
+                    }
+                    Frame::Candidate { candidate } => {
+                        if let Some(peer) = self.peers.iter().find(|peer| peer.id == peer_id) {
+                            T::receive_renegotiation(peer.manager.clone(), SignalData::Candidate { candidate });
+                        }
+                    }
+                    Frame::Identity { identity } => {
+                        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.id == peer_id) {
+                            peer.identity = Some(identity.clone());
+                        }
+                        if self.is_host() {
+                            self.known_authors.insert(room::participant_id(peer_id), identity.clone());
+                            let adopted = self.adopt_returning_authors();
+                            self.save_messages(&adopted);
+                        }
+
+                        if self.conversation_id.is_none() {
+                            self.conversation_id = Some(identity.clone());
+                            let link = ctx.link().clone();
+                            spawn_local(async move {
+                                match history::load_messages(&identity).await {
+                                    Ok(stored) => link.send_message(Msg::HistoryLoaded(stored)),
+                                    Err(err) => console::log_2(&"Failed to load chat history".into(), &err),
+                                }
+                            });
+                        }
+                        return false;
+                    }
+                    Frame::Hello { nickname } => {
+                        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.id == peer_id) {
+                            peer.nickname = room::clean_nickname(&nickname);
+                        }
+                        self.refresh_roster();
+                        return true;
+                    }
+                    Frame::Roster { you, participants } => {
+                        // Only the host hands out participant ids
+                        if self.is_host() {
+                            return false;
+                        }
+                        self.my_participant_id = Some(you);
+                        self.roster = participants;
+                        return true;
+                    }
+                    other => {
+                        console::log_1(&format!("Unhandled frame: {:?}", other).into());
+                    }
+                }
+
+                false
+            }
+
             Msg::Send => {
-                let my_message = Message::new(self.chat_value.clone(), MessageSender::Me);
-                self.messages.push(my_message);
-                self.web_rtc_manager.borrow().send_message(&self.chat_value);
+                self.stop_typing();
+                if let Some(message_id) = self.editing.take() {
+                    let content = std::mem::take(&mut self.chat_value);
+                    let edited_at = js_sys::Date::now() as u64;
+                    let edit = Frame::Edit { message_id: message_id.clone(), content: content.clone(), edited_at, author: None };
+                    if self.update_message(&message_id, |message| message.apply_edit(content, edited_at)) {
+                        self.broadcast(&edit);
+                    }
+                    return true;
+                }
+
+                let my_message = Message {
+                    reply_to: self.replying_to.take(),
+                    ..Message::new(self.chat_value.clone(), MessageSender::Me)
+                };
+                self.broadcast(&my_message.to_frame());
+                self.add_message(my_message);
                 self.chat_value = "".into();
                 self.scroll_top();
 
@@ -296,8 +944,23 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
             }
 
             Msg::Disconnect => {
-                self.web_rtc_manager = T::new(ctx.link());
+                self.end_call(false);
+                self.new_pairing_manager(ctx);
+                self.peers = vec![];
+                self.roster = vec![];
+                self.my_participant_id = None;
+                self.conversation_id = None;
+                self.set_reconnect(ctx, None);
+                self.signaling = None;
                 self.messages = vec![];
+                self.transfers = vec![];
+                self.board = Rc::new(Board::default());
+                self.board_unseen = false;
+                self.typing = vec![];
+                self.unread = vec![];
+                self.editing = None;
+                self.replying_to = None;
+                self.thread = None;
                 self.chat_value = "".into();
                 self.value = "".into();
 
@@ -306,35 +969,112 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
 
             Msg::OnKeyUp(event) => {
                 if event.key_code() == 13 && !self.chat_value.is_empty() {
-                    let my_message = Message::new(self.chat_value.clone(), MessageSender::Me);
-                    self.messages.push(my_message);
-                    self.web_rtc_manager.borrow().send_message(&self.chat_value);
+                    ctx.link().send_message(Msg::Send);
+                }
+
+                true
+            }
+
+            Msg::StartEdit(message_id) => {
+                let Some(message) = self.messages.iter().find(|message| message.id == message_id) else {
+                    return false;
+                };
+                self.chat_value = message.content.clone();
+                self.replying_to = None;
+                self.editing = Some(message_id);
+                true
+            }
+
+            Msg::CancelEdit => {
+                self.editing = None;
+                self.chat_value = "".into();
+                true
+            }
+
+            Msg::DeleteMessage(message_id) => {
+                let confirmed = web_sys::window()
+                    .and_then(|window| window.confirm_with_message("Delete this message for everyone?").ok())
+                    .unwrap_or(false);
+                if !confirmed {
+                    return false;
+                }
+
+                if self.editing.as_ref() == Some(&message_id) {
+                    self.editing = None;
                     self.chat_value = "".into();
-                    self.scroll_top();
                 }
+                if self.update_message(&message_id, Message::apply_delete) {
+                    self.broadcast(&Frame::Delete { message_id, author: None });
+                }
+                true
+            }
+
+            Msg::ReplyTo(message_id) => {
+                self.editing = None;
+                self.replying_to = Some(message_id);
+                true
+            }
+
+            Msg::CancelReply => {
+                self.replying_to = None;
+                true
+            }
+
+            Msg::ScrollToMessage(message_id) => {
+                if let Some(element) = gloo_utils::document().get_element_by_id(&message_element_id(&message_id)) {
+                    element.scroll_into_view();
+                }
+                false
+            }
+
+            Msg::OpenThread(message_id) => {
+                self.thread = Some(message_id);
+                true
+            }
+
+            Msg::CloseThread => {
+                self.thread = None;
+                true
+            }
+
+            Msg::ToggleReaction(message_id, emoji) => {
+                let me = self.my_reactor_id();
+                let Some(message) = self.messages.iter().find(|message| message.id == message_id) else {
+                    return false;
+                };
+                let add = !message.reactions.get(&emoji).is_some_and(|reactors| reactors.contains(&me));
 
+                if self.update_message(&message_id, |message| message.apply_reaction(&emoji, &me, add)) {
+                    self.broadcast(&Frame::Reaction { message_id, emoji, add, author: None });
+                }
                 true
             }
 
             Msg::CopyToClipboard => {
-                self.copy_content_to_clipboard();
+                match self.code_to_share() {
+                    Ok(code) => self.copy_text(ctx, &code),
+                    Err(err) => self.show_toast(ctx, err.to_string()),
+                }
+                true
+            }
 
+            Msg::CopyCode(code) => {
+                self.copy_text(ctx, &code);
                 true
             }
 
             // New connection wizard handlers
             Msg::SetConnectionStep(step) => {
                 self.connection_step = step;
+                self.send_read_receipts();
                 true
             }
 
             Msg::ChooseHost => {
-                self.connection_step = ConnectionStep::GeneratingCode;
-                self.web_rtc_manager
-                    .borrow_mut()
-                    .set_state(State::Server(ConnectionState::new()));
-                T::start_web_rtc(self.web_rtc_manager.clone())
-                    .expect("Failed to start WebRTC manager");
+                match self.start_connection(State::Server(ConnectionState::new())) {
+                    Ok(()) => self.connection_step = ConnectionStep::GeneratingCode,
+                    Err(err) => self.show_toast(ctx, err.to_string()),
+                }
                 true
             }
 
@@ -345,11 +1085,12 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
 
             Msg::GenerateQRCode => {
                 // Generate QR code for the current connection code
-                let code_to_encode = if let Some(code) = &self.connection_code {
-                    code.clone()
-                } else {
-                    // For clients, get the generated answer
-                    self.get_serialized_offer_and_candidates()
+                let code_to_encode = match self.code_to_share() {
+                    Ok(code) => code,
+                    Err(err) => {
+                        self.show_toast(ctx, err.to_string());
+                        return true;
+                    }
                 };
                 
                 match QrCodeGenerator::generate_qr_code_data_url(&code_to_encode) {
@@ -357,12 +1098,7 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
                         self.qr_code_data_url = Some(data_url);
                         self.show_qr_modal = true;
                     }
-                    Err(err) => {
-                        web_sys::Window::alert_with_message(
-                            &web_sys::window().unwrap(),
-                            &format!("Failed to generate QR code: {}", err)
-                        ).expect("alert should work");
-                    }
+                    Err(err) => self.show_toast(ctx, format!("Failed to generate QR code: {}", err)),
                 }
                 true
             }
@@ -372,82 +1108,1578 @@ impl<T: NetworkManager + 'static> Component for ChatModel<T> {
                 true
             }
 
-            // Typing indicator handlers
-            Msg::StartTyping => {
-                let now = js_sys::Date::now() as u64;
-                if now - self.last_typing_time > 2000 { // Only send every 2 seconds
-                    self.last_typing_time = now;
-                    // TODO: Send typing indicator via WebRTC
-                }
+            Msg::OpenQrScanner => {
+                self.show_qr_scanner = true;
                 true
             }
 
-            Msg::StopTyping => {
-                // TODO: Send stop typing indicator via WebRTC
+            Msg::CloseQrScanner => {
+                self.show_qr_scanner = false;
                 true
             }
 
-            Msg::UpdateTypingState(is_typing) => {
-                self.typing_state.is_typing = is_typing;
-                self.typing_state.last_activity = js_sys::Date::now() as u64;
+            Msg::QrCodeScanned(code) => {
+                self.show_qr_scanner = false;
+                self.value = code;
                 true
             }
-        }
-    }
 
-    fn view(&self, ctx: &Context<Self>) -> Html {
-        let content = match (&self.connection_step, &self.web_rtc_manager.borrow().get_state()) {
-            // Connection wizard steps
-            (ConnectionStep::Welcome, _) => self.render_welcome_step(ctx),
-            (ConnectionStep::ChooseRole, _) => self.render_choose_role_step(ctx),
-            (ConnectionStep::GeneratingCode, _) => self.render_generating_step(ctx),
-            (ConnectionStep::SharingCode, _) => self.render_sharing_step(ctx),
-            (ConnectionStep::WaitingForConnection, _) => self.render_waiting_step(ctx),
-            (ConnectionStep::WaitingForAnswer, _) => self.render_waiting_for_answer_step(ctx),
-            
-            // Connected state - show chat interface
-            (_, state @ State::Server(connection_state)) | (_, state @ State::Client(connection_state)) 
-                if self.is_data_channel_open(connection_state) => {
-                self.render_chat_interface(ctx)
+            Msg::SelectFiles(files) => {
+                self.is_dragging_file = false;
+
+                for file in files {
+                    if let Err(err) = FileHandler::validate_file(&file, MAX_FILE_SIZE_MB) {
+                        ctx.link().send_message(Msg::FileTransferFailed(err));
+                        continue;
+                    }
+
+                    let link = ctx.link().clone();
+                    spawn_local(async move {
+                        match FileHandler::read_file(&file).await {
+                            Ok(file_info) => link.send_message(Msg::FileRead(file_info)),
+                            Err(err) => link.send_message(Msg::FileTransferFailed(err)),
+                        }
+                    });
+                }
+                true
             }
-            
-            // Fallback to connection progress for other states
-            _ => {
-                html! {
-                    <>
-                        { self.get_chat_header(ctx) }
-                        <main class="flex-1 overflow-y-auto p-4 bg-gray-50 flex items-center justify-center" id="chat-main" ref={self.node_ref.clone()}>
-                            <div class="text-center">
-                                <div class="animate-spin rounded-full h-12 w-12 border-b-2 border-blue-500 mx-auto mb-4"></div>
-                                <p class="text-gray-600">{"Establishing connection..."}</p>
-                            </div>
-                        </main>
-                        { self.get_input_for_chat_message(ctx) }
-                    </>
+
+            Msg::SetDraggingFile(is_dragging) => {
+                let changed = self.is_dragging_file != is_dragging;
+                self.is_dragging_file = is_dragging;
+                changed
+            }
+
+            Msg::FileRead(file_info) => {
+                self.start_transfer(ctx, FileTransfer::outgoing(file_info));
+                true
+            }
+
+            Msg::StartRecording => {
+                if self.recording_since.is_some() || self.voice_recorder.is_some() {
+                    return false;
                 }
+                self.recording_since = Some(js_sys::Date::now());
+
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match voice::open_microphone().await {
+                        Ok(stream) => link.send_message(Msg::RecordingStarted(stream)),
+                        Err(err) => link.send_message(Msg::VoiceFailed(err)),
+                    }
+                });
+                true
             }
-        };
 
-        html! {
-            <>
+            Msg::RecordingStarted(stream) => {
+                // Released before the microphone was ready
+                if self.recording_since.is_none() {
+                    voice::stop_tracks(&stream);
+                    return false;
+                }
+                ctx.link().send_message(Msg::RecordFrom(stream));
+                false
+            }
+
+            Msg::RecordFrom(stream) => {
+                if self.voice_recorder.is_some() {
+                    voice::stop_tracks(&stream);
+                    return false;
+                }
+                self.recording_since.get_or_insert_with(js_sys::Date::now);
+
+                let on_done = ctx.link().callback(Msg::VoiceRecorded);
+                match VoiceRecorder::start(stream.clone(), on_done) {
+                    Ok(recorder) => {
+                        self.voice_recorder = Some(recorder);
+                        let link = ctx.link().clone();
+                        self._recording_tick = Some(Interval::new(1_000, move || {
+                            link.send_message(Msg::RecordingTick)
+                        }));
+                    }
+                    Err(err) => {
+                        voice::stop_tracks(&stream);
+                        ctx.link().send_message(Msg::VoiceFailed(format!("{:?}", err)));
+                    }
+                }
+                true
+            }
+
+            Msg::RecordingTick => {
+                let elapsed = self.recording_since.map(|since| js_sys::Date::now() - since);
+                if elapsed.is_some_and(|elapsed| elapsed >= voice::MAX_VOICE_MS as f64) {
+                    ctx.link().send_message(Msg::StopRecording);
+                }
+                true
+            }
+
+            Msg::StopRecording => {
+                if self.recording_since.take().is_none() {
+                    return false;
+                }
+                self._recording_tick = None;
+                if let Some(recorder) = &self.voice_recorder {
+                    recorder.stop();
+                }
+                true
+            }
+
+            Msg::VoiceRecorded(result) => {
+                self.voice_recorder = None;
+                let clip = match result {
+                    Ok(clip) => clip,
+                    Err(err) => {
+                        ctx.link().send_message(Msg::VoiceFailed(err));
+                        return true;
+                    }
+                };
+                if clip.info.duration_ms < voice::MIN_VOICE_MS || clip.data.is_empty() {
+                    console::log_1(&"Dropping a voice message that was too short".into());
+                    return true;
+                }
+
+                let file_info = FileInfo {
+                    name: voice::file_name(&clip.mime_type),
+                    size: clip.data.len() as u64,
+                    file_type: clip.mime_type,
+                    data: clip.data,
+                };
+                match FileTransfer::outgoing_voice(file_info, clip.info) {
+                    Ok(transfer) => self.start_transfer(ctx, transfer),
+                    Err(err) => ctx.link().send_message(Msg::VoiceFailed(err)),
+                }
+                true
+            }
+
+            Msg::VoiceFailed(err) => {
+                self.recording_since = None;
+                self._recording_tick = None;
+                self.voice_recorder = None;
+                console::log_1(&err.clone().into());
+                self.show_toast(ctx, format!("Voice message failed: {}", err));
+                true
+            }
+
+            Msg::StartCall(video) => {
+                let Some(peer_id) = self.call_peer() else {
+                    return false;
+                };
+                if self.call.is_some() {
+                    return false;
+                }
+                self.call = Some(Call::new(peer_id, CallPhase::Outgoing, video));
+                open_call_media(ctx, video);
+                true
+            }
+
+            Msg::AcceptCall => {
+                match &self.call {
+                    Some(call) if call.phase == CallPhase::Incoming => open_call_media(ctx, call.video),
+                    _ => return false,
+                }
+                false
+            }
+
+            Msg::DeclineCall | Msg::HangUp => {
+                self.end_call(true);
+                true
+            }
+
+            Msg::LocalMediaOpened(stream) => {
+                // Hung up before the devices were ready
+                if self.call.as_ref().is_none_or(|call| call.local_stream.is_some()) {
+                    voice::stop_tracks(&stream);
+                    return false;
+                }
+                ctx.link().send_message(Msg::CallWith(stream));
+                false
+            }
+
+            Msg::CallWith(stream) => {
+                let peer_id = match (&self.call, self.call_peer()) {
+                    (Some(call), _) => call.peer_id,
+                    (None, Some(peer_id)) => peer_id,
+                    (None, None) => {
+                        voice::stop_tracks(&stream);
+                        return false;
+                    }
+                };
+                let video = stream.get_video_tracks().length() > 0;
+                let call = self.call.get_or_insert_with(|| Call::new(peer_id, CallPhase::Outgoing, video));
+                if call.local_stream.is_some() {
+                    voice::stop_tracks(&stream);
+                    return false;
+                }
+                call.local_stream = Some(stream);
+
+                // The side accepting attaches its media first, so only one
+                // offer is ever in flight; the caller follows in its answer
+                if call.phase == CallPhase::Incoming {
+                    call.phase = CallPhase::Active;
+                    self.attach_call_media();
+                    self.send_to(peer_id, &Frame::Call { action: CallAction::Accept });
+                } else {
+                    let action = CallAction::Invite { video: call.video };
+                    self.send_to(peer_id, &Frame::Call { action });
+                }
+                true
+            }
+
+            Msg::RemoteMedia(peer_id, stream) => {
+                if let Some(peer) = self.peers.iter_mut().find(|peer| peer.id == peer_id) {
+                    peer.remote_stream = Some(stream);
+                }
+                if self.call.as_ref().is_some_and(|call| {
+                    call.peer_id == peer_id && call.local_stream.is_some() && !call.sending
+                }) {
+                    self.attach_call_media();
+                }
+                true
+            }
+
+            Msg::ToggleMute => {
+                let Some(call) = self.call.as_mut() else {
+                    return false;
+                };
+                call.set_muted(!call.muted);
+                self.send_media_state();
+                true
+            }
+
+            Msg::ToggleCamera => {
+                let Some(call) = self.call.as_mut() else {
+                    return false;
+                };
+                call.set_camera_off(!call.camera_off);
+                self.send_media_state();
+                true
+            }
+
+            Msg::StartScreenShare => {
+                let link = ctx.link().clone();
+                spawn_local(async move {
+                    match call::open_screen().await {
+                        Ok(stream) => link.send_message(Msg::ScreenShareOpened(stream)),
+                        Err(err) => console::log_1(&err.into()),
+                    }
+                });
+                false
+            }
+
+            Msg::ScreenShareOpened(stream) => {
+                let track = stream.get_video_tracks().get(0).dyn_into::<web_sys::MediaStreamTrack>();
+                let (Some(call), Ok(track)) = (self.call.as_mut(), track) else {
+                    voice::stop_tracks(&stream);
+                    return false;
+                };
+                // Only the picture is shared, never the tab's own audio
+                for audio in stream.get_audio_tracks().iter() {
+                    audio.unchecked_into::<web_sys::MediaStreamTrack>().stop();
+                }
+
+                // Stopping from the browser's own "Stop sharing" bar
+                let link = ctx.link().clone();
+                call.share_screen(track, move |_| link.send_message(Msg::StopScreenShare));
+                if call.sending {
+                    self.attach_call_media();
+                }
+                true
+            }
+
+            Msg::StopScreenShare => {
+                let Some(call) = self.call.as_mut() else {
+                    return false;
+                };
+                call.stop_screen_share();
+                if call.sending {
+                    self.attach_call_media();
+                }
+                true
+            }
+
+            Msg::CallFailed(err) => {
+                self.end_call(true);
+                console::log_1(&err.clone().into());
+                self.show_toast(ctx, format!("Call failed: {}", err));
+                true
+            }
+
+            Msg::SendNextChunk(transfer_id) => {
+                // Chunks would only pile up behind a full buffer
+                if self.is_send_queue_full() {
+                    if !self.paused_transfers.contains(&transfer_id) {
+                        self.paused_transfers.push(transfer_id);
+                    }
+                    return true;
+                }
+
+                let frame = self
+                    .transfers
+                    .iter_mut()
+                    .find(|t| t.id == transfer_id)
+                    .and_then(|transfer| transfer.next_chunk());
+
+                if let Some(frame) = frame {
+                    self.broadcast(&frame);
+
+                    // Yield between chunks so the progress bar gets a chance to render
+                    let link = ctx.link().clone();
+                    Timeout::new(0, move || link.send_message(Msg::SendNextChunk(transfer_id)))
+                        .forget();
+                }
+                true
+            }
+
+            Msg::SendQueueDrained(_peer_id) => {
+                if !self.is_send_queue_full() {
+                    for transfer_id in std::mem::take(&mut self.paused_transfers) {
+                        ctx.link().send_message(Msg::SendNextChunk(transfer_id));
+                    }
+                }
+                // Redraws the queue depth
+                true
+            }
+
+            Msg::ToggleIceSettings => {
+                self.show_ice_settings = !self.show_ice_settings;
+                true
+            }
+
+            Msg::AddIceServer => {
+                self.ice_settings.ice_servers.push(IceServer {
+                    urls: vec![],
+                    username: None,
+                    credential: None,
+                });
+                true
+            }
+
+            Msg::RemoveIceServer(index) => {
+                if index < self.ice_settings.ice_servers.len() {
+                    self.ice_settings.ice_servers.remove(index);
+                }
+                true
+            }
+
+            Msg::UpdateIceServer(index, field, value) => {
+                if let Some(server) = self.ice_settings.ice_servers.get_mut(index) {
+                    let value = value.trim().to_string();
+                    match field {
+                        IceServerField::Urls => {
+                            server.urls = value
+                                .split(|c: char| c == ',' || c.is_whitespace())
+                                .filter(|url| !url.is_empty())
+                                .map(String::from)
+                                .collect();
+                        }
+                        IceServerField::Username => {
+                            server.username = Some(value).filter(|v| !v.is_empty());
+                        }
+                        IceServerField::Credential => {
+                            server.credential = Some(value).filter(|v| !v.is_empty());
+                        }
+                    }
+                }
+                true
+            }
+
+            Msg::SetRelayOnly(relay_only) => {
+                self.ice_settings.relay_only = relay_only;
+                true
+            }
+
+            Msg::ResetIceSettings => {
+                self.ice_settings = default_ice_settings();
+                true
+            }
+
+            Msg::ReconnectAttempt => {
+                let reconnect = match &self.reconnect {
+                    Some(reconnect @ Reconnect::Waiting { .. }) => reconnect.clone(),
+                    _ => return false,
+                };
+
+                // Only the side that made the original offer restarts ICE, so
+                // both ends never send competing offers at the same time.
+                if self.is_host() {
+                    for peer in self.peers.iter().filter(|peer| peer.is_ice_dropped()) {
+                        if let Err(err) = T::restart_ice(peer.manager.clone()) {
+                            console::log_1(&format!("Failed to restart ICE: {}", err).into());
+                        }
+                    }
+                }
+
+                self.set_reconnect(ctx, Some(reconnect.next(js_sys::Date::now())));
+                true
+            }
+
+            Msg::ReconnectTick => true,
+
+            Msg::StepTick => {
+                let Some(step_timeout) = &self.step_timeout else {
+                    return false;
+                };
+
+                let (step_timeout, retry) = step_timeout.advance(js_sys::Date::now());
+                let kind = step_timeout.kind();
+                self.step_timeout = Some(step_timeout);
+                if retry {
+                    self.retry_step(ctx, kind);
+                }
+                true
+            }
+
+            Msg::RetryStep => {
+                let Some(step_timeout) = &self.step_timeout else {
+                    return false;
+                };
+
+                let step_timeout = step_timeout.retry_now(js_sys::Date::now());
+                let kind = step_timeout.kind();
+                self.step_timeout = Some(step_timeout);
+                self.retry_step(ctx, kind);
+                true
+            }
+
+            Msg::RePair => {
+                let was_host = self.is_host();
+
+                // Messages, transfers and the roster are kept, only the
+                // connection starts over
+                self.new_pairing_manager(ctx);
+                self.peers = vec![];
+                self.set_reconnect(ctx, None);
+                self.connection_code = None;
+                self.value = "".into();
+
+                if was_host {
+                    ctx.link().send_message(Msg::ChooseHost);
+                } else {
+                    self.connection_step = ConnectionStep::WaitingForConnection;
+                }
+                true
+            }
+
+            Msg::FileTransferFailed(err) => {
+                console::log_1(&err.clone().into());
+                self.show_toast(ctx, format!("File transfer failed: {}", err));
+                true
+            }
+
+            Msg::InviteParticipant => {
+                self.new_pairing_manager(ctx);
+                self.connection_code = None;
+                self.value = "".into();
+                ctx.link().send_message(Msg::ChooseHost);
+                true
+            }
+
+            Msg::UpdateNickname(nickname) => {
+                self.nickname = nickname;
+                true
+            }
+
+            Msg::UpdateSignalingUrl(url) => {
+                self.ice_settings.signaling_url = Some(url.trim().to_string()).filter(|url| !url.is_empty());
+                true
+            }
+
+            Msg::UpdateSignalingRoom(room) => {
+                self.signaling_room = room;
+                true
+            }
+
+            Msg::JoinSignalingRoom(is_host) => {
+                let room = self.signaling_room.trim().to_string();
+                let Some(url) = self.ice_settings.signaling_url.clone() else {
+                    return false;
+                };
+                if room.is_empty() {
+                    return false;
+                }
+
+                match SignalingClient::connect(&url, &room, ctx.link().callback(Msg::SignalingEvent)) {
+                    Ok(client) => {
+                        self.signaling = Some(SignalingSession { client, room, is_host, connections: vec![] });
+                        self.signaling_error = None;
+                        self.connection_step = ConnectionStep::SignalingRoom;
+                    }
+                    Err(err) => {
+                        console::log_2(&"Cannot open the signaling connection".into(), &err);
+                        self.signaling_error = Some(format!("Cannot reach {}", url));
+                    }
+                }
+                true
+            }
+
+            Msg::SignalingEvent(event) => self.receive_signaling_event(ctx, event),
+
+            Msg::SendSignal(remote_id, data) => {
+                if let Some(session) = &self.signaling {
+                    session.client.send_signal(remote_id, &data);
+                }
+                false
+            }
+
+            Msg::ConnectionError(peer_id, error) => {
+                console::log_1(&format!("Connection error on peer {}: {:?}", peer_id, error).into());
+
+                // A code that turned out unusable sends the guest back to paste another
+                if peer_id == self.pairing_peer_id
+                    && !self.is_host()
+                    && matches!(self.connection_step, ConnectionStep::SharingCode)
+                {
+                    self.new_pairing_manager(ctx);
+                    self.connection_step = ConnectionStep::WaitingForConnection;
+                }
+
+                let text = if self.peers.iter().any(|peer| peer.id == peer_id) {
+                    format!("{}: {}", self.peer_name(peer_id), error)
+                } else {
+                    error.to_string()
+                };
+                self.show_toast(ctx, text);
+                true
+            }
+
+            Msg::DismissToast(id) => {
+                let count = self.toasts.len();
+                self.toasts.retain(|toast| toast.id != id);
+                self.toasts.len() != count
+            }
+
+            Msg::LeaveSignalingRoom => {
+                self.signaling = None;
+                self.connection_step = if self.peers.is_empty() {
+                    ConnectionStep::ChooseRole
+                } else {
+                    ConnectionStep::Connected
+                };
+                true
+            }
+
+            // Typing indicator handlers
+            Msg::StartTyping => {
+                if self.chat_value.is_empty() {
+                    self.stop_typing();
+                    return false;
+                }
+
+                let now = js_sys::Date::now() as u64;
+                if now - self.last_typing_time > TYPING_THROTTLE_MS {
+                    self.last_typing_time = now;
+                    self.broadcast(&Frame::Typing { is_typing: true, author: None });
+                }
+
+                let link = ctx.link().clone();
+                self._typing_idle = Some(Timeout::new(TYPING_IDLE_MS, move || {
+                    link.send_message(Msg::StopTyping)
+                }));
+                false
+            }
+
+            Msg::StopTyping => {
+                self.stop_typing();
+                false
+            }
+
+            Msg::UpdateTypingState(author, is_typing) => {
+                self.typing.retain(|typing| typing.author != author);
+                if is_typing {
+                    self.typing.push(TypingState { author, last_activity: js_sys::Date::now() as u64 });
+
+                    let link = ctx.link().clone();
+                    Timeout::new(TYPING_EXPIRY_MS, move || link.send_message(Msg::ExpireTyping)).forget();
+                }
+                true
+            }
+
+            Msg::ExpireTyping => {
+                let now = js_sys::Date::now() as u64;
+                let count = self.typing.len();
+                self.typing
+                    .retain(|typing| now - typing.last_activity < TYPING_EXPIRY_MS as u64);
+                self.typing.len() != count
+            }
+
+            Msg::VisibilityChanged => {
+                self.send_read_receipts();
+                false
+            }
+        }
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let content = match (&self.connection_step, &self.web_rtc_manager.borrow().get_state()) {
+            // Connection wizard steps
+            (ConnectionStep::Welcome, _) => self.render_welcome_step(ctx),
+            (ConnectionStep::ChooseRole, _) => self.render_choose_role_step(ctx),
+            (ConnectionStep::GeneratingCode, _) => self.render_generating_step(ctx),
+            (ConnectionStep::SharingCode, _) => self.render_sharing_step(ctx),
+            (ConnectionStep::WaitingForConnection, _) => self.render_waiting_step(ctx),
+            (ConnectionStep::WaitingForAnswer, _) => self.render_waiting_for_answer_step(ctx),
+            (ConnectionStep::SignalingRoom, _) => self.render_signaling_room_step(ctx),
+            
+            // Connected state - show chat interface
+            (ConnectionStep::Connected, _) if self.is_chat_enabled() => self.render_chat_interface(ctx),
+            
+            // Fallback to connection progress for other states
+            _ => {
+                html! {
+                    <>
+                        { self.get_chat_header(ctx) }
+                        <main class="flex-1 overflow-y-auto p-4 bg-gray-50 flex items-center justify-center" id="chat-main" ref={self.node_ref.clone()}>
+                            <div class="text-center">
+                                <div class="animate-spin rounded-full h-12 w-12 border-b-2 border-blue-500 mx-auto mb-4"></div>
+                                <p class="text-gray-600">{"Establishing connection..."}</p>
+                            </div>
+                        </main>
+                        { self.get_input_for_chat_message(ctx) }
+                    </>
+                }
+            }
+        };
+
+        html! {
+            <>
                 <div class="flex flex-col justify-between w-full max-w-4xl mx-auto my-6 h-[calc(100vh-100px)] border-2 border-gray-300 rounded-lg bg-white shadow-xl">
                     { content }
                 </div>
-                
-                // QR Code Modal
-                if self.show_qr_modal {
-                    { self.render_qr_modal(ctx) }
+                
+                // QR Code Modal
+                if self.show_qr_modal {
+                    { self.render_qr_modal(ctx) }
+                }
+
+                if self._stats_poll.is_some() {
+                    { self.render_diagnostics_panel(ctx) }
+                }
+
+                { self.render_toasts(ctx) }
+
+                if self.show_history {
+                    <ChatHistory on_close={ctx.link().callback(|_| Msg::CloseHistory)} />
+                }
+
+                if self.show_qr_scanner {
+                    <QrScanner
+                        on_decoded={ctx.link().callback(Msg::QrCodeScanned)}
+                        on_close={ctx.link().callback(|_| Msg::CloseQrScanner)}
+                    />
+                }
+            </>
+        }
+    }
+
+    fn rendered(&mut self, ctx: &Context<Self>, _first_render: bool) {
+        self.sync_step_timeout(ctx);
+    }
+}
+
+impl<T: NetworkManager + 'static> ChatModel<T> {
+    fn start_connection(&self, state: State) -> Result<(), WebRtcError> {
+        {
+            let mut web_rtc_manager = self.web_rtc_manager.borrow_mut();
+            web_rtc_manager.set_state(state);
+            web_rtc_manager.set_ice_settings(self.ice_settings.clone());
+        }
+
+        T::start_web_rtc(self.web_rtc_manager.clone())
+    }
+
+    // The same failure often arrives once per frame; show it once.
+    fn show_toast(&mut self, ctx: &Context<Self>, text: String) {
+        if self.toasts.iter().any(|toast| toast.text == text) {
+            return;
+        }
+
+        let id = self.next_toast_id;
+        self.next_toast_id += 1;
+        self.toasts.push(Toast { id, text });
+
+        let link = ctx.link().clone();
+        Timeout::new(TOAST_MS, move || link.send_message(Msg::DismissToast(id))).forget();
+    }
+
+    // Announces an outgoing transfer, adds its bubble and starts sending chunks.
+    fn start_transfer(&mut self, ctx: &Context<Self>, transfer: FileTransfer) {
+        self.broadcast(&transfer.manifest());
+        self.add_message(Message::with_attachment(&transfer, MessageSender::Me));
+
+        ctx.link().send_message(Msg::SendNextChunk(transfer.id.clone()));
+        self.transfers.push(transfer);
+        self.scroll_top();
+    }
+
+    fn add_message(&mut self, message: Message) {
+        self.save_messages(std::slice::from_ref(&message));
+        self.messages.push(message);
+    }
+
+    // Changes one message in place and saves it if `change` reports a change.
+    fn update_message(&mut self, message_id: &str, change: impl FnOnce(&mut Message) -> bool) -> bool {
+        let Some(message) = self.messages.iter_mut().find(|message| message.id == message_id) else {
+            return false;
+        };
+        if !change(message) {
+            return false;
+        }
+
+        let message = message.clone();
+        self.save_messages(std::slice::from_ref(&message));
+        true
+    }
+
+    fn save_messages(&self, messages: &[Message]) {
+        let Some(conversation_id) = self.conversation_id.clone() else {
+            return;
+        };
+
+        let conversation = Conversation {
+            title: self.conversation_title(&conversation_id),
+            id: conversation_id.clone(),
+            last_activity: js_sys::Date::now() as u64,
+        };
+        let stored: Vec<StoredMessage> = messages
+            .iter()
+            .map(|message| message.to_stored(&conversation_id))
+            .collect();
+
+        spawn_local(async move {
+            if let Err(err) = history::save(&conversation, &stored).await {
+                console::log_2(&"Failed to save chat history".into(), &err);
+            }
+        });
+    }
+
+    fn conversation_title(&self, conversation_id: &str) -> String {
+        self.peers
+            .iter()
+            .find(|peer| peer.identity.as_deref() == Some(conversation_id))
+            .and_then(|peer| {
+                self.participants()
+                    .into_iter()
+                    .find(|participant| self.peer_for_participant(participant).map(|p| p.id) == Some(peer.id))
+            })
+            .map(|participant| participant.nickname)
+            .unwrap_or_else(|| "Conversation".to_string())
+    }
+
+    // The host made the offers, by code or through a signaling room. The
+    // pairing manager alone is not enough: `ResetWebRTC` replaces it while
+    // the peers it already connected stay in the room.
+    fn is_host(&self) -> bool {
+        let is_server = |manager: &Rc<RefCell<T>>| matches!(manager.borrow().get_state(), State::Server(_));
+        is_server(&self.web_rtc_manager)
+            || self.peers.iter().any(|peer| is_server(&peer.manager))
+            || self.signaling.as_ref().is_some_and(|session| session.is_host)
+    }
+
+    // Starts a fresh connection for the wizard, leaving connected peers alone.
+    fn new_pairing_manager(&mut self, ctx: &Context<Self>) {
+        self.pairing_peer_id = self.next_peer_id;
+        self.next_peer_id += 1;
+        self.web_rtc_manager = T::new(ctx.link(), self.pairing_peer_id);
+    }
+
+    fn broadcast(&self, frame: &Frame) {
+        for peer in self.peers.iter().filter(|peer| peer.is_open()) {
+            peer.send(frame);
+        }
+    }
+
+    // Forwards a frame from one peer to everyone else in the room.
+    fn relay(&self, from: PeerId, frame: &Frame) {
+        for peer in self.peers.iter().filter(|peer| peer.id != from && peer.is_open()) {
+            peer.send(frame);
+        }
+    }
+
+    fn send_to(&self, peer_id: PeerId, frame: &Frame) {
+        if let Some(peer) = self.peers.iter().find(|peer| peer.id == peer_id) {
+            peer.send(frame);
+        }
+    }
+
+    // The slowest open link holds back bulk data for everyone.
+    fn is_send_queue_full(&self) -> bool {
+        self.peers
+            .iter()
+            .any(|peer| peer.is_open() && peer.queued_frames() >= MAX_QUEUED_FRAMES)
+    }
+
+    // Calls are one-to-one, so they are offered only with a single peer.
+    fn call_peer(&self) -> Option<PeerId> {
+        match self.peers.as_slice() {
+            [peer] if peer.is_open() => Some(peer.id),
+            _ => None,
+        }
+    }
+
+    fn receive_call_action(&mut self, peer_id: PeerId, action: CallAction) -> bool {
+        match action {
+            CallAction::Invite { video } => {
+                if self.call.is_some() {
+                    self.send_to(peer_id, &Frame::Call { action: CallAction::Decline });
+                    return false;
+                }
+                self.call = Some(Call::new(peer_id, CallPhase::Incoming, video));
+            }
+            CallAction::Accept => {
+                let Some(call) = self.call.as_mut().filter(|call| call.peer_id == peer_id) else {
+                    return false;
+                };
+                call.phase = CallPhase::Active;
+
+                // Media sections exist from an earlier call, so nothing
+                // will be renegotiated and no remote media will announce it
+                let has_remote_media = self
+                    .peers
+                    .iter()
+                    .any(|peer| peer.id == peer_id && peer.remote_stream.is_some());
+                if has_remote_media && !call.sending {
+                    self.attach_call_media();
+                }
+            }
+            CallAction::Decline | CallAction::HangUp => {
+                if self.call.as_ref().is_none_or(|call| call.peer_id != peer_id) {
+                    return false;
+                }
+                self.end_call(false);
+            }
+            CallAction::MediaState { muted, camera_off } => {
+                let Some(call) = self.call.as_mut().filter(|call| call.peer_id == peer_id) else {
+                    return false;
+                };
+                call.remote_muted = muted;
+                call.remote_camera_off = camera_off;
+            }
+        }
+        true
+    }
+
+    // Sends our microphone and camera (or screen) to the peer we call.
+    fn attach_call_media(&mut self) {
+        let Some(call) = self.call.as_mut() else {
+            return;
+        };
+        let Some(peer) = self.peers.iter().find(|peer| peer.id == call.peer_id) else {
+            return;
+        };
+
+        let result = call
+            .outgoing_stream()
+            .map_err(WebRtcError::from)
+            .and_then(|stream| T::set_local_media(peer.manager.clone(), Some(stream)));
+        match result {
+            Ok(()) => call.sending = true,
+            Err(err) => console::log_1(&format!("Failed to send call media: {}", err).into()),
+        }
+    }
+
+    fn send_media_state(&self) {
+        if let Some(call) = &self.call {
+            let action = CallAction::MediaState { muted: call.muted, camera_off: call.camera_off };
+            self.send_to(call.peer_id, &Frame::Call { action });
+        }
+    }
+
+    fn end_call(&mut self, notify_peer: bool) {
+        let Some(mut call) = self.call.take() else {
+            return;
+        };
+        if notify_peer && call.is_announced() {
+            let action = match call.phase {
+                CallPhase::Incoming => CallAction::Decline,
+                _ => CallAction::HangUp,
+            };
+            self.send_to(call.peer_id, &Frame::Call { action });
+        }
+        if call.sending
+            && let Some(peer) = self.peers.iter().find(|peer| peer.id == call.peer_id)
+            && let Err(err) = T::set_local_media(peer.manager.clone(), None)
+        {
+            console::log_1(&format!("Failed to stop call media: {}", err).into());
+        }
+        call.stop();
+    }
+
+    fn stop_typing(&mut self) {
+        self._typing_idle = None;
+        if self.last_typing_time != 0 {
+            self.last_typing_time = 0;
+            self.broadcast(&Frame::Typing { is_typing: false, author: None });
+        }
+    }
+
+    // Messages count as read once the chat is on screen in a visible tab.
+    fn send_read_receipts(&mut self) {
+        if gloo_utils::document().hidden() || !matches!(self.connection_step, ConnectionStep::Connected) {
+            return;
+        }
+
+        for (peer_id, message_id) in std::mem::take(&mut self.unread) {
+            if let Some(peer) = self.peers.iter().find(|peer| peer.id == peer_id) {
+                peer.send(&Frame::Receipt { message_id, kind: ReceiptKind::Read });
+            }
+        }
+    }
+
+    // Upgrades the tick on our own message, or, on the host, passes a guest's
+    // receipt on to whoever wrote the message.
+    fn accept_receipt(&mut self, from: PeerId, message_id: String, kind: ReceiptKind) {
+        let is_host = self.is_host();
+        let Some(message) = self.messages.iter_mut().find(|message| message.id == message_id) else {
+            return;
+        };
+
+        match message.sender {
+            MessageSender::Me => message.receipt = message.receipt.max(Some(kind)),
+            MessageSender::Other if is_host => {
+                let author = message.author.clone();
+                let peer = self
+                    .peers
+                    .iter()
+                    .find(|peer| peer.id != from && Some(room::participant_id(peer.id)) == author);
+                if let Some(peer) = peer {
+                    peer.send(&Frame::Receipt { message_id, kind });
+                }
+            }
+            MessageSender::Other => {}
+        }
+    }
+
+    fn typing_label(&self) -> Option<String> {
+        let names: Vec<String> = self
+            .typing
+            .iter()
+            .map(|typing| {
+                self.roster
+                    .iter()
+                    .find(|participant| participant.id == typing.author)
+                    .map(|participant| participant.nickname.clone())
+                    .unwrap_or_else(|| "Friend".to_string())
+            })
+            .collect();
+
+        match names.as_slice() {
+            [] => None,
+            [name] => Some(format!("{} is typing…", name)),
+            names => Some(format!("{} are typing…", names.join(", "))),
+        }
+    }
+
+    // How the others in the room refer to us, see `participant_id_of`.
+    fn my_reactor_id(&self) -> String {
+        if self.is_host() {
+            HOST_PARTICIPANT_ID.to_string()
+        } else {
+            self.my_participant_id.clone().unwrap_or_default()
+        }
+    }
+
+    fn participant_id_of(&self, peer_id: PeerId) -> String {
+        if self.is_host() {
+            room::participant_id(peer_id)
+        } else {
+            HOST_PARTICIPANT_ID.to_string()
+        }
+    }
+
+    // Who sent a frame that arrived from `peer_id`. The host knows that
+    // from the link and ignores what a guest claims; a guest only hears
+    // from the host, which relays other guests' frames with their author.
+    fn author_of(&self, peer_id: PeerId, claimed: Option<String>) -> String {
+        if self.is_host() {
+            room::participant_id(peer_id)
+        } else {
+            claimed.unwrap_or_else(|| HOST_PARTICIPANT_ID.to_string())
+        }
+    }
+
+    // A guest that pairs again gets a new participant id from the host, so
+    // its earlier messages and reactions move over to the new id; otherwise
+    // it could no longer edit or delete what it sent, while its own side
+    // still can. Returns the messages that changed.
+    fn adopt_returning_authors(&mut self) -> Vec<Message> {
+        if !self.is_host() {
+            return vec![];
+        }
+
+        let present: Vec<String> = self.peers.iter().map(|peer| room::participant_id(peer.id)).collect();
+        let renamed: BTreeMap<String, String> = self
+            .known_authors
+            .iter()
+            .filter(|(old, _)| !present.contains(old))
+            .filter_map(|(old, identity)| {
+                let mut returning = self.peers.iter().filter(|peer| peer.identity.as_ref() == Some(identity));
+                match (returning.next(), returning.next()) {
+                    (Some(peer), None) => Some((old.clone(), room::participant_id(peer.id))),
+                    _ => None,
+                }
+            })
+            .collect();
+        if renamed.is_empty() {
+            return vec![];
+        }
+
+        let mut adopted = vec![];
+        for message in &mut self.messages {
+            let mut changed = false;
+            if let Some(new) = message.author.as_ref().and_then(|author| renamed.get(author)) {
+                message.author = Some(new.clone());
+                changed = true;
+            }
+            for reactors in message.reactions.values_mut() {
+                for (old, new) in &renamed {
+                    if reactors.remove(old) {
+                        reactors.insert(new.clone());
+                        changed = true;
+                    }
+                }
+            }
+            if changed {
+                adopted.push(message.clone());
+            }
+        }
+        adopted
+    }
+
+    // Adds the pairing connection to the room once its channel opens and
+    // drops peers whose channel closed, then updates reconnection.
+    fn track_peer(&mut self, ctx: &Context<Self>, peer_id: PeerId, web_rtc_state: &State) {
+        let data_channel_state = match web_rtc_state {
+            State::Server(connection_state) | State::Client(connection_state) => {
+                connection_state.data_channel_state
+            }
+            State::Default => return,
+        };
+        let is_known = self.peers.iter().any(|peer| peer.id == peer_id);
+        let connecting = if is_known { None } else { self.connecting_manager(peer_id) };
+
+        match (data_channel_state, connecting) {
+            (Some(RtcDataChannelState::Open), Some(manager)) => {
+                let sender = manager.clone();
+                let link = ctx.link().clone();
+                let secure = SecureChannel::start(
+                    move |frame, priority| {
+                        if let Err(error) = sender.borrow().send_message(&protocol::encode(frame), priority) {
+                            link.send_message(Msg::ConnectionError(peer_id, error));
+                        }
+                    },
+                    ctx.link().callback(move |frame| Msg::ReceiveSecureFrame(peer_id, frame)),
+                    ctx.link().callback(move |_| Msg::SecureSessionReady(peer_id)),
+                    ctx.link().callback(move |_| Msg::SendQueueDrained(peer_id)),
+                );
+                let peer = RoomPeer::new(peer_id, manager, secure);
+                peer.send(&Frame::Identity { identity: self.local_identity.clone() });
+
+                if !self.is_host() {
+                    let nickname =
+                        room::clean_nickname(&self.nickname).unwrap_or_else(room::random_nickname);
+                    peer.send(&Frame::Hello { nickname });
+                }
+                // Ops the peer already has are ignored. The host sends the whole
+                // board with each op's author; a guest only sends back its own
+                // ops, which the host credits to this link.
+                let me = self.my_reactor_id();
+                let is_host = self.is_host();
+                for (author, op) in self.board.replay() {
+                    if is_host {
+                        peer.send(&Frame::Board { op, author: Some(author) });
+                    } else if author == me {
+                        peer.send(&Frame::Board { op, author: None });
+                    }
+                }
+                self.peers.push(peer);
+
+                if matches!(self.connection_step, ConnectionStep::SignalingRoom) {
+                    self.connection_step = ConnectionStep::Connected;
+                }
+            }
+            // A closed data channel cannot be restarted, only re-paired
+            (Some(RtcDataChannelState::Closed), _) if is_known => {
+                if self.call.as_ref().is_some_and(|call| call.peer_id == peer_id) {
+                    self.end_call(false);
+                }
+                self.peers.retain(|peer| peer.id != peer_id);
+                if self.peers.is_empty() {
+                    self.set_reconnect(ctx, Some(Reconnect::GaveUp));
+                }
+                // Transfers it held back can go on to the others
+                ctx.link().send_message(Msg::SendQueueDrained(peer_id));
+            }
+            _ => {}
+        }
+
+        self.refresh_roster();
+        self.track_reconnection(ctx);
+    }
+
+    // The connection behind `peer_id` while it is still being set up, by the
+    // wizard or through the signaling room.
+    fn connecting_manager(&self, peer_id: PeerId) -> Option<Rc<RefCell<T>>> {
+        if peer_id == self.pairing_peer_id {
+            return Some(self.web_rtc_manager.clone());
+        }
+        self.signaling
+            .as_ref()
+            .and_then(|session| session.by_peer_id(peer_id))
+            .map(|connection| connection.manager.clone())
+    }
+
+    // The host offers a connection to everyone in the room, guests answer
+    // the offers they receive. Connections stay listed after they open so
+    // late candidates still reach them.
+    fn receive_signaling_event(&mut self, ctx: &Context<Self>, event: SignalingEvent) -> bool {
+        let Some(session) = &mut self.signaling else {
+            return false;
+        };
+        let is_host = session.is_host;
+
+        match event {
+            SignalingEvent::Welcome { peers } if is_host => {
+                for remote_id in peers {
+                    self.open_signaled_connection(ctx, remote_id, State::Server(ConnectionState::new()));
+                }
+            }
+            SignalingEvent::PeerJoined(remote_id) if is_host => {
+                self.open_signaled_connection(ctx, remote_id, State::Server(ConnectionState::new()));
+            }
+            SignalingEvent::Welcome { .. } | SignalingEvent::PeerJoined(_) => {}
+            SignalingEvent::PeerLeft(remote_id) => {
+                session.connections.retain(|connection| connection.remote_id != remote_id);
+            }
+            SignalingEvent::Signal { from, data } => {
+                let manager = match session.by_remote_id(from) {
+                    Some(connection) => connection.manager.clone(),
+                    None if !is_host && matches!(data, SignalData::Description { .. }) => {
+                        self.open_signaled_connection(ctx, from, State::Client(ConnectionState::new()))
+                    }
+                    None => return false,
+                };
+                T::receive_signal(manager, data);
+            }
+            SignalingEvent::Error(message) => {
+                self.signaling_error = Some(message);
+            }
+            SignalingEvent::Closed(reason) => {
+                self.signaling = None;
+                if matches!(self.connection_step, ConnectionStep::SignalingRoom) {
+                    self.signaling_error = Some(reason);
+                    self.connection_step = ConnectionStep::ChooseRole;
+                }
+            }
+        }
+        true
+    }
+
+    fn open_signaled_connection(&mut self, ctx: &Context<Self>, remote_id: ClientId, state: State) -> Rc<RefCell<T>> {
+        let peer_id = self.next_peer_id;
+        self.next_peer_id += 1;
+
+        let manager = T::new(ctx.link(), peer_id);
+        {
+            let mut manager = manager.borrow_mut();
+            manager.set_state(state);
+            manager.set_ice_settings(self.ice_settings.clone());
+            manager.use_signaling(ctx.link().callback(move |data| Msg::SendSignal(remote_id, data)));
+        }
+        if let Err(err) = T::start_web_rtc(manager.clone()) {
+            console::log_1(&format!("Failed to start a signaled connection: {}", err).into());
+        }
+
+        if let Some(session) = &mut self.signaling {
+            session.connections.push(SignaledConnection { remote_id, peer_id, manager: manager.clone() });
+        }
+        manager
+    }
+
+    // Starts or clears the automatic reconnection as ICE drops and recovers
+    // on any peer.
+    fn track_reconnection(&mut self, ctx: &Context<Self>) {
+        if self.peers.is_empty() {
+            return;
+        }
+
+        let is_dropped = self.peers.iter().any(RoomPeer::is_ice_dropped);
+        match (&self.reconnect, is_dropped) {
+            (None, true) => self.set_reconnect(ctx, Some(Reconnect::start(js_sys::Date::now()))),
+            (Some(_), false) => {
+                console::log_1(&"Connection recovered".into());
+                self.set_reconnect(ctx, None);
+            }
+            _ => {}
+        }
+    }
+
+    // The host owns the roster: it lists itself, every peer, and anyone who
+    // left, and sends it to each guest whenever it changes.
+    fn refresh_roster(&mut self) {
+        if !self.is_host() {
+            return;
+        }
+
+        let mut roster = vec![Participant {
+            id: HOST_PARTICIPANT_ID.to_string(),
+            nickname: room::clean_nickname(&self.nickname).unwrap_or_else(|| "Host".to_string()),
+            color: room::participant_color(0).to_string(),
+            status: ParticipantStatus::Connected,
+        }];
+        roster.extend(self.peers.iter().map(RoomPeer::to_participant));
+
+        for participant in &self.roster {
+            if !roster.iter().any(|known| known.id == participant.id) {
+                roster.push(Participant {
+                    status: ParticipantStatus::Left,
+                    ..participant.clone()
+                });
+            }
+        }
+
+        if roster == self.roster {
+            return;
+        }
+        self.roster = roster;
+
+        for peer in self.peers.iter().filter(|peer| peer.is_open()) {
+            peer.send(&Frame::Roster {
+                you: room::participant_id(peer.id),
+                participants: self.roster.clone(),
+            });
+        }
+    }
+
+    // Guests see the host's status through their own link to it.
+    fn participants(&self) -> Vec<Participant> {
+        let host_status = self
+            .peers
+            .first()
+            .map(|peer| ParticipantStatus::from_state(&peer.state()))
+            .unwrap_or(ParticipantStatus::Left);
+
+        self.roster
+            .iter()
+            .map(|participant| match participant.id.as_str() {
+                HOST_PARTICIPANT_ID if !self.is_host() => Participant {
+                    status: host_status,
+                    ..participant.clone()
+                },
+                _ => participant.clone(),
+            })
+            .collect()
+    }
+
+    fn peer_for_participant(&self, participant: &Participant) -> Option<&RoomPeer<T>> {
+        if self.is_host() {
+            self.peers
+                .iter()
+                .find(|peer| room::participant_id(peer.id) == participant.id)
+        } else if participant.id == HOST_PARTICIPANT_ID {
+            self.peers.first()
+        } else {
+            None
+        }
+    }
+
+    fn render_security_status(&self, ctx: &Context<Self>) -> Html {
+        match self.peers.as_slice() {
+            [] => html! {},
+            [peer] => match (peer.secure.safety_number(), peer.verified) {
+                (None, _) => html! {
+                    <div class="text-sm text-gray-500">{"🔐 Securing…"}</div>
+                },
+                (Some(number), true) => html! {
+                    <div
+                        class="bg-green-100 text-green-800 text-sm px-3 py-1 rounded-full"
+                        title={format!("Link to {} verified: {}", self.peer_name(peer.id), number)}
+                    >
+                        {"🔒 Link verified"}
+                    </div>
+                },
+                (Some(number), false) => {
+                    let peer_id = peer.id;
+                    html! {
+                        <div
+                            class="flex items-center space-x-2 bg-yellow-100 text-yellow-800 text-sm px-3 py-1 rounded-full"
+                            title="Safety number of this link; frames are encrypted hop by hop"
+                        >
+                            <span>{"🔓 Link not verified"}</span>
+                            <span class="font-mono">{ number }</span>
+                            <button
+                                class="bg-yellow-500 hover:bg-yellow-600 text-white text-xs font-bold py-1 px-2 rounded transition-colors"
+                                title="Only mark the link as verified once your friend reads you the same number"
+                                onclick={ctx.link().callback(move |_| Msg::MarkVerified(peer_id))}
+                            >
+                                {"Mark verified"}
+                            </button>
+                        </div>
+                    }
+                }
+            },
+            peers => {
+                let verified = peers.iter().filter(|peer| peer.verified).count();
+                html! {
+                    <div
+                        class="bg-gray-100 text-gray-700 text-sm px-3 py-1 rounded-full"
+                        title="Each guest has its own link to you; you relay between them"
+                    >
+                        { format!("🔒 {}/{} links verified", verified, peers.len()) }
+                    </div>
+                }
+            }
+        }
+    }
+
+    fn render_participant_safety_number(&self, ctx: &Context<Self>, participant: &Participant) -> Html {
+        let Some(peer) = self.peer_for_participant(participant) else {
+            // Other guests are only reached through the host, which can read what it relays
+            if self.is_me(participant) {
+                return html! {};
+            }
+            return html! {
+                <div class="text-xs text-gray-400" title="Sealed only for each link; the host relays it">
+                    {"via the host's link"}
+                </div>
+            };
+        };
+        let Some(number) = peer.secure.safety_number() else {
+            return html! {};
+        };
+        let peer_id = peer.id;
+
+        html! {
+            <div
+                class="text-xs text-gray-500"
+                title="Safety number of your link to them; frames are encrypted hop by hop"
+            >
+                <span class="font-mono">{ number }</span>
+                if peer.verified {
+                    <span class="text-green-600">{" ✓"}</span>
+                } else {
+                    <button
+                        class="ml-1 text-blue-600 hover:underline"
+                        onclick={ctx.link().callback(move |_| Msg::MarkVerified(peer_id))}
+                    >
+                        {"verify"}
+                    </button>
+                }
+            </div>
+        }
+    }
+
+    fn is_me(&self, participant: &Participant) -> bool {
+        if self.is_host() {
+            participant.id == HOST_PARTICIPANT_ID
+        } else {
+            self.my_participant_id.as_deref() == Some(participant.id.as_str())
+        }
+    }
+
+    fn set_reconnect(&mut self, ctx: &Context<Self>, reconnect: Option<Reconnect>) {
+        self._reconnect_timeout = None;
+        self._reconnect_countdown = None;
+
+        if let Some(Reconnect::Waiting { retry_at, .. }) = &reconnect {
+            let delay = (retry_at - js_sys::Date::now()).max(0.0) as u32;
+            let link = ctx.link().clone();
+            self._reconnect_timeout = Some(Timeout::new(delay, move || {
+                link.send_message(Msg::ReconnectAttempt)
+            }));
+
+            let link = ctx.link().clone();
+            self._reconnect_countdown = Some(Interval::new(1_000, move || {
+                link.send_message(Msg::ReconnectTick)
+            }));
+        }
+
+        self.reconnect = reconnect;
+    }
+
+    // What the wizard is waiting on right now, judged from the pairing connection.
+    fn expected_wait(&self) -> Option<WaitKind> {
+        let (is_host, has_code, ice_checking) = match self.web_rtc_manager.borrow().get_state() {
+            State::Server(connection_state) => (true, self.connection_code.is_some(), is_ice_checking(&connection_state)),
+            State::Client(connection_state) => (
+                false,
+                self.web_rtc_manager.borrow().get_offer().is_some(),
+                is_ice_checking(&connection_state),
+            ),
+            State::Default => (false, false, false),
+        };
+
+        expected_wait(&self.connection_step, is_host, has_code, ice_checking)
+    }
+
+    // Starts, moves or clears the step deadline as the wizard moves along.
+    fn sync_step_timeout(&mut self, ctx: &Context<Self>) {
+        let now = js_sys::Date::now();
+        let step_timeout = match (&self.step_timeout, self.expected_wait()) {
+            (_, None) => None,
+            (None, Some(kind)) => Some(StepTimeout::start(kind, now)),
+            (Some(step_timeout), Some(kind)) => Some(step_timeout.follow(kind, now)),
+        };
+        if step_timeout == self.step_timeout {
+            return;
+        }
+
+        if step_timeout.is_none() {
+            self._step_tick = None;
+        } else if self._step_tick.is_none() {
+            let link = ctx.link().clone();
+            self._step_tick = Some(Interval::new(1_000, move || link.send_message(Msg::StepTick)));
+        }
+        self.step_timeout = step_timeout;
+    }
+
+    // A host replaces a stalled or stale offer with a fresh one. A guest
+    // answers the same offer again, or asks for a new code once the host
+    // has not used its answer in time.
+    fn retry_step(&mut self, ctx: &Context<Self>, kind: WaitKind) {
+        let is_host = matches!(self.web_rtc_manager.borrow().get_state(), State::Server(_));
+        self.new_pairing_manager(ctx);
+        self.connection_code = None;
+        self.value = "".into();
+
+        if is_host {
+            if kind == WaitKind::Answer {
+                self.show_toast(ctx, "The connection code expired, so a new one was generated. Share the new code.".to_string());
+            }
+            ctx.link().send_message(Msg::ChooseHost);
+            return;
+        }
+
+        let answered = match kind {
+            WaitKind::Gathering => self
+                .start_connection(State::Client(ConnectionState::new()))
+                .and_then(|()| T::validate_offer(self.web_rtc_manager.clone(), &self.pasted_offer)),
+            WaitKind::Answer | WaitKind::Connecting => {
+                self.show_toast(ctx, "Your friend did not use your answer in time. Ask them for a new connection code.".to_string());
+                self.connection_step = ConnectionStep::WaitingForConnection;
+                return;
+            }
+        };
+        if let Err(err) = answered {
+            self.show_toast(ctx, err.to_string());
+            self.connection_step = ConnectionStep::WaitingForConnection;
+        }
+    }
+
+    fn render_step_timeout(&self, ctx: &Context<Self>) -> Html {
+        let now = js_sys::Date::now();
+        let is_host = matches!(self.web_rtc_manager.borrow().get_state(), State::Server(_));
+        let countdown = |seconds: u32| {
+            if seconds >= 60 {
+                format!("{}:{:02}", seconds / 60, seconds % 60)
+            } else {
+                format!("{}s", seconds)
+            }
+        };
+
+        match &self.step_timeout {
+            Some(step_timeout @ StepTimeout::Waiting { kind, .. }) if step_timeout.is_still_waiting(now) => {
+                let hint = match (kind, is_host) {
+                    (WaitKind::Gathering, _) => "Still gathering connection info. A firewall may be blocking the STUN servers; a TURN server in the ICE settings can help.",
+                    (WaitKind::Answer, true) => "Still waiting for your friend's answer code. Make sure they received the complete code.",
+                    (WaitKind::Answer, false) => "Still waiting for your friend to paste your answer.",
+                    (WaitKind::Connecting, _) => "Still connecting. Peers on different networks may need a TURN relay.",
+                };
+                let deadline = match kind {
+                    WaitKind::Answer if is_host => "This code expires in",
+                    _ => "Trying again in",
+                };
+
+                html! {
+                    <div class="mt-6 p-4 bg-yellow-50 border border-yellow-200 rounded-lg text-sm text-yellow-800 text-left">
+                        <p>{ hint }</p>
+                        <p class="mt-1 text-xs text-yellow-700">
+                            { format!("{} {}", deadline, countdown(step_timeout.seconds_left(now))) }
+                        </p>
+                    </div>
                 }
-            </>
+            }
+            Some(step_timeout @ StepTimeout::Retrying { attempt, .. }) => html! {
+                <div class="mt-6 p-4 bg-yellow-50 border border-yellow-200 rounded-lg text-sm text-yellow-800 flex items-center justify-between">
+                    <span>
+                        { format!(
+                            "Timed out. Retrying in {} (attempt {}/{})",
+                            countdown(step_timeout.seconds_left(now)),
+                            attempt + 2,
+                            MAX_STEP_ATTEMPTS,
+                        ) }
+                    </span>
+                    <button
+                        class="bg-yellow-500 hover:bg-yellow-600 text-white text-xs font-bold py-1 px-2 rounded transition-colors"
+                        onclick={ctx.link().callback(|_| Msg::RetryStep)}
+                    >
+                        {"Retry now"}
+                    </button>
+                </div>
+            },
+            Some(StepTimeout::GaveUp { .. }) => html! {
+                <div class="mt-6 p-4 bg-red-50 border border-red-200 rounded-lg text-sm text-red-800 flex items-center justify-between">
+                    <span>{ format!("Could not connect after {} attempts.", MAX_STEP_ATTEMPTS) }</span>
+                    <button
+                        class="bg-red-500 hover:bg-red-600 text-white text-xs font-bold py-1 px-2 rounded transition-colors"
+                        onclick={ctx.link().callback(|_| Msg::RetryStep)}
+                    >
+                        {"Try again"}
+                    </button>
+                </div>
+            },
+            _ => html! {},
         }
     }
-}
 
-impl<T: NetworkManager + 'static> ChatModel<T> {
-    // Helper method to check if data channel is open
-    fn is_data_channel_open(&self, connection_state: &ConnectionState) -> bool {
-        connection_state.data_channel_state.is_some() &&
-        connection_state.data_channel_state.unwrap() == RtcDataChannelState::Open
+    fn render_send_queue_status(&self) -> Html {
+        let queued: usize = self.peers.iter().map(RoomPeer::queued_frames).sum();
+        if queued == 0 {
+            return html! {};
+        }
+
+        html! {
+            <div
+                class="flex items-center space-x-1 bg-blue-100 text-blue-800 text-sm px-3 py-1 rounded-full"
+                title="Frames waiting for the connection to catch up. Typing and receipts still go first."
+            >
+                <span>{"📤"}</span>
+                <span>{ format!("{} queued", queued) }</span>
+            </div>
+        }
+    }
+
+    fn render_reconnect_status(&self, ctx: &Context<Self>) -> Html {
+        let is_host = matches!(self.web_rtc_manager.borrow().get_state(), State::Server(_));
+
+        match &self.reconnect {
+            None => html! {},
+            Some(reconnect @ Reconnect::Waiting { attempt, .. }) => {
+                let seconds = reconnect.seconds_until_retry(js_sys::Date::now());
+                let detail = if is_host {
+                    format!("attempt {}/{} · next try in {}s", attempt + 1, MAX_RECONNECT_ATTEMPTS, seconds)
+                } else {
+                    format!("waiting for host · {}/{}", attempt + 1, MAX_RECONNECT_ATTEMPTS)
+                };
+
+                html! {
+                    <div class="flex items-center space-x-2 bg-yellow-100 text-yellow-800 text-sm px-3 py-1 rounded-full">
+                        <span class="animate-spin">{"🔄"}</span>
+                        <span>{ format!("Reconnecting… {}", detail) }</span>
+                    </div>
+                }
+            }
+            Some(Reconnect::GaveUp) => html! {
+                <div class="flex items-center space-x-2 bg-red-100 text-red-800 text-sm px-3 py-1 rounded-full">
+                    <span>{"⚠️ Connection lost"}</span>
+                    <button
+                        class="bg-red-500 hover:bg-red-600 text-white text-xs font-bold py-1 px-2 rounded transition-colors"
+                        onclick={ctx.link().callback(|_| Msg::RePair)}
+                    >
+                        {"Re-pair (keeps chat)"}
+                    </button>
+                </div>
+            },
+        }
     }
 
     // Connection Wizard Steps
@@ -466,7 +2698,7 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                         <div class="space-y-4">
                             <div class="flex items-center justify-center space-x-2 text-sm text-gray-500">
                                 <span class="w-2 h-2 bg-green-400 rounded-full"></span>
-                                <span>{"End-to-end encrypted"}</span>
+                                <span>{"Encrypted hop by hop, on every link"}</span>
                             </div>
                             <div class="flex items-center justify-center space-x-2 text-sm text-gray-500">
                                 <span class="w-2 h-2 bg-blue-400 rounded-full"></span>
@@ -501,6 +2733,17 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                             <p class="text-gray-600">{"How would you like to start the conversation?"}</p>
                         </div>
 
+                        <div class="mb-6">
+                            <label class="block text-sm font-medium text-gray-700 mb-1">{"Your name"}</label>
+                            <input
+                                type="text"
+                                class="w-full border border-gray-300 rounded-lg px-3 py-2 text-sm focus:outline-none focus:ring-2 focus:ring-blue-500"
+                                maxlength="32"
+                                value={self.nickname.clone()}
+                                oninput={ctx.link().callback(|e: InputEvent| Msg::UpdateNickname(e.target_unchecked_into::<HtmlInputElement>().value()))}
+                            />
+                        </div>
+
                         <div class="grid md:grid-cols-2 gap-6">
                             // Host option
                             <div class="border-2 border-gray-200 hover:border-blue-400 rounded-xl p-6 transition-colors cursor-pointer group"
@@ -529,6 +2772,21 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                             </div>
                         </div>
 
+                        { self.render_signaling_options(ctx) }
+
+                        <div class="mt-6 text-center">
+                            <button
+                                class="text-sm text-gray-600 hover:text-gray-800 underline"
+                                onclick={ctx.link().callback(|_| Msg::ToggleIceSettings)}
+                            >
+                                { if self.show_ice_settings { "Hide connection settings" } else { "⚙️ Connection settings" } }
+                            </button>
+                        </div>
+
+                        if self.show_ice_settings {
+                            { self.render_ice_settings_panel(ctx) }
+                        }
+
                         <button
                             class="w-full mt-6 bg-gray-300 hover:bg-gray-400 text-gray-700 font-medium py-2 px-4 rounded-lg transition-colors"
                             onclick={ctx.link().callback(|_| Msg::SetConnectionStep(ConnectionStep::Welcome))}
@@ -541,6 +2799,184 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
         }
     }
 
+    fn render_signaling_options(&self, ctx: &Context<Self>) -> Html {
+        let input_class = "w-full border border-gray-300 rounded-lg px-3 py-2 text-sm font-mono focus:outline-none focus:ring-2 focus:ring-blue-500";
+        let can_join = self.ice_settings.signaling_url.is_some() && !self.signaling_room.trim().is_empty();
+
+        html! {
+            <div class="mt-6 border border-gray-200 rounded-xl p-4 space-y-3">
+                <div>
+                    <h3 class="font-semibold text-gray-800">{"Use a signaling server"}</h3>
+                    <p class="text-xs text-gray-500">{"Skip copying codes: both sides join the same room and connect automatically."}</p>
+                </div>
+                <div class="grid md:grid-cols-2 gap-3">
+                    <input
+                        type="text"
+                        class={input_class}
+                        placeholder="ws://localhost:9001"
+                        value={self.ice_settings.signaling_url.clone().unwrap_or_default()}
+                        oninput={ctx.link().callback(|e: InputEvent| Msg::UpdateSignalingUrl(e.target_unchecked_into::<HtmlInputElement>().value()))}
+                    />
+                    <input
+                        type="text"
+                        class={input_class}
+                        placeholder="Room name"
+                        maxlength="64"
+                        value={self.signaling_room.clone()}
+                        oninput={ctx.link().callback(|e: InputEvent| Msg::UpdateSignalingRoom(e.target_unchecked_into::<HtmlInputElement>().value()))}
+                    />
+                </div>
+                if let Some(error) = &self.signaling_error {
+                    <p class="text-sm text-red-600">{ error }</p>
+                }
+                <div class="flex space-x-3">
+                    <button
+                        class="flex-1 bg-blue-600 hover:bg-blue-700 disabled:bg-gray-400 disabled:cursor-not-allowed text-white font-medium py-2 px-4 rounded-lg transition-colors"
+                        disabled={!can_join}
+                        onclick={ctx.link().callback(|_| Msg::JoinSignalingRoom(true))}
+                    >
+                        {"🏠 Host room"}
+                    </button>
+                    <button
+                        class="flex-1 bg-green-600 hover:bg-green-700 disabled:bg-gray-400 disabled:cursor-not-allowed text-white font-medium py-2 px-4 rounded-lg transition-colors"
+                        disabled={!can_join}
+                        onclick={ctx.link().callback(|_| Msg::JoinSignalingRoom(false))}
+                    >
+                        {"🔗 Join room"}
+                    </button>
+                </div>
+            </div>
+        }
+    }
+
+    fn render_signaling_room_step(&self, ctx: &Context<Self>) -> Html {
+        let (room, is_host, connecting) = match &self.signaling {
+            Some(session) => (session.room.clone(), session.is_host, session.connections.len()),
+            None => (String::new(), false, 0),
+        };
+        let status = match (is_host, connecting) {
+            (true, 0) => "Waiting for someone to join this room...".to_string(),
+            (true, count) => format!("Connecting to {} guest(s)...", count),
+            (false, 0) => "Waiting for the host's offer...".to_string(),
+            (false, _) => "Connecting to the host...".to_string(),
+        };
+
+        html! {
+            <>
+                { self.get_chat_header(ctx) }
+                <main class="flex-1 overflow-y-auto p-6 bg-gradient-to-br from-blue-50 to-indigo-100">
+                    <div class="max-w-md mx-auto bg-white rounded-2xl shadow-xl p-8 text-center">
+                        <div class="text-4xl mb-4">{ if is_host { "🏠" } else { "🔗" } }</div>
+                        <h2 class="text-2xl font-bold text-gray-800 mb-2">{"Signaling room"}</h2>
+                        <p class="text-gray-600 mb-4">{"Ask the other side to join the room"}</p>
+                        <div class="bg-gray-100 rounded-lg p-3 font-mono text-lg text-gray-800 mb-6 select-all">{ room }</div>
+
+                        <div class="animate-spin rounded-full h-10 w-10 border-b-2 border-blue-500 mx-auto mb-3"></div>
+                        <p class="text-sm text-gray-600">{ status }</p>
+                        if let Some(error) = &self.signaling_error {
+                            <p class="mt-2 text-sm text-red-600">{ error }</p>
+                        }
+
+                        <button
+                            class="w-full mt-6 bg-gray-300 hover:bg-gray-400 text-gray-700 font-medium py-2 px-4 rounded-lg transition-colors"
+                            onclick={ctx.link().callback(|_| Msg::LeaveSignalingRoom)}
+                        >
+                            {"Cancel"}
+                        </button>
+                    </div>
+                </main>
+            </>
+        }
+    }
+
+    fn render_ice_settings_panel(&self, ctx: &Context<Self>) -> Html {
+        let has_turn_server = self.ice_settings.ice_servers.iter().any(IceServer::is_turn);
+        let input_class = "w-full border border-gray-300 rounded-lg px-3 py-2 text-sm font-mono focus:outline-none focus:ring-2 focus:ring-blue-500";
+
+        html! {
+            <div class="mt-4 border border-gray-200 rounded-xl p-4 bg-gray-50">
+                <div class="flex justify-between items-center mb-3">
+                    <h3 class="font-semibold text-gray-800">{"ICE Servers"}</h3>
+                    <button
+                        class="text-sm text-blue-600 hover:text-blue-800"
+                        onclick={ctx.link().callback(|_| Msg::ResetIceSettings)}
+                    >
+                        {"Reset to defaults"}
+                    </button>
+                </div>
+
+                <div class="space-y-3">
+                    {
+                        for self.ice_settings.ice_servers.iter().enumerate().map(|(index, server)| html! {
+                            <div class="grid grid-cols-1 md:grid-cols-12 gap-2 items-center">
+                                <input
+                                    class={classes!(input_class, "md:col-span-5")}
+                                    placeholder="turn:turn.example.com:3478"
+                                    value={server.urls.join(", ")}
+                                    onchange={ctx.link().callback(move |e: web_sys::Event| {
+                                        Msg::UpdateIceServer(index, IceServerField::Urls, e.target_unchecked_into::<HtmlInputElement>().value())
+                                    })}
+                                />
+                                <input
+                                    class={classes!(input_class, "md:col-span-3")}
+                                    placeholder="Username"
+                                    value={server.username.clone().unwrap_or_default()}
+                                    onchange={ctx.link().callback(move |e: web_sys::Event| {
+                                        Msg::UpdateIceServer(index, IceServerField::Username, e.target_unchecked_into::<HtmlInputElement>().value())
+                                    })}
+                                />
+                                <input
+                                    type="password"
+                                    class={classes!(input_class, "md:col-span-3")}
+                                    placeholder="Credential"
+                                    value={server.credential.clone().unwrap_or_default()}
+                                    onchange={ctx.link().callback(move |e: web_sys::Event| {
+                                        Msg::UpdateIceServer(index, IceServerField::Credential, e.target_unchecked_into::<HtmlInputElement>().value())
+                                    })}
+                                />
+                                <button
+                                    class="md:col-span-1 text-red-500 hover:text-red-700 text-lg"
+                                    title="Remove server"
+                                    onclick={ctx.link().callback(move |_| Msg::RemoveIceServer(index))}
+                                >
+                                    {"✕"}
+                                </button>
+                            </div>
+                        })
+                    }
+                </div>
+
+                <button
+                    class="mt-3 text-sm bg-white border border-gray-300 hover:bg-gray-100 text-gray-700 py-1 px-3 rounded-lg transition-colors"
+                    onclick={ctx.link().callback(|_| Msg::AddIceServer)}
+                >
+                    {"+ Add server"}
+                </button>
+
+                <label class="flex items-center space-x-2 mt-4 text-sm text-gray-700">
+                    <input
+                        type="checkbox"
+                        checked={self.ice_settings.relay_only}
+                        onchange={ctx.link().callback(|e: web_sys::Event| {
+                            Msg::SetRelayOnly(e.target_unchecked_into::<HtmlInputElement>().checked())
+                        })}
+                    />
+                    <span>{"ICE relay only (only use TURN relayed paths)"}</span>
+                </label>
+
+                if self.ice_settings.relay_only && !has_turn_server {
+                    <p class="mt-2 text-xs text-red-600">
+                        {"Relay only mode needs at least one turn: or turns: server, otherwise no connection can be made."}
+                    </p>
+                }
+
+                <p class="mt-2 text-xs text-gray-500">
+                    {"These settings only apply to this session. Defaults come from config.json."}
+                </p>
+            </div>
+        }
+    }
+
     fn render_generating_step(&self, ctx: &Context<Self>) -> Html {
         html! {
             <>
@@ -567,6 +3003,8 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                                 <span>{"Ready to share"}</span>
                             </div>
                         </div>
+
+                        { self.render_step_timeout(ctx) }
                     </div>
                 </main>
             </>
@@ -596,17 +3034,7 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
             )
         };
         
-        let connection_code = if let Some(code) = &self.connection_code {
-            code
-        } else {
-            // For clients, get the generated answer
-            if let Some(offer) = self.web_rtc_manager.borrow().get_offer() {
-                // This is actually the answer for clients
-                &self.get_serialized_offer_and_candidates()
-            } else {
-                &loading_text
-            }
-        };
+        let connection_code = self.code_to_share().unwrap_or(loading_text);
         
         html! {
             <>
@@ -679,6 +3107,8 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                                 <div class="w-2 h-2 bg-blue-400 rounded-full animate-bounce" style="animation-delay: 0.2s"></div>
                             </div>
                         </div>
+
+                        { self.render_step_timeout(ctx) }
                     </div>
                 </main>
             </>
@@ -707,6 +3137,12 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                                     value={self.value.clone()}
                                     oninput={ctx.link().callback(|e: InputEvent| {Msg::UpdateInputValue(e.target_unchecked_into::<HtmlInputElement>().value())})}
                                 />
+                                <button
+                                    class="mt-2 w-full bg-white border border-gray-300 hover:bg-gray-50 text-gray-700 text-sm font-medium py-2 px-4 rounded-lg transition-colors"
+                                    onclick={ctx.link().callback(|_| Msg::OpenQrScanner)}
+                                >
+                                    {"📷 Scan QR Code"}
+                                </button>
                             </div>
 
                             <button
@@ -762,6 +3198,12 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                                     value={self.value.clone()}
                                     oninput={ctx.link().callback(|e: InputEvent| {Msg::UpdateInputValue(e.target_unchecked_into::<HtmlInputElement>().value())})}
                                 />
+                                <button
+                                    class="mt-2 w-full bg-white border border-gray-300 hover:bg-gray-50 text-gray-700 text-sm font-medium py-2 px-4 rounded-lg transition-colors"
+                                    onclick={ctx.link().callback(|_| Msg::OpenQrScanner)}
+                                >
+                                    {"📷 Scan QR Code"}
+                                </button>
                             </div>
 
                             <button
@@ -773,6 +3215,8 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                             </button>
                         </div>
 
+                        { self.render_step_timeout(ctx) }
+
                         <div class="mt-6 p-4 bg-purple-50 rounded-lg">
                             <div class="flex items-start space-x-3">
                                 <span class="text-purple-600 text-lg">{"💡"}</span>
@@ -795,6 +3239,28 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
         }
     }
 
+    fn render_toasts(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <div class="fixed top-4 left-1/2 -translate-x-1/2 z-50 w-full max-w-md space-y-2 px-4" role="status">
+                { for self.toasts.iter().map(|toast| {
+                    let id = toast.id;
+                    html! {
+                        <div key={id} class="flex items-start justify-between bg-red-50 border border-red-200 text-red-800 text-sm rounded-lg shadow-lg px-4 py-3">
+                            <span>{"⚠️ "}{ &toast.text }</span>
+                            <button
+                                class="ml-4 text-red-500 hover:text-red-700"
+                                title="Dismiss"
+                                onclick={ctx.link().callback(move |_| Msg::DismissToast(id))}
+                            >
+                                {"✕"}
+                            </button>
+                        </div>
+                    }
+                }) }
+            </div>
+        }
+    }
+
     fn render_qr_modal(&self, ctx: &Context<Self>) -> Html {
         let qr_data_url = self.qr_code_data_url.clone().unwrap_or_default();
         
@@ -836,29 +3302,300 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
         }
     }
 
-    fn render_chat_interface(&self, ctx: &Context<Self>) -> Html {
+    fn render_chat_interface(&self, ctx: &Context<Self>) -> Html {
+        html! {
+            <>
+                { self.get_chat_header(ctx) }
+                { self.render_call_panel(ctx) }
+                { self.render_tabs(ctx) }
+                <div class="flex flex-1 overflow-hidden">
+                if self.show_whiteboard {
+                    <Whiteboard
+                        board={self.board.clone()}
+                        author={self.my_reactor_id()}
+                        on_op={ctx.link().callback(Msg::DrawOnBoard)}
+                        on_error={ctx.link().callback(Msg::WhiteboardFailed)}
+                    />
+                } else {
+                <main
+                    class={if self.is_dragging_file {
+                        "flex-1 overflow-y-auto p-4 bg-blue-50 ring-4 ring-inset ring-blue-300"
+                    } else {
+                        "flex-1 overflow-y-auto p-4 bg-gray-50"
+                    }}
+                    id="chat-main"
+                    ref={self.node_ref.clone()}
+                    ondragover={ctx.link().callback(|e: DragEvent| {
+                        e.prevent_default();
+                        Msg::SetDraggingFile(true)
+                    })}
+                    ondragleave={ctx.link().callback(|_: DragEvent| Msg::SetDraggingFile(false))}
+                    ondrop={ctx.link().callback(|e: DragEvent| {
+                        e.prevent_default();
+                        let files = e.data_transfer().and_then(|transfer| transfer.files());
+                        Msg::SelectFiles(files_from_list(files))
+                    })}
+                >
+                    { self.get_messages_as_html(ctx) }
+                    
+                    // Typing indicator
+                    if let Some(label) = self.typing_label() {
+                        <div class="flex items-end mb-4">
+                            <div class="max-w-md p-3 rounded-2xl rounded-bl-none bg-gray-200 flex items-center space-x-2">
+                                <div class="flex space-x-1">
+                                    <div class="w-2 h-2 bg-gray-500 rounded-full animate-bounce"></div>
+                                    <div class="w-2 h-2 bg-gray-500 rounded-full animate-bounce" style="animation-delay: 0.1s"></div>
+                                    <div class="w-2 h-2 bg-gray-500 rounded-full animate-bounce" style="animation-delay: 0.2s"></div>
+                                </div>
+                                <span class="text-xs text-gray-500">{ label }</span>
+                            </div>
+                        </div>
+                    }
+                </main>
+                { self.render_thread_panel(ctx) }
+                }
+                { self.render_participant_list(ctx) }
+                </div>
+                if !self.show_whiteboard {
+                    { self.get_enhanced_input_for_chat_message(ctx) }
+                }
+            </>
+        }
+    }
+
+    fn render_tabs(&self, ctx: &Context<Self>) -> Html {
+        let tab = |active: bool| {
+            if active {
+                "px-4 py-2 text-sm font-semibold text-blue-600 border-b-2 border-blue-500"
+            } else {
+                "px-4 py-2 text-sm text-gray-500 hover:text-gray-700 border-b-2 border-transparent"
+            }
+        };
+
+        html! {
+            <nav class="flex px-4 bg-white border-b border-gray-200">
+                <button class={tab(!self.show_whiteboard)} onclick={ctx.link().callback(|_| Msg::ShowWhiteboard(false))}>
+                    {"💬 Chat"}
+                </button>
+                <button class={tab(self.show_whiteboard)} onclick={ctx.link().callback(|_| Msg::ShowWhiteboard(true))}>
+                    {"🎨 Whiteboard"}
+                    if self.board_unseen {
+                        <span class="inline-block w-2 h-2 ml-1 align-top bg-red-500 rounded-full" title="New drawings"></span>
+                    }
+                </button>
+            </nav>
+        }
+    }
+
+    fn render_call_panel(&self, ctx: &Context<Self>) -> Html {
+        let Some(call) = &self.call else {
+            return html! {};
+        };
+        let name = self.peer_name(call.peer_id);
+        let kind = if call.video { "video" } else { "audio" };
+
+        match call.phase {
+            CallPhase::Incoming => html! {
+                <div class="flex items-center justify-between px-4 py-3 bg-green-100 border-b border-green-300 text-green-900">
+                    <span class="font-semibold">{ format!("📞 Incoming {} call from {}", kind, name) }</span>
+                    <div class="flex space-x-2">
+                        <button
+                            class="bg-green-500 hover:bg-green-600 text-white font-bold py-1 px-4 rounded"
+                            onclick={ctx.link().callback(|_| Msg::AcceptCall)}
+                        >
+                            {"Accept"}
+                        </button>
+                        <button
+                            class="bg-red-500 hover:bg-red-600 text-white font-bold py-1 px-4 rounded"
+                            onclick={ctx.link().callback(|_| Msg::DeclineCall)}
+                        >
+                            {"Decline"}
+                        </button>
+                    </div>
+                </div>
+            },
+            CallPhase::Outgoing => html! {
+                <div class="flex items-center justify-between px-4 py-3 bg-blue-100 border-b border-blue-300 text-blue-900">
+                    <span class="font-semibold animate-pulse">{ format!("Calling {}…", name) }</span>
+                    <button
+                        class="bg-red-500 hover:bg-red-600 text-white font-bold py-1 px-4 rounded"
+                        onclick={ctx.link().callback(|_| Msg::HangUp)}
+                    >
+                        {"Cancel"}
+                    </button>
+                </div>
+            },
+            CallPhase::Active => {
+                let remote_stream = self
+                    .peers
+                    .iter()
+                    .find(|peer| peer.id == call.peer_id)
+                    .and_then(|peer| peer.remote_stream.clone());
+                let remote_placeholder = (!call.video || call.remote_camera_off).then(|| {
+                    AttrValue::from(if call.remote_muted { "🔇" } else { "👤" })
+                });
+                let remote_label = if call.remote_muted { format!("{} (muted)", name) } else { name };
+                let local_placeholder = (call.screen_track.is_none() && (!call.video || call.camera_off))
+                    .then(|| AttrValue::from("👤"));
+                let local_label = if call.screen_track.is_some() { "Sharing your screen" } else { "You" };
+                let control = "text-white font-bold py-2 px-4 rounded-full transition-colors";
+
+                html! {
+                    <div class="flex flex-col items-center p-3 bg-gray-800 border-b border-gray-900">
+                        <div class="relative w-full max-w-2xl">
+                            <VideoTile stream={remote_stream} label={remote_label} placeholder={remote_placeholder} class="w-full h-64" />
+                            <VideoTile
+                                stream={call.local_stream.clone()}
+                                label={local_label}
+                                muted=true
+                                placeholder={local_placeholder}
+                                class="absolute bottom-2 right-2 w-32 h-24 ring-2 ring-gray-700"
+                            />
+                        </div>
+                        <div class="flex space-x-3 mt-3">
+                            <button
+                                class={classes!(control, if call.muted { "bg-yellow-500" } else { "bg-gray-600 hover:bg-gray-500" })}
+                                title={if call.muted { "Unmute" } else { "Mute" }}
+                                onclick={ctx.link().callback(|_| Msg::ToggleMute)}
+                            >
+                                { if call.muted { "🔇" } else { "🎤" } }
+                            </button>
+                            if call.video {
+                                <button
+                                    class={classes!(control, if call.camera_off { "bg-yellow-500" } else { "bg-gray-600 hover:bg-gray-500" })}
+                                    title={if call.camera_off { "Turn camera on" } else { "Turn camera off" }}
+                                    onclick={ctx.link().callback(|_| Msg::ToggleCamera)}
+                                >
+                                    {"📷"}
+                                </button>
+                            }
+                            if call.screen_track.is_some() {
+                                <button
+                                    class={classes!(control, "bg-blue-500", "hover:bg-blue-600")}
+                                    title="Stop sharing"
+                                    onclick={ctx.link().callback(|_| Msg::StopScreenShare)}
+                                >
+                                    {"🖥️ Stop"}
+                                </button>
+                            } else {
+                                <button
+                                    class={classes!(control, "bg-gray-600", "hover:bg-gray-500")}
+                                    title="Share your screen"
+                                    onclick={ctx.link().callback(|_| Msg::StartScreenShare)}
+                                >
+                                    {"🖥️"}
+                                </button>
+                            }
+                            <button
+                                class={classes!(control, "bg-red-500", "hover:bg-red-600")}
+                                title="Hang up"
+                                onclick={ctx.link().callback(|_| Msg::HangUp)}
+                            >
+                                {"Hang up"}
+                            </button>
+                        </div>
+                    </div>
+                }
+            }
+        }
+    }
+
+    // Every connected peer, plus the connection being paired so a pairing
+    // that never connects can be diagnosed too.
+    fn stats_targets(&self) -> Vec<(PeerId, Rc<RefCell<T>>)> {
+        let mut targets: Vec<_> = self.peers.iter().map(|peer| (peer.id, peer.manager.clone())).collect();
+        let is_pairing = self.web_rtc_manager.borrow().get_state() != State::Default;
+        if is_pairing && !targets.iter().any(|(peer_id, _)| *peer_id == self.pairing_peer_id) {
+            targets.push((self.pairing_peer_id, self.web_rtc_manager.clone()));
+        }
+        targets
+    }
+
+    fn render_diagnostics_panel(&self, ctx: &Context<Self>) -> Html {
+        let targets = self.stats_targets();
+
+        html! {
+            <div class="fixed bottom-4 right-4 w-96 max-h-[80vh] overflow-y-auto bg-white border border-gray-300 rounded-lg shadow-xl p-4 z-40">
+                <div class="flex justify-between items-center mb-3">
+                    <h3 class="font-bold text-gray-700">{"Connection diagnostics"}</h3>
+                    <button
+                        class="text-gray-500 hover:text-gray-700"
+                        onclick={ctx.link().callback(|_| Msg::ToggleDiagnostics)}
+                    >
+                        {"✕"}
+                    </button>
+                </div>
+                if targets.is_empty() {
+                    <p class="text-sm text-gray-500">{"No connection yet."}</p>
+                }
+                { for targets.iter().map(|(peer_id, _)| {
+                    let name = if self.peers.iter().any(|peer| peer.id == *peer_id) {
+                        self.peer_name(*peer_id)
+                    } else {
+                        "Pairing".to_string()
+                    };
+                    render_stats_card(&name, self.stats.get(peer_id))
+                }) }
+            </div>
+        }
+    }
+
+    fn peer_name(&self, peer_id: PeerId) -> String {
+        let participant_id = self.participant_id_of(peer_id);
+        self.participants()
+            .into_iter()
+            .find(|participant| participant.id == participant_id)
+            .map(|participant| participant.nickname)
+            .unwrap_or_else(|| "Friend".to_string())
+    }
+
+    fn render_participant_list(&self, ctx: &Context<Self>) -> Html {
+        let participants = self.participants();
+        if participants.is_empty() {
+            return html! {};
+        }
+
         html! {
-            <>
-                { self.get_chat_header(ctx) }
-                <main class="flex-1 overflow-y-auto p-4 bg-gray-50" id="chat-main" ref={self.node_ref.clone()}>
-                    { self.get_messages_as_html() }
-                    
-                    // Typing indicator
-                    if self.typing_state.is_typing {
-                        <div class="flex items-end mb-4">
-                            <div class="max-w-md p-3 rounded-2xl rounded-bl-none bg-gray-200 flex items-center space-x-2">
-                                <div class="flex space-x-1">
-                                    <div class="w-2 h-2 bg-gray-500 rounded-full animate-bounce"></div>
-                                    <div class="w-2 h-2 bg-gray-500 rounded-full animate-bounce" style="animation-delay: 0.1s"></div>
-                                    <div class="w-2 h-2 bg-gray-500 rounded-full animate-bounce" style="animation-delay: 0.2s"></div>
-                                </div>
-                                <span class="text-xs text-gray-500">{"typing..."}</span>
-                            </div>
-                        </div>
+            <aside class="w-56 border-l-2 border-gray-300 bg-white p-4 overflow-y-auto">
+                <h3 class="text-sm font-bold text-gray-700 mb-3">
+                    { format!("Participants ({})", participants.iter().filter(|p| p.status != ParticipantStatus::Left).count()) }
+                </h3>
+                <ul class="space-y-2">
+                    {
+                        for participants.iter().map(|participant| {
+                            let status_class = match participant.status {
+                                ParticipantStatus::Connected => "text-green-600",
+                                ParticipantStatus::Connecting | ParticipantStatus::Reconnecting => "text-yellow-600",
+                                ParticipantStatus::Left => "text-gray-400",
+                            };
+                            html! {
+                                <li class="flex items-center space-x-2">
+                                    <span class="w-3 h-3 rounded-full flex-shrink-0" style={format!("background-color: {}", participant.color)}></span>
+                                    <div class="min-w-0">
+                                        <div class="text-sm font-medium text-gray-800 truncate">
+                                            { participant.nickname.clone() }
+                                            if self.is_me(participant) {
+                                                <span class="text-gray-500 font-normal">{" (you)"}</span>
+                                            }
+                                        </div>
+                                        <div class={classes!("text-xs", status_class)}>{ participant.status.label() }</div>
+                                        { self.render_participant_safety_number(ctx, participant) }
+                                    </div>
+                                </li>
+                            }
+                        })
                     }
-                </main>
-                { self.get_enhanced_input_for_chat_message(ctx) }
-            </>
+                </ul>
+
+                if self.is_host() {
+                    <button
+                        class="w-full mt-4 bg-blue-600 hover:bg-blue-700 text-white text-sm font-medium py-2 px-3 rounded-lg transition-colors"
+                        onclick={ctx.link().callback(|_| Msg::InviteParticipant)}
+                    >
+                        {"➕ Invite someone"}
+                    </button>
+                }
+            </aside>
         }
     }
 
@@ -868,7 +3605,46 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
 
         html! {
             <div class="border-t-2 border-gray-200 bg-white p-4">
+                if let Some(parent_id) = &self.replying_to {
+                    <div class="flex justify-between items-center text-sm text-gray-700 bg-gray-100 rounded-lg px-3 py-1 mb-2">
+                        <span class="truncate">{ self.reply_banner(parent_id) }</span>
+                        <button class="hover:underline ml-3" onclick={ctx.link().callback(|_| Msg::CancelReply)}>
+                            {"Cancel"}
+                        </button>
+                    </div>
+                }
+                if self.editing.is_some() {
+                    <div class="flex justify-between items-center text-sm text-blue-700 bg-blue-50 rounded-lg px-3 py-1 mb-2">
+                        <span>{"✏️ Editing message"}</span>
+                        <button class="hover:underline" onclick={ctx.link().callback(|_| Msg::CancelEdit)}>
+                            {"Cancel"}
+                        </button>
+                    </div>
+                }
                 <div class="flex items-end space-x-3">
+                    <label
+                        class={if is_chat_enabled {
+                            "bg-gray-200 hover:bg-gray-300 text-gray-700 p-3 rounded-full transition-colors duration-200 cursor-pointer"
+                        } else {
+                            "bg-gray-100 text-gray-400 p-3 rounded-full cursor-not-allowed"
+                        }}
+                        title="Send a file"
+                    >
+                        {"📎"}
+                        <input
+                            type="file"
+                            class="hidden"
+                            multiple=true
+                            disabled={!is_chat_enabled}
+                            onchange={ctx.link().callback(|e: web_sys::Event| {
+                                let input = e.target_unchecked_into::<HtmlInputElement>();
+                                let files = files_from_list(input.files());
+                                input.set_value("");
+                                Msg::SelectFiles(files)
+                            })}
+                        />
+                    </label>
+                    { self.render_record_button(ctx, is_chat_enabled) }
                     <div class="flex-1">
                         <input
                             type="text"
@@ -902,19 +3678,57 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
         }
     }
 
+    // Hold to record; releasing (or leaving the button) sends the message.
+    fn render_record_button(&self, ctx: &Context<Self>, is_chat_enabled: bool) -> Html {
+        let class = match (is_chat_enabled, self.recording_since.is_some()) {
+            (false, _) => "bg-gray-100 text-gray-400 p-3 rounded-full cursor-not-allowed",
+            (true, true) => "bg-red-500 text-white p-3 rounded-full animate-pulse select-none",
+            (true, false) => "bg-gray-200 hover:bg-gray-300 text-gray-700 p-3 rounded-full transition-colors duration-200 select-none",
+        };
+
+        html! {
+            <>
+                <button
+                    class={class}
+                    title="Hold to record a voice message"
+                    disabled={!is_chat_enabled}
+                    onmousedown={ctx.link().callback(|_| Msg::StartRecording)}
+                    onmouseup={ctx.link().callback(|_| Msg::StopRecording)}
+                    onmouseleave={ctx.link().callback(|_| Msg::StopRecording)}
+                    ontouchstart={ctx.link().callback(|e: web_sys::TouchEvent| {
+                        e.prevent_default();
+                        Msg::StartRecording
+                    })}
+                    ontouchend={ctx.link().callback(|_| Msg::StopRecording)}
+                >
+                    {"🎤"}
+                </button>
+                if let Some(since) = self.recording_since {
+                    <span class="text-sm text-red-600 tabular-nums self-center">
+                        { format!("● {} · release to send", voice::format_duration((js_sys::Date::now() - since) as u32)) }
+                    </span>
+                }
+            </>
+        }
+    }
+
     fn scroll_top(&self) {
         let node_ref = self.node_ref.clone();
 
         spawn_local(async move {
-            let chat_main = node_ref.cast::<Element>().unwrap();
+            // Not there while the whiteboard is shown
+            let Some(chat_main) = node_ref.cast::<Element>() else {
+                return;
+            };
             let current_scroll_top = chat_main.scroll_top();
             chat_main.set_scroll_top(current_scroll_top + 100000000);
         })
     }
 
     fn get_chat_header(&self, ctx: &Context<Self>) -> Html {
-        let is_disconnect_button_visible =
-            self.web_rtc_manager.borrow().get_state() != State::Default;
+        let is_disconnect_button_visible = self.web_rtc_manager.borrow().get_state() != State::Default
+            || !self.peers.is_empty()
+            || self.signaling.is_some();
         html! {
             <header class="flex justify-between items-center p-4 border-b-2 border-gray-300 bg-gray-200 text-gray-600">
                 <div class="text-2xl font-bold">
@@ -923,6 +3737,60 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
 
                 { self.get_debug_html() }
 
+                <button
+                    class={classes!(
+                        "font-bold", "py-2", "px-3", "rounded", "transition-colors",
+                        if self._stats_poll.is_some() { "bg-blue-500 text-white" } else { "bg-gray-300 hover:bg-gray-400 text-gray-700" }
+                    )}
+                    title="Connection diagnostics"
+                    onclick={ctx.link().callback(|_| Msg::ToggleDiagnostics)}
+                >
+                    {"📊"}
+                </button>
+
+                { self.render_security_status(ctx) }
+
+                <button
+                    class="bg-gray-300 hover:bg-gray-400 text-gray-700 font-bold py-2 px-4 rounded transition-colors"
+                    onclick={ctx.link().callback(|_| Msg::OpenHistory)}
+                >
+                    {"📜 History"}
+                </button>
+
+                { self.render_export_menu(ctx) }
+
+                { self.render_reconnect_status(ctx) }
+
+                { self.render_send_queue_status() }
+
+                if self.call.is_none() && self.call_peer().is_some() {
+                    <div class="flex space-x-2">
+                        <button
+                            class="bg-green-500 hover:bg-green-600 text-white font-bold py-2 px-3 rounded transition-colors"
+                            title="Audio call"
+                            onclick={ctx.link().callback(|_| Msg::StartCall(false))}
+                        >
+                            {"📞"}
+                        </button>
+                        <button
+                            class="bg-green-500 hover:bg-green-600 text-white font-bold py-2 px-3 rounded transition-colors"
+                            title="Video call"
+                            onclick={ctx.link().callback(|_| Msg::StartCall(true))}
+                        >
+                            {"🎥"}
+                        </button>
+                    </div>
+                }
+
+                if !matches!(self.connection_step, ConnectionStep::Connected) && self.is_chat_enabled() {
+                    <button
+                        class="bg-blue-500 hover:bg-blue-600 text-white font-bold py-2 px-4 rounded transition-colors"
+                        onclick={ctx.link().callback(|_| Msg::SetConnectionStep(ConnectionStep::Connected))}
+                    >
+                        {"← Back to chat"}
+                    </button>
+                }
+
                 {
                     if is_disconnect_button_visible {
                         html! {
@@ -943,17 +3811,7 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
     }
 
     fn is_chat_enabled(&self) -> bool {
-        match &self.web_rtc_manager.borrow().get_state() {
-            State::Default => false,
-            State::Server(connection_state) => {
-                connection_state.data_channel_state.is_some()
-                    && connection_state.data_channel_state.unwrap() == RtcDataChannelState::Open
-            }
-            State::Client(connection_state) => {
-                connection_state.data_channel_state.is_some()
-                    && connection_state.data_channel_state.unwrap() == RtcDataChannelState::Open
-            }
-        }
+        self.peers.iter().any(RoomPeer::is_open)
     }
 
     fn get_input_for_chat_message(&self, ctx: &Context<Self>) -> Html {
@@ -1011,23 +3869,20 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
         }
     }
 
-    fn get_serialized_offer_and_candidates(&self) -> String {
+    fn get_serialized_offer_and_candidates(&self) -> Result<String, WebRtcError> {
+        let manager = self.web_rtc_manager.borrow();
         let connection_string = ConnectionString {
-            offer: self
-                .web_rtc_manager
-                .borrow()
+            offer: manager
                 .get_offer()
-                .expect("no offer yet"),
-            ice_candidates: self.web_rtc_manager.borrow().get_ice_candidates(),
+                .ok_or_else(|| WebRtcError::NegotiationFailed("the offer is not ready yet".into()))?,
+            ice_candidates: manager.get_ice_candidates(),
         };
 
-        let serialized: String = serde_json::to_string(&connection_string).unwrap();
-
-        base64::encode(serialized)
+        Ok(connection_code::encode(&connection_string)?)
     }
 
     fn get_offer_and_candidates(&self, ctx: &Context<Self>) -> Html {
-        let encoded = self.get_serialized_offer_and_candidates();
+        let encoded = self.get_serialized_offer_and_candidates().unwrap_or_else(|err| err.to_string());
         html! {
             <div class="space-y-3">
                 <p class="text-sm text-gray-700">{ "Give this code to the person you want to talk to:" }</p>
@@ -1070,26 +3925,335 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
         html
     }
 
-    fn copy_content_to_clipboard(&self) {
-        let window = web_sys::window().unwrap();
-        let document = window.document().unwrap();
-        let aux = document.create_element("input").unwrap();
-        let aux = aux.dyn_into::<web_sys::HtmlInputElement>().unwrap();
-        let content: String = document
-            .get_element_by_id("copy-elem")
-            .unwrap()
-            .inner_html();
-        let _result = aux.set_attribute("value", &content);
-        let document = window.document().unwrap();
-        let _result = document.body().unwrap().append_child(&aux);
-        aux.select();
-        let html_document = document.dyn_into::<web_sys::HtmlDocument>().unwrap();
-        let _result = html_document.exec_command("copy");
-        let document = window.document().unwrap();
-        let _result = document.body().unwrap().remove_child(&aux);
-    }
-
-    fn get_messages_as_html(&self) -> Html {
+    // The code shown while sharing: the host's offer, or for clients the
+    // answer once it has been made.
+    fn code_to_share(&self) -> Result<String, WebRtcError> {
+        match &self.connection_code {
+            Some(code) => Ok(code.clone()),
+            None => self.get_serialized_offer_and_candidates(),
+        }
+    }
+
+    fn copy_text(&mut self, ctx: &Context<Self>, text: &str) {
+        if let Err(err) = copy_to_clipboard(text) {
+            console::log_2(&"Failed to copy to the clipboard".into(), &err);
+            self.show_toast(ctx, "Could not copy to the clipboard".to_string());
+        }
+    }
+
+    fn render_file_attachment(&self, transfer: &FileTransfer, sender: &MessageSender) -> Html {
+        if let Some(voice) = &transfer.voice {
+            return render_voice_message(transfer, voice);
+        }
+
+        let is_done = transfer.is_complete();
+        let status = match (sender, is_done) {
+            (MessageSender::Me, true) => "Sent".to_string(),
+            (MessageSender::Me, false) => format!("Sending… {}%", transfer.progress_percent()),
+            (MessageSender::Other, true) => "Received".to_string(),
+            (MessageSender::Other, false) => format!("Receiving… {}%", transfer.progress_percent()),
+        };
+
+        html! {
+            <div class="space-y-2 min-w-[14rem]">
+                if let (true, Some(url)) = (transfer.info.is_image(), transfer.download_url.clone()) {
+                    <img
+                        src={url}
+                        alt={transfer.info.name.clone()}
+                        class="max-h-64 rounded-lg"
+                    />
+                }
+                <div class="flex items-center space-x-2">
+                    <span class="text-2xl">{"📄"}</span>
+                    <div class="flex-1 min-w-0">
+                        <div class="font-medium truncate">{ transfer.info.name.clone() }</div>
+                        <div class="text-xs opacity-75">{ format!("{} · {}", transfer.info.format_size(), status) }</div>
+                    </div>
+                </div>
+                if !is_done {
+                    <div class="w-full bg-white bg-opacity-40 rounded-full h-2">
+                        <div
+                            class="bg-green-400 h-2 rounded-full transition-all duration-200"
+                            style={format!("width: {}%", transfer.progress_percent())}
+                        ></div>
+                    </div>
+                }
+                if let Some(url) = transfer.download_url.clone() {
+                    <a
+                        href={url}
+                        download={transfer.info.name.clone()}
+                        class="inline-block text-sm font-semibold underline"
+                    >
+                        {"⬇ Download"}
+                    </a>
+                }
+            </div>
+        }
+    }
+
+    // The session as it stands, with names as they are shown in the chat.
+    fn transcript(&self) -> Transcript {
+        let title = match &self.conversation_id {
+            Some(conversation_id) => self.conversation_title(conversation_id),
+            None => "Chat".to_string(),
+        };
+        let messages = self
+            .messages
+            .iter()
+            .map(|message| TranscriptMessage {
+                id: message.id.clone(),
+                sender: self.sender_name(message).0,
+                from_me: message.sender == MessageSender::Me,
+                timestamp: message.timestamp,
+                // What was deleted stays deleted in the export too
+                content: if message.deleted { String::new() } else { message.content.clone() },
+                edited: message.edited_at.is_some(),
+                deleted: message.deleted,
+                reply_to: message.reply_to.clone(),
+                attachment: message
+                    .attachment
+                    .as_ref()
+                    .and_then(|id| self.transfers.iter().find(|transfer| &transfer.id == id))
+                    .map(|transfer| transfer.info.name.clone()),
+            })
+            .collect();
+
+        Transcript::new(title, js_sys::Date::now() as u64, messages)
+    }
+
+    fn render_export_menu(&self, ctx: &Context<Self>) -> Html {
+        if self.messages.is_empty() {
+            return html! {};
+        }
+
+        html! {
+            <div class="relative">
+                <button
+                    class="bg-gray-300 hover:bg-gray-400 text-gray-700 font-bold py-2 px-3 rounded transition-colors"
+                    title="Export transcript"
+                    onclick={ctx.link().callback(|_| Msg::ToggleExportMenu)}
+                >
+                    {"⬇"}
+                </button>
+                if self.show_export_menu {
+                    <div class="absolute right-0 mt-2 w-40 bg-white border border-gray-200 rounded-lg shadow-lg z-40">
+                        { for TranscriptFormat::ALL.iter().map(|&format| html! {
+                            <button
+                                class="block w-full text-left px-4 py-2 text-sm text-gray-700 hover:bg-gray-100"
+                                onclick={ctx.link().callback(move |_| Msg::ExportTranscript(format))}
+                            >
+                                { format!("Export as {}", format.label()) }
+                            </button>
+                        }) }
+                    </div>
+                }
+            </div>
+        }
+    }
+
+    fn sender_name(&self, message: &Message) -> (String, String) {
+        let author = message
+            .author
+            .as_ref()
+            .and_then(|id| self.roster.iter().find(|participant| &participant.id == id));
+        match (&message.sender, author) {
+            (MessageSender::Me, _) => ("Me".to_string(), String::new()),
+            (MessageSender::Other, Some(author)) => (author.nickname.clone(), format!("color: {}", author.color)),
+            (MessageSender::Other, None) => ("Friend".to_string(), String::new()),
+        }
+    }
+
+    fn replies_to<'a>(&'a self, message_id: &'a str) -> impl Iterator<Item = &'a Message> + 'a {
+        self.messages
+            .iter()
+            .filter(move |message| message.reply_to.as_deref() == Some(message_id))
+    }
+
+    // Every message below `root` in its reply tree, oldest first.
+    fn thread_of(&self, root: &str) -> Vec<&Message> {
+        let mut ids = vec![root.to_string()];
+        let mut thread = vec![];
+        for message in &self.messages {
+            if message.reply_to.as_ref().is_some_and(|parent| ids.contains(parent)) {
+                ids.push(message.id.clone());
+                thread.push(message);
+            }
+        }
+        thread
+    }
+
+    fn quote_text(&self, message_id: &str) -> (String, String) {
+        match self.messages.iter().find(|message| message.id == message_id) {
+            Some(message) if message.deleted => (self.sender_name(message).0, "deleted message".to_string()),
+            Some(message) => (self.sender_name(message).0, snippet(&message.content)),
+            None => ("Unknown".to_string(), "original message not available".to_string()),
+        }
+    }
+
+    fn reply_banner(&self, parent_id: &str) -> String {
+        let (name, text) = self.quote_text(parent_id);
+        format!("↩️ Replying to {}: {}", name, text)
+    }
+
+    fn render_quote(&self, ctx: &Context<Self>, message: &Message) -> Html {
+        let Some(parent_id) = message.reply_to.clone() else {
+            return html! {};
+        };
+        let (name, text) = self.quote_text(&parent_id);
+
+        html! {
+            <button
+                class="block w-full text-left text-xs border-l-4 border-current opacity-75 hover:opacity-100 pl-2 mb-2"
+                title="Show the original message"
+                onclick={ctx.link().callback(move |_| Msg::ScrollToMessage(parent_id.clone()))}
+            >
+                <div class="font-bold">{ name }</div>
+                <div class="truncate">{ text }</div>
+            </button>
+        }
+    }
+
+    fn render_thread_panel(&self, ctx: &Context<Self>) -> Html {
+        let Some(root_id) = self.thread.clone() else {
+            return html! {};
+        };
+        let Some(root) = self.messages.iter().find(|message| message.id == root_id) else {
+            return html! {};
+        };
+        let replies = self.thread_of(&root_id);
+
+        let entry = |message: &Message| {
+            let (name, name_style) = self.sender_name(message);
+            let scroll_id = message.id.clone();
+            html! {
+                <li
+                    class="bg-gray-100 hover:bg-gray-200 rounded-lg p-2 cursor-pointer"
+                    onclick={ctx.link().callback(move |_| Msg::ScrollToMessage(scroll_id.clone()))}
+                >
+                    <div class="text-xs font-bold" style={name_style}>{ name }</div>
+                    <div class="text-sm text-gray-800 break-words">
+                        { if message.deleted { "🚫 This message was deleted".to_string() } else { message.content.clone() } }
+                    </div>
+                </li>
+            }
+        };
+
+        html! {
+            <aside class="w-72 border-l-2 border-gray-300 bg-white p-4 overflow-y-auto flex flex-col">
+                <div class="flex justify-between items-center mb-3">
+                    <h3 class="text-sm font-bold text-gray-700">{ format!("Thread ({} replies)", replies.len()) }</h3>
+                    <button
+                        class="text-gray-500 hover:text-gray-700 text-xl"
+                        onclick={ctx.link().callback(|_| Msg::CloseThread)}
+                    >
+                        {"×"}
+                    </button>
+                </div>
+                <ul class="space-y-2">
+                    { entry(root) }
+                    <li class="border-t border-gray-200"></li>
+                    { for replies.into_iter().map(entry) }
+                </ul>
+                if self.is_chat_enabled() {
+                    <button
+                        class="mt-4 bg-blue-600 hover:bg-blue-700 text-white text-sm font-medium py-2 px-3 rounded-lg transition-colors"
+                        onclick={ctx.link().callback(move |_| Msg::ReplyTo(root_id.clone()))}
+                    >
+                        {"↩️ Reply in thread"}
+                    </button>
+                }
+            </aside>
+        }
+    }
+
+    fn render_reactions(&self, ctx: &Context<Self>, message: &Message) -> Html {
+        if message.reactions.is_empty() {
+            return html! {};
+        }
+
+        let me = self.my_reactor_id();
+        html! {
+            <div class="flex flex-wrap gap-1 mt-2">
+                {
+                    for message.reactions.iter().map(|(emoji, reactors)| {
+                        let class = if reactors.contains(&me) {
+                            "bg-blue-100 border-blue-400 text-blue-800"
+                        } else {
+                            "bg-white border-gray-300 text-gray-700"
+                        };
+                        let message_id = message.id.clone();
+                        let toggled = emoji.clone();
+                        html! {
+                            <button
+                                class={classes!("text-xs", "border", "rounded-full", "px-2", "py-0.5", class)}
+                                onclick={ctx.link().callback(move |_| Msg::ToggleReaction(message_id.clone(), toggled.clone()))}
+                            >
+                                { format!("{} {}", emoji, reactors.len()) }
+                            </button>
+                        }
+                    })
+                }
+            </div>
+        }
+    }
+
+    // Shown next to a bubble on hover: reactions for any message, edit and
+    // delete for our own.
+    fn render_message_actions(&self, ctx: &Context<Self>, message: &Message) -> Html {
+        if message.deleted || !self.is_chat_enabled() {
+            return html! {};
+        }
+
+        let is_mine = message.sender == MessageSender::Me;
+        let reply_id = message.id.clone();
+        let edit_id = message.id.clone();
+        let delete_id = message.id.clone();
+        html! {
+            <div class="hidden group-hover:flex items-center space-x-1 mx-2 text-sm">
+                {
+                    for REACTION_EMOJI.iter().map(|emoji| {
+                        let message_id = message.id.clone();
+                        html! {
+                            <button
+                                class="hover:scale-125 transition-transform"
+                                title="React"
+                                onclick={ctx.link().callback(move |_| Msg::ToggleReaction(message_id.clone(), emoji.to_string()))}
+                            >
+                                { *emoji }
+                            </button>
+                        }
+                    })
+                }
+                <button
+                    class="text-gray-500 hover:text-gray-800"
+                    title="Reply"
+                    onclick={ctx.link().callback(move |_| Msg::ReplyTo(reply_id.clone()))}
+                >
+                    {"↩️"}
+                </button>
+                if is_mine && message.attachment.is_none() {
+                    <button
+                        class="text-gray-500 hover:text-gray-800"
+                        title="Edit"
+                        onclick={ctx.link().callback(move |_| Msg::StartEdit(edit_id.clone()))}
+                    >
+                        {"✏️"}
+                    </button>
+                }
+                if is_mine {
+                    <button
+                        class="text-gray-500 hover:text-red-600"
+                        title="Delete for everyone"
+                        onclick={ctx.link().callback(move |_| Msg::DeleteMessage(delete_id.clone()))}
+                    >
+                        {"🗑️"}
+                    </button>
+                }
+            </div>
+        }
+    }
+
+    fn get_messages_as_html(&self, ctx: &Context<Self>) -> Html {
         html! {
             <div class="space-y-3">
                 {
@@ -1100,17 +4264,46 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
                         } else {
                             ("flex items-end mb-4 flex-row-reverse", "max-w-md p-4 rounded-2xl rounded-br-none bg-blue-500", "text-white")
                         };
-                        let message_sender_name = if a_message.sender == MessageSender::Other { "Friend" } else { "Me" };
+                        let (message_sender_name, name_style) = self.sender_name(a_message);
+                        let reply_count = self.replies_to(&a_message.id).count();
+                        let thread_id = a_message.id.clone();
                         html! {
-                            <div class={container_class}>
+                            <div id={message_element_id(&a_message.id)} class={classes!("group", container_class)}>
                                 <div class={bubble_class}>
                                     <div class="flex justify-between items-center mb-2">
-                                        <div class="font-bold text-sm">{ message_sender_name }</div>
+                                        <div class="font-bold text-sm" style={name_style}>{ message_sender_name }</div>
+                                        if a_message.sender == MessageSender::Me {
+                                            <div class="text-xs text-blue-100 ml-3" title={receipt_title(a_message.receipt)}>
+                                                { receipt_ticks(a_message.receipt) }
+                                            </div>
+                                        }
                                     </div>
+                                    { self.render_quote(ctx, a_message) }
                                     <div class={text_class}>
-                                        { a_message.content.clone() }
+                                        {
+                                            match a_message.attachment.as_ref().and_then(|id| self.transfers.iter().find(|t| &t.id == id)) {
+                                                _ if a_message.deleted => html! {
+                                                    <span class="italic opacity-75">{"🚫 This message was deleted"}</span>
+                                                },
+                                                Some(transfer) => self.render_file_attachment(transfer, &a_message.sender),
+                                                None => markdown::render(&a_message.content, &ctx.link().callback(Msg::CopyCode)),
+                                            }
+                                        }
+                                        if a_message.edited_at.is_some() && !a_message.deleted {
+                                            <span class="text-xs opacity-75 ml-2">{"(edited)"}</span>
+                                        }
                                     </div>
+                                    { self.render_reactions(ctx, a_message) }
+                                    if reply_count > 0 {
+                                        <button
+                                            class={classes!("text-xs", "font-semibold", "hover:underline", "mt-2", text_class)}
+                                            onclick={ctx.link().callback(move |_| Msg::OpenThread(thread_id.clone()))}
+                                        >
+                                            { format!("💬 {} {}", reply_count, if reply_count == 1 { "reply" } else { "replies" }) }
+                                        </button>
+                                    }
                                 </div>
+                                { self.render_message_actions(ctx, a_message) }
                             </div>
                         }
                     })
@@ -1120,6 +4313,175 @@ impl<T: NetworkManager + 'static> ChatModel<T> {
     }
 }
 
+fn default_ice_settings() -> WebRtcDefaults {
+    let config_str = include_str!("../../config.json");
+    let config: Config = serde_json::from_str(config_str).expect("Failed to parse config.json");
+    config.webrtc_defaults
+}
+
+// A textarea rather than an input so line breaks in code survive the copy.
+fn copy_to_clipboard(text: &str) -> Result<(), JsValue> {
+    let document = gloo_utils::document();
+    let aux = document.create_element("textarea")?.dyn_into::<web_sys::HtmlTextAreaElement>()?;
+    aux.set_value(text);
+    let body = document.body().ok_or_else(|| JsValue::from_str("No document body"))?;
+    body.append_child(&aux)?;
+    aux.select();
+    let copied = document.dyn_into::<web_sys::HtmlDocument>()?.exec_command("copy");
+    aux.remove();
+
+    if copied? {
+        Ok(())
+    } else {
+        Err(JsValue::from_str("The browser refused to copy"))
+    }
+}
+
+// The remote candidates arrived, so the codes were exchanged.
+fn is_ice_checking(connection_state: &ConnectionState) -> bool {
+    connection_state
+        .ice_connection_state
+        .is_some_and(|state| state != RtcIceConnectionState::New)
+}
+
+fn render_voice_message(transfer: &FileTransfer, voice: &VoiceInfo) -> Html {
+    html! {
+        <div class="space-y-2 min-w-[16rem]">
+            <div class="flex items-center space-x-2">
+                <span class="text-xl">{"🎤"}</span>
+                <div class="flex items-end h-8 space-x-px flex-1" title="Waveform">
+                    {
+                        for voice.waveform.iter().map(|level| html! {
+                            <div
+                                class="flex-1 bg-current opacity-75 rounded-sm"
+                                style={format!("height: {}%", (*level as u32 * 100 / 255).max(4))}
+                            ></div>
+                        })
+                    }
+                </div>
+                <span class="text-xs opacity-75 tabular-nums">{ voice::format_duration(voice.duration_ms) }</span>
+            </div>
+            {
+                match transfer.download_url.clone() {
+                    Some(url) => html! { <audio controls=true preload="metadata" src={url} class="w-full h-8"></audio> },
+                    None => html! {
+                        <div class="text-xs opacity-75">{ format!("Receiving… {}%", transfer.progress_percent()) }</div>
+                    },
+                }
+            }
+        </div>
+    }
+}
+
+fn render_stats_card(name: &str, history: Option<&StatsHistory>) -> Html {
+    let Some(stats) = history.and_then(|history| history.latest.as_ref()) else {
+        return html! {
+            <div class="mb-3 text-sm text-gray-500">{ format!("{}: waiting for stats…", name) }</div>
+        };
+    };
+    let history = history.cloned().unwrap_or_default();
+    let candidate = |candidate: &Option<diagnostics::CandidateInfo>| match candidate {
+        Some(candidate) => format!(
+            "{} ({}, {})",
+            candidate.candidate_type,
+            candidate.protocol,
+            candidate.description()
+        ),
+        None => "not selected yet".to_string(),
+    };
+
+    let rtt = history.series(|sample| sample.rtt_ms.unwrap_or_default());
+    let sent = history.series(|sample| sample.send_kbps);
+    let received = history.series(|sample| sample.receive_kbps);
+    let throughput_max = sent.iter().chain(&received).cloned().fold(1.0, f64::max);
+
+    html! {
+        <div class="mb-4 text-sm">
+            <div class="font-semibold text-gray-800 mb-1">{ name.to_string() }</div>
+            <dl class="grid grid-cols-2 gap-x-2 text-xs font-mono text-gray-700">
+                <dt class="text-gray-500">{"Local"}</dt><dd>{ candidate(&stats.local_candidate) }</dd>
+                <dt class="text-gray-500">{"Remote"}</dt><dd>{ candidate(&stats.remote_candidate) }</dd>
+                <dt class="text-gray-500">{"RTT"}</dt>
+                <dd>{ stats.rtt_ms.map(|rtt| format!("{:.0} ms", rtt)).unwrap_or_else(|| "–".to_string()) }</dd>
+                <dt class="text-gray-500">{"Sent / received"}</dt>
+                <dd>{ format!("{} / {}", diagnostics::format_bytes(stats.bytes_sent), diagnostics::format_bytes(stats.bytes_received)) }</dd>
+                <dt class="text-gray-500">{"Buffered"}</dt>
+                <dd>{ diagnostics::format_bytes(stats.buffered_amount as u64) }</dd>
+            </dl>
+            { render_stats_chart("RTT (ms)", rtt.iter().cloned().fold(50.0, f64::max), &[("#2563eb", rtt)]) }
+            { render_stats_chart("kbit/s sent, received", throughput_max, &[("#16a34a", sent), ("#d97706", received)]) }
+        </div>
+    }
+}
+
+fn render_stats_chart(title: &str, max: f64, series: &[(&str, Vec<f64>)]) -> Html {
+    const WIDTH: f64 = 320.0;
+    const HEIGHT: f64 = 48.0;
+
+    html! {
+        <div class="mt-2">
+            <div class="flex justify-between text-xs text-gray-500">
+                <span>{ title.to_string() }</span>
+                <span>{ format!("max {:.0}", max) }</span>
+            </div>
+            <svg class="w-full bg-gray-50 border border-gray-200 rounded" viewBox={format!("0 0 {} {}", WIDTH, HEIGHT)} preserveAspectRatio="none" height="48">
+                { for series.iter().map(|(color, values)| html! {
+                    <polyline
+                        fill="none"
+                        stroke={color.to_string()}
+                        stroke-width="1.5"
+                        points={diagnostics::chart_points(values, max, WIDTH, HEIGHT)}
+                    />
+                }) }
+            </svg>
+        </div>
+    }
+}
+
+fn open_call_media<T: NetworkManager + 'static>(ctx: &Context<ChatModel<T>>, video: bool) {
+    let link = ctx.link().clone();
+    spawn_local(async move {
+        match call::open_media(video).await {
+            Ok(stream) => link.send_message(Msg::LocalMediaOpened(stream)),
+            Err(err) => link.send_message(Msg::CallFailed(err)),
+        }
+    });
+}
+
+fn message_element_id(message_id: &str) -> String {
+    format!("message-{}", message_id)
+}
+
+fn snippet(content: &str) -> String {
+    if content.chars().count() > QUOTE_LEN {
+        format!("{}…", content.chars().take(QUOTE_LEN).collect::<String>())
+    } else {
+        content.to_string()
+    }
+}
+
+fn receipt_ticks(receipt: Option<ReceiptKind>) -> &'static str {
+    match receipt {
+        None => "…",
+        Some(ReceiptKind::Delivered) => "✓",
+        Some(ReceiptKind::Read) => "✓✓",
+    }
+}
+
+fn receipt_title(receipt: Option<ReceiptKind>) -> &'static str {
+    match receipt {
+        None => "Sent",
+        Some(ReceiptKind::Delivered) => "Delivered",
+        Some(ReceiptKind::Read) => "Read",
+    }
+}
+
+fn files_from_list(file_list: Option<FileList>) -> Vec<File> {
+    file_list
+        .map(|list| (0..list.length()).filter_map(|i| list.get(i)).collect())
+        .unwrap_or_default()
+}
+
 fn get_debug_state_string(state: &State) -> String {
     match state {
         State::Default => "Default State".into(),
@@ -1139,4 +4501,6 @@ fn get_debug_state_string(state: &State) -> String {
             connection_state.data_channel_state,
         ),
     }
-}
\ No newline at end of file
+}
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests;
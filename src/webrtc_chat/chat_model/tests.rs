@@ -0,0 +1,701 @@
+use std::time::Duration;
+
+use wasm_bindgen_test::{wasm_bindgen_test, wasm_bindgen_test_configure};
+use web_sys::{RtcDataChannelState, RtcIceConnectionState};
+use yew::platform::time::sleep;
+use yew::AppHandle;
+
+use super::*;
+use crate::webrtc_chat::loopback::{LoopbackFault, LoopbackManager};
+use crate::webrtc_chat::send_queue::Priority;
+use crate::webrtc_chat::whiteboard::{Item, Shape};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+type LoopbackChat = ChatModel<LoopbackManager>;
+
+fn mount() -> AppHandle<LoopbackChat> {
+    let document = gloo_utils::document();
+    let root = document.create_element("div").unwrap();
+    document.body().unwrap().append_child(&root).unwrap();
+
+    yew::Renderer::<LoopbackChat>::with_root(root).render()
+}
+
+// Lets queued component messages, simulated transitions and WebCrypto run.
+async fn settle() {
+    sleep(Duration::from_millis(50)).await;
+}
+
+fn step(chat: &AppHandle<LoopbackChat>) -> ConnectionStep {
+    chat.get_component().unwrap().connection_step.clone()
+}
+
+fn connection_state(chat: &AppHandle<LoopbackChat>) -> ConnectionState {
+    match chat.get_component().unwrap().web_rtc_manager.borrow().get_state() {
+        State::Server(connection_state) | State::Client(connection_state) => connection_state,
+        State::Default => panic!("chat has not started a connection"),
+    }
+}
+
+fn last_message(chat: &AppHandle<LoopbackChat>) -> Message {
+    chat.get_component().unwrap().messages.last().cloned().expect("a message")
+}
+
+/// Mounts a host and a guest and pairs them, see `connect`.
+async fn pair(fault: Option<LoopbackFault>) -> (AppHandle<LoopbackChat>, AppHandle<LoopbackChat>) {
+    if let Some(fault) = fault {
+        LoopbackManager::inject_fault(fault);
+    }
+
+    let host = mount();
+    let guest = mount();
+    assert!(matches!(step(&host), ConnectionStep::Welcome));
+
+    connect(&host, &guest).await;
+    (host, guest)
+}
+
+/// Walks a host and a guest through every wizard step up to the answer being
+/// accepted, asserting each intermediate step on the way.
+async fn connect(host: &AppHandle<LoopbackChat>, guest: &AppHandle<LoopbackChat>) {
+    host.send_message(Msg::SetConnectionStep(ConnectionStep::ChooseRole));
+    settle().await;
+    assert!(matches!(step(host), ConnectionStep::ChooseRole));
+
+    host.send_message(Msg::ChooseHost);
+    settle().await;
+    assert!(matches!(step(host), ConnectionStep::SharingCode));
+
+    let offer = host.get_component().unwrap().connection_code.clone().expect("an offer code");
+    host.send_message(Msg::SetConnectionStep(ConnectionStep::WaitingForAnswer));
+
+    guest.send_message(Msg::SetConnectionStep(ConnectionStep::ChooseRole));
+    guest.send_message(Msg::ChooseJoin);
+    settle().await;
+    assert!(matches!(step(guest), ConnectionStep::WaitingForConnection));
+
+    guest.send_message(Msg::UpdateInputValue(offer));
+    guest.send_message(Msg::ValidateOffer);
+    settle().await;
+    assert!(matches!(step(guest), ConnectionStep::SharingCode));
+    assert!(matches!(step(host), ConnectionStep::WaitingForAnswer));
+
+    let answer = guest.get_component().unwrap().get_serialized_offer_and_candidates().expect("an answer code");
+    host.send_message(Msg::UpdateInputValue(answer));
+    host.send_message(Msg::ValidateOffer);
+    settle().await;
+}
+
+#[wasm_bindgen_test]
+async fn wizard_connects_host_and_guest() {
+    let (host, guest) = pair(None).await;
+
+    for chat in [&host, &guest] {
+        assert!(matches!(step(chat), ConnectionStep::Connected));
+        assert!(chat.get_component().unwrap().is_chat_enabled());
+        assert_eq!(
+            connection_state(chat).ice_connection_state,
+            Some(RtcIceConnectionState::Connected)
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+async fn messages_are_exchanged_both_ways() {
+    let (host, guest) = pair(None).await;
+
+    host.send_message(Msg::UpdateInputChatValue("hello from host".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+
+    let received = last_message(&guest);
+    assert_eq!(received.content, "hello from host");
+    assert_eq!(received.sender, MessageSender::Other);
+    assert_eq!(received.id, last_message(&host).id);
+
+    guest.send_message(Msg::UpdateInputChatValue("hi host".into()));
+    guest.send_message(Msg::Send);
+    settle().await;
+
+    let received = last_message(&host);
+    assert_eq!(received.content, "hi host");
+    assert_eq!(received.sender, MessageSender::Other);
+    assert_eq!(host.get_component().unwrap().messages.len(), 2);
+    assert_eq!(guest.get_component().unwrap().messages.len(), 2);
+}
+
+#[wasm_bindgen_test]
+async fn ice_failure_never_reaches_chat() {
+    let (host, guest) = pair(Some(LoopbackFault::IceFailed)).await;
+
+    assert!(matches!(step(&host), ConnectionStep::WaitingForAnswer));
+    assert!(matches!(step(&guest), ConnectionStep::SharingCode));
+
+    for chat in [&host, &guest] {
+        let connection_state = connection_state(chat);
+        assert_eq!(connection_state.ice_connection_state, Some(RtcIceConnectionState::Failed));
+        assert_eq!(connection_state.data_channel_state, None);
+        assert!(!chat.get_component().unwrap().is_chat_enabled());
+    }
+}
+
+#[wasm_bindgen_test]
+async fn closed_channel_disables_chat() {
+    let (host, guest) = pair(Some(LoopbackFault::ChannelClosed)).await;
+    settle().await;
+
+    for chat in [&host, &guest] {
+        assert_eq!(connection_state(chat).data_channel_state, Some(RtcDataChannelState::Closed));
+        assert!(!chat.get_component().unwrap().is_chat_enabled());
+    }
+
+    host.send_message(Msg::UpdateInputChatValue("anyone there?".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+
+    assert!(guest.get_component().unwrap().messages.is_empty());
+}
+
+#[wasm_bindgen_test]
+async fn connection_failures_show_toasts() {
+    let guest = mount();
+    guest.send_message(Msg::SetConnectionStep(ConnectionStep::ChooseRole));
+    guest.send_message(Msg::ChooseJoin);
+    guest.send_message(Msg::UpdateInputValue("not a connection code".into()));
+    guest.send_message(Msg::ValidateOffer);
+    guest.send_message(Msg::ValidateOffer);
+    settle().await;
+
+    let component = guest.get_component().unwrap();
+    assert!(matches!(component.connection_step, ConnectionStep::WaitingForConnection));
+    assert_eq!(component.toasts.len(), 1);
+    assert!(component.toasts[0].text.starts_with("This connection code cannot be used"));
+
+    let (host, _guest) = pair(Some(LoopbackFault::ChannelClosed)).await;
+    settle().await;
+    let manager = host.get_component().unwrap().web_rtc_manager.clone();
+    assert_eq!(manager.borrow().send_message("anyone there?", Priority::Normal), Err(WebRtcError::ChannelClosed));
+}
+
+#[wasm_bindgen_test]
+async fn sharing_a_code_before_it_exists_shows_a_toast() {
+    let guest = mount();
+    guest.send_message(Msg::GenerateQRCode);
+    settle().await;
+
+    let component = guest.get_component().unwrap();
+    assert!(!component.show_qr_modal);
+    assert_eq!(component.toasts.len(), 1);
+    assert!(component.toasts[0].text.contains("not ready yet"));
+}
+
+#[wasm_bindgen_test]
+async fn dropped_ice_is_restarted_and_keeps_history() {
+    let (host, guest) = pair(Some(LoopbackFault::IceDropped)).await;
+    settle().await;
+
+    for chat in [&host, &guest] {
+        assert!(matches!(
+            chat.get_component().unwrap().reconnect,
+            Some(Reconnect::Waiting { attempt: 0, .. })
+        ));
+    }
+
+    // The first restart fires after the initial one second backoff
+    sleep(Duration::from_millis(1_200)).await;
+
+    for chat in [&host, &guest] {
+        assert!(chat.get_component().unwrap().reconnect.is_none());
+        assert!(chat.get_component().unwrap().is_chat_enabled());
+    }
+
+    host.send_message(Msg::UpdateInputChatValue("still here".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+    assert_eq!(last_message(&guest).content, "still here");
+}
+
+#[wasm_bindgen_test]
+async fn re_pairing_keeps_messages() {
+    let (host, guest) = pair(Some(LoopbackFault::ChannelClosed)).await;
+    host.send_message(Msg::NewMessage(Message::new("before".into(), MessageSender::Me)));
+    settle().await;
+    assert_eq!(host.get_component().unwrap().reconnect, Some(Reconnect::GaveUp));
+
+    host.send_message(Msg::RePair);
+    guest.send_message(Msg::RePair);
+    settle().await;
+
+    assert!(matches!(step(&host), ConnectionStep::SharingCode));
+    assert!(matches!(step(&guest), ConnectionStep::WaitingForConnection));
+    assert_eq!(last_message(&host).content, "before");
+}
+
+#[wasm_bindgen_test]
+async fn host_stays_host_after_a_failed_invitation() {
+    let (host, _guest) = pair(None).await;
+
+    host.send_message(Msg::InviteParticipant);
+    host.send_message(Msg::ResetWebRTC);
+    settle().await;
+
+    let component = host.get_component().unwrap();
+    assert!(matches!(component.web_rtc_manager.borrow().get_state(), State::Default));
+    assert!(component.is_host());
+    assert_eq!(component.my_reactor_id(), HOST_PARTICIPANT_ID);
+}
+
+/// Has an already connected host invite one more guest into the room.
+async fn join(host: &AppHandle<LoopbackChat>, nickname: &str) -> AppHandle<LoopbackChat> {
+    host.send_message(Msg::InviteParticipant);
+    settle().await;
+    assert!(matches!(step(host), ConnectionStep::SharingCode));
+
+    let offer = host.get_component().unwrap().connection_code.clone().expect("an offer code");
+    host.send_message(Msg::SetConnectionStep(ConnectionStep::WaitingForAnswer));
+
+    let guest = mount();
+    guest.send_message(Msg::SetConnectionStep(ConnectionStep::ChooseRole));
+    guest.send_message(Msg::UpdateNickname(nickname.into()));
+    guest.send_message(Msg::ChooseJoin);
+    guest.send_message(Msg::UpdateInputValue(offer));
+    guest.send_message(Msg::ValidateOffer);
+    settle().await;
+
+    let answer = guest.get_component().unwrap().get_serialized_offer_and_candidates().expect("an answer code");
+    host.send_message(Msg::UpdateInputValue(answer));
+    host.send_message(Msg::ValidateOffer);
+    settle().await;
+
+    guest
+}
+
+#[wasm_bindgen_test]
+async fn room_relays_messages_between_guests() {
+    let (host, alice) = pair(None).await;
+    let bob = join(&host, "Bob").await;
+
+    for chat in [&host, &alice, &bob] {
+        assert!(matches!(step(chat), ConnectionStep::Connected));
+        let participants = chat.get_component().unwrap().participants();
+        assert_eq!(participants.len(), 3);
+        assert!(participants.iter().all(|p| p.status == ParticipantStatus::Connected));
+    }
+
+    alice.send_message(Msg::UpdateInputChatValue("hi all".into()));
+    alice.send_message(Msg::Send);
+    settle().await;
+
+    let alice_id = alice.get_component().unwrap().my_participant_id.clone();
+    for chat in [&host, &bob] {
+        let received = last_message(chat);
+        assert_eq!(received.content, "hi all");
+        assert_eq!(received.author, alice_id);
+    }
+
+    let bob_component = bob.get_component().unwrap();
+    let me = bob_component.participants().into_iter().find(|p| bob_component.is_me(p)).unwrap();
+    assert_eq!(me.nickname, "Bob");
+}
+
+#[wasm_bindgen_test]
+async fn host_takes_authors_from_the_link() {
+    let (host, alice) = pair(None).await;
+    let bob = join(&host, "Bob").await;
+    let alice_id = alice.get_component().unwrap().my_participant_id.clone().unwrap();
+    let bob_id = bob.get_component().unwrap().my_participant_id.clone().unwrap();
+    let bob_link = host
+        .get_component()
+        .unwrap()
+        .peers
+        .iter()
+        .map(|peer| peer.id)
+        .find(|peer_id| room::participant_id(*peer_id) == bob_id)
+        .unwrap();
+
+    let mut forged = Message::new("as alice".into(), MessageSender::Other);
+    forged.author = Some(alice_id.clone());
+    host.send_message(Msg::ReceiveSecureFrame(bob_link, forged.to_frame()));
+    host.send_message(Msg::ReceiveSecureFrame(bob_link, Frame::Roster { you: alice_id, participants: vec![] }));
+    settle().await;
+
+    for chat in [&host, &alice] {
+        assert_eq!(last_message(chat).author.as_deref(), Some(bob_id.as_str()));
+    }
+    let component = host.get_component().unwrap();
+    assert_eq!(component.my_reactor_id(), HOST_PARTICIPANT_ID);
+    assert_eq!(component.participants().len(), 3);
+}
+
+#[wasm_bindgen_test]
+async fn colliding_offers_settle_on_the_host_offer() {
+    let (host, guest) = pair(None).await;
+    let managers = [&host, &guest].map(|chat| chat.get_component().unwrap().peers[0].manager.clone());
+
+    // Both offer before either sees the other's; plain frames would be dropped
+    for manager in &managers {
+        LoopbackManager::negotiate(manager);
+    }
+    settle().await;
+
+    for manager in &managers {
+        let manager = manager.borrow();
+        assert!(!manager.is_negotiating());
+        assert_eq!(manager.renegotiations(), 1);
+    }
+}
+
+fn safety_number(chat: &AppHandle<LoopbackChat>) -> Option<String> {
+    chat.get_component().unwrap().peers[0].secure.safety_number()
+}
+
+#[wasm_bindgen_test]
+async fn both_sides_agree_on_the_safety_number() {
+    let (host, guest) = pair(None).await;
+
+    let number = safety_number(&host).expect("a safety number");
+    assert_eq!(safety_number(&guest), Some(number));
+
+    let peer_id = host.get_component().unwrap().peers[0].id;
+    assert!(!host.get_component().unwrap().peers[0].verified);
+    host.send_message(Msg::MarkVerified(peer_id));
+    settle().await;
+    assert!(host.get_component().unwrap().peers[0].verified);
+}
+
+#[wasm_bindgen_test]
+async fn unencrypted_frames_are_dropped() {
+    let (host, guest) = pair(None).await;
+
+    let peer_id = guest.get_component().unwrap().peers[0].id;
+    let forged = Message::new("forged".into(), MessageSender::Other).to_frame();
+    guest.send_message(Msg::ReceiveFrame(peer_id, forged));
+    settle().await;
+    assert!(guest.get_component().unwrap().messages.is_empty());
+
+    host.send_message(Msg::UpdateInputChatValue("sealed".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+    assert_eq!(last_message(&guest).content, "sealed");
+}
+
+#[wasm_bindgen_test]
+async fn messages_are_kept_in_history_after_disconnect() {
+    let (host, guest) = pair(None).await;
+
+    host.send_message(Msg::UpdateInputChatValue("remember me".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+
+    let conversation_id = guest
+        .get_component()
+        .unwrap()
+        .conversation_id
+        .clone()
+        .expect("the host identified itself");
+
+    guest.send_message(Msg::Disconnect);
+    settle().await;
+    assert!(guest.get_component().unwrap().messages.is_empty());
+
+    let stored = history::load_messages(&conversation_id).await.unwrap();
+    assert!(stored.iter().any(|message| message.content == "remember me"));
+}
+
+#[wasm_bindgen_test]
+async fn messages_restored_from_history_can_still_be_edited() {
+    let (host, guest) = pair(None).await;
+
+    guest.send_message(Msg::UpdateInputChatValue("draft".into()));
+    guest.send_message(Msg::Send);
+    settle().await;
+    let message_id = last_message(&guest).id;
+
+    // Pairing again hands the guest a new participant id on the host
+    for chat in [&host, &guest] {
+        chat.send_message(Msg::Disconnect);
+    }
+    settle().await;
+    connect(&host, &guest).await;
+    settle().await;
+
+    guest.send_message(Msg::StartEdit(message_id.clone()));
+    guest.send_message(Msg::UpdateInputChatValue("final".into()));
+    guest.send_message(Msg::Send);
+    settle().await;
+
+    for chat in [&host, &guest] {
+        let component = chat.get_component().unwrap();
+        let message = component.messages.iter().find(|message| message.id == message_id).expect("restored");
+        assert_eq!(message.content, "final");
+    }
+}
+
+#[wasm_bindgen_test]
+async fn typing_and_receipts_reach_the_sender() {
+    let (host, guest) = pair(None).await;
+
+    guest.send_message(Msg::UpdateInputChatValue("typ".into()));
+    guest.send_message(Msg::StartTyping);
+    settle().await;
+    assert_eq!(
+        host.get_component().unwrap().typing_label().as_deref(),
+        Some("Friend is typing…")
+    );
+
+    guest.send_message(Msg::Send);
+    settle().await;
+    assert_eq!(host.get_component().unwrap().typing_label(), None);
+
+    // Both tabs are visible and showing the chat, so the message is read at once
+    assert_eq!(last_message(&guest).receipt, Some(ReceiptKind::Read));
+}
+
+#[wasm_bindgen_test]
+async fn edits_deletes_and_reactions_are_synced() {
+    let (host, guest) = pair(None).await;
+
+    host.send_message(Msg::UpdateInputChatValue("helo".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+    let message_id = last_message(&host).id;
+
+    host.send_message(Msg::StartEdit(message_id.clone()));
+    host.send_message(Msg::UpdateInputChatValue("hello".into()));
+    host.send_message(Msg::Send);
+    guest.send_message(Msg::ToggleReaction(message_id.clone(), "👍".into()));
+    settle().await;
+
+    for chat in [&host, &guest] {
+        let message = last_message(chat);
+        assert_eq!(message.content, "hello");
+        assert!(message.edited_at.is_some());
+        assert_eq!(message.reactions["👍"].len(), 1);
+    }
+
+    // An older edit arriving late must not win over the newer one
+    let peer_id = guest.get_component().unwrap().peers[0].id;
+    let stale = Frame::Edit { message_id: message_id.clone(), content: "stale".into(), edited_at: 1, author: None };
+    guest.send_message(Msg::ReceiveSecureFrame(peer_id, stale));
+    settle().await;
+    assert_eq!(last_message(&guest).content, "hello");
+
+    // Only the author may change a message
+    let forged = Frame::Delete { message_id: message_id.clone(), author: None };
+    host.send_message(Msg::ReceiveSecureFrame(host.get_component().unwrap().peers[0].id, forged));
+    settle().await;
+    assert!(!last_message(&host).deleted);
+
+    // A deletion wins over any edit, however new
+    let mut message = last_message(&host);
+    assert!(message.apply_delete());
+    assert!(!message.apply_edit("after".into(), u64::MAX));
+}
+
+#[wasm_bindgen_test]
+async fn guests_cannot_edit_messages_they_do_not_own() {
+    let (host, alice) = pair(None).await;
+    let bob = join(&host, "Bob").await;
+
+    alice.send_message(Msg::UpdateInputChatValue("mine".into()));
+    alice.send_message(Msg::Send);
+    settle().await;
+    let message_id = last_message(&alice).id;
+    let alice_id = alice.get_component().unwrap().my_participant_id.clone();
+    let bob_id = bob.get_component().unwrap().my_participant_id.clone().unwrap();
+
+    // Bob claims to be Alice on his own link to the host
+    let forged = [
+        Frame::Edit { message_id: message_id.clone(), content: "yours".into(), edited_at: u64::MAX, author: alice_id.clone() },
+        Frame::Delete { message_id: message_id.clone(), author: alice_id.clone() },
+        Frame::Reaction { message_id: message_id.clone(), emoji: "👎".into(), add: true, author: alice_id },
+    ];
+    for frame in forged {
+        bob.get_component().unwrap().peers[0].send(&frame);
+    }
+    settle().await;
+
+    for chat in [&host, &alice, &bob] {
+        let message = last_message(chat);
+        assert_eq!(message.content, "mine");
+        assert!(!message.deleted && message.edited_at.is_none());
+        assert!(message.reactions["👎"].contains(&bob_id));
+    }
+}
+
+#[wasm_bindgen_test]
+async fn guests_cannot_undo_board_ops_they_do_not_own() {
+    let (host, alice) = pair(None).await;
+    let bob = join(&host, "Bob").await;
+
+    let item = Item { id: "a1".into(), shape: Shape::Line { from: [0, 0], to: [9, 9] }, color: 0, width: 2 };
+    alice.send_message(Msg::DrawOnBoard(BoardOp::Add { item }));
+    settle().await;
+    let alice_id = alice.get_component().unwrap().my_participant_id.clone();
+
+    bob.get_component().unwrap().peers[0].send(&Frame::Board { op: BoardOp::Undo { id: "a1".into() }, author: alice_id });
+    settle().await;
+
+    for chat in [&host, &alice, &bob] {
+        assert_eq!(chat.get_component().unwrap().board.visible().count(), 1);
+    }
+}
+
+#[wasm_bindgen_test]
+async fn a_new_pairing_starts_with_an_empty_board() {
+    let (host, guest) = pair(None).await;
+
+    let item = Item { id: "old".into(), shape: Shape::Line { from: [0, 0], to: [9, 9] }, color: 0, width: 2 };
+    host.send_message(Msg::DrawOnBoard(BoardOp::Add { item }));
+    settle().await;
+    assert!(guest.get_component().unwrap().board_unseen);
+
+    for chat in [&host, &guest] {
+        chat.send_message(Msg::Disconnect);
+    }
+    settle().await;
+    connect(&host, &guest).await;
+    settle().await;
+
+    for chat in [&host, &guest] {
+        let component = chat.get_component().unwrap();
+        assert_eq!(component.board.visible().count(), 0);
+        assert!(!component.board_unseen);
+    }
+}
+
+#[wasm_bindgen_test]
+fn stored_messages_keep_author_reactions_and_attachment() {
+    let mut message = Message::new("photo.png".into(), MessageSender::Other);
+    message.author = Some("peer-2".into());
+    message.attachment = Some("file_1".into());
+    message.apply_reaction("👍", "host", true);
+
+    let mut restored = Message::from_stored(message.to_stored("conversation"));
+
+    assert_eq!(restored.author.as_deref(), Some("peer-2"));
+    assert_eq!(restored.reactions, message.reactions);
+    assert_eq!(restored.attachment.as_deref(), Some("file_1"));
+    // Still a file message, so it cannot be turned into text
+    assert!(!restored.apply_edit("text".into(), 1));
+}
+
+#[wasm_bindgen_test]
+fn equal_edit_times_resolve_the_same_way_in_any_order() {
+    let mut first = Message::new("original".into(), MessageSender::Me);
+    let mut second = first.clone();
+
+    first.apply_edit("apple".into(), 10);
+    first.apply_edit("banana".into(), 10);
+    second.apply_edit("banana".into(), 10);
+    second.apply_edit("apple".into(), 10);
+
+    assert_eq!(first.content, second.content);
+}
+
+#[wasm_bindgen_test]
+async fn replies_carry_their_parent_to_the_peer() {
+    let (host, guest) = pair(None).await;
+
+    host.send_message(Msg::UpdateInputChatValue("question".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+    let question = last_message(&host).id;
+
+    guest.send_message(Msg::ReplyTo(question.clone()));
+    guest.send_message(Msg::UpdateInputChatValue("answer".into()));
+    guest.send_message(Msg::Send);
+    settle().await;
+    let answer = last_message(&guest).id;
+
+    host.send_message(Msg::ReplyTo(answer.clone()));
+    host.send_message(Msg::UpdateInputChatValue("follow-up".into()));
+    host.send_message(Msg::Send);
+    settle().await;
+
+    for chat in [&host, &guest] {
+        let component = chat.get_component().unwrap();
+        let thread: Vec<&str> = component.thread_of(&question).iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(thread, ["answer", "follow-up"]);
+        assert_eq!(component.replies_to(&question).count(), 1);
+        assert_eq!(component.replying_to, None);
+    }
+}
+
+// A steady tone, so recording works without a microphone.
+fn synthetic_audio() -> MediaStream {
+    let context = web_sys::AudioContext::new().unwrap();
+    let oscillator = context.create_oscillator().unwrap();
+    let destination = context.create_media_stream_destination().unwrap();
+    oscillator.connect_with_audio_node(&destination).unwrap();
+    oscillator.start().unwrap();
+    destination.stream()
+}
+
+#[wasm_bindgen_test]
+async fn voice_messages_arrive_with_duration_and_waveform() {
+    let (host, guest) = pair(None).await;
+
+    host.send_message(Msg::RecordFrom(synthetic_audio()));
+    sleep(Duration::from_millis(1_200)).await;
+    host.send_message(Msg::StopRecording);
+    sleep(Duration::from_millis(500)).await;
+
+    let component = guest.get_component().unwrap();
+    let transfer_id = last_message(&guest).attachment.expect("a voice attachment");
+    let transfer = component.transfers.iter().find(|t| t.id == transfer_id).unwrap();
+    let voice = transfer.voice.as_ref().expect("voice details");
+
+    assert!(transfer.is_complete());
+    assert!(transfer.download_url.is_some());
+    assert!(voice.duration_ms >= voice::MIN_VOICE_MS);
+    assert!(voice.waveform.len() <= voice::WAVEFORM_BARS);
+    assert!(host.get_component().unwrap().voice_recorder.is_none());
+}
+
+#[wasm_bindgen_test]
+async fn calls_exchange_media_and_hang_up_on_both_sides() {
+    let (host, guest) = pair(None).await;
+
+    host.send_message(Msg::CallWith(synthetic_audio()));
+    settle().await;
+    let incoming = guest.get_component().unwrap().call.as_ref().map(|call| call.phase);
+    assert_eq!(incoming, Some(CallPhase::Incoming));
+
+    guest.send_message(Msg::CallWith(synthetic_audio()));
+    settle().await;
+    for chat in [&host, &guest] {
+        let component = chat.get_component().unwrap();
+        let call = component.call.as_ref().expect("a call");
+        assert_eq!(call.phase, CallPhase::Active);
+        assert!(call.sending);
+        assert!(component.peers[0].remote_stream.is_some());
+    }
+
+    guest.send_message(Msg::ToggleMute);
+    settle().await;
+    assert!(host.get_component().unwrap().call.as_ref().unwrap().remote_muted);
+
+    host.send_message(Msg::HangUp);
+    settle().await;
+    assert!(host.get_component().unwrap().call.is_none());
+    assert!(guest.get_component().unwrap().call.is_none());
+}
+
+#[wasm_bindgen_test]
+async fn diagnostics_collect_stats_while_open() {
+    let (host, _guest) = pair(None).await;
+    host.send_message(Msg::ToggleDiagnostics);
+    settle().await;
+
+    let component = host.get_component().unwrap();
+    let stats = component.stats.values().next().and_then(|history| history.latest.clone());
+    let stats = stats.expect("stats for the peer");
+    assert_eq!(stats.local_candidate.map(|candidate| candidate.candidate_type), Some("host".into()));
+    drop(component);
+
+    host.send_message(Msg::ToggleDiagnostics);
+    settle().await;
+    assert!(host.get_component().unwrap().stats.is_empty());
+}
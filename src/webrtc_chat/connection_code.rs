@@ -0,0 +1,201 @@
+use std::str;
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::webrtc_chat::chat_model::ConnectionString;
+use crate::webrtc_chat::web_rtc_manager::{IceCandidate, OfferError};
+
+/// Marks a compact code. Legacy codes are standard base64 of a JSON object,
+/// so they always start with "ey" and can never collide with this prefix.
+const COMPACT_PREFIX: &str = "c1.";
+const CHECKSUM_LEN: usize = 4;
+// Far more than any offer needs; a pasted code cannot make us inflate more.
+const MAX_INFLATED_LEN: usize = 64 * 1024;
+
+// Lines browsers emit that a data-channel-only session can do without.
+const REDUNDANT_SDP_LINES: [&str; 3] = ["a=extmap-allow-mixed", "a=msid-semantic:", "a=ice-options:"];
+
+#[derive(Serialize, Deserialize)]
+struct SessionDescription {
+    #[serde(rename = "type")]
+    kind: String,
+    sdp: String,
+}
+
+// Short field names on purpose: this is what ends up inside the QR code.
+#[derive(Serialize, Deserialize)]
+struct CompactConnection {
+    t: String,
+    s: String,
+    #[serde(default)]
+    c: Vec<(String, String, u16)>,
+}
+
+/// Encodes an offer or answer plus its candidates as a short, URL-safe code.
+pub fn encode(connection_string: &ConnectionString) -> Result<String, OfferError> {
+    let description = serde_json::from_str::<SessionDescription>(&connection_string.offer)
+        .map_err(|_| OfferError::InvalidOffer)?;
+
+    let compact = CompactConnection {
+        t: description.kind,
+        s: strip_sdp(&description.sdp),
+        c: connection_string
+            .ice_candidates
+            .iter()
+            .map(|candidate| {
+                (
+                    candidate
+                        .candidate
+                        .trim_start_matches("candidate:")
+                        .to_string(),
+                    candidate.sdp_mid.clone(),
+                    candidate.sdp_m_line_index,
+                )
+            })
+            .collect(),
+    };
+
+    let json = serde_json::to_vec(&compact).map_err(|_| OfferError::SerializationError)?;
+    let mut bytes = miniz_oxide::deflate::compress_to_vec(&json, 9);
+    bytes.extend_from_slice(&crc32fast::hash(&bytes).to_be_bytes());
+
+    Ok(format!(
+        "{}{}",
+        COMPACT_PREFIX,
+        general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    ))
+}
+
+/// Decodes either a compact code or a legacy base64 JSON code.
+pub fn decode(code: &str) -> Result<ConnectionString, OfferError> {
+    let code: String = code.split_whitespace().collect();
+
+    match code.strip_prefix(COMPACT_PREFIX) {
+        Some(compact) => decode_compact(compact),
+        None => decode_legacy(&code),
+    }
+}
+
+fn decode_legacy(code: &str) -> Result<ConnectionString, OfferError> {
+    let bytes = general_purpose::STANDARD
+        .decode(code)
+        .map_err(|_| OfferError::InvalidBase64)?;
+    let json = str::from_utf8(&bytes).map_err(|_| OfferError::InvalidString)?;
+
+    serde_json::from_str::<ConnectionString>(json).map_err(|_| OfferError::SerializationError)
+}
+
+fn decode_compact(code: &str) -> Result<ConnectionString, OfferError> {
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(code)
+        .map_err(|_| OfferError::InvalidBase64)?;
+
+    if bytes.len() <= CHECKSUM_LEN {
+        return Err(OfferError::InvalidBase64);
+    }
+
+    let (deflated, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if crc32fast::hash(deflated).to_be_bytes() != checksum {
+        return Err(OfferError::ChecksumMismatch);
+    }
+
+    let json = miniz_oxide::inflate::decompress_to_vec_with_limit(deflated, MAX_INFLATED_LEN)
+        .map_err(|_| OfferError::SerializationError)?;
+    let compact = serde_json::from_slice::<CompactConnection>(&json)
+        .map_err(|_| OfferError::SerializationError)?;
+
+    let description = SessionDescription {
+        kind: compact.t,
+        sdp: restore_sdp(&compact.s),
+    };
+
+    Ok(ConnectionString {
+        offer: serde_json::to_string(&description).map_err(|_| OfferError::SerializationError)?,
+        ice_candidates: compact
+            .c
+            .into_iter()
+            .map(|(candidate, sdp_mid, sdp_m_line_index)| IceCandidate {
+                candidate: format!("candidate:{}", candidate),
+                sdp_mid,
+                sdp_m_line_index,
+            })
+            .collect(),
+    })
+}
+
+fn strip_sdp(sdp: &str) -> String {
+    sdp.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .filter(|line| !REDUNDANT_SDP_LINES.iter().any(|prefix| line.starts_with(prefix)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// SDP requires CRLF line endings, including after the last line.
+fn restore_sdp(sdp: &str) -> String {
+    sdp.lines().map(|line| format!("{}\r\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFER_SDP: &str = "v=0\r\no=- 4611 2 IN IP4 127.0.0.1\r\ns=-\r\nt=0 0\r\na=extmap-allow-mixed\r\n\
+        m=application 9 UDP/DTLS/SCTP webrtc-datachannel\r\na=mid:0\r\na=sctp-port:5000\r\n";
+
+    fn connection_string() -> ConnectionString {
+        ConnectionString {
+            offer: serde_json::json!({ "type": "offer", "sdp": OFFER_SDP }).to_string(),
+            ice_candidates: vec![IceCandidate {
+                candidate: "candidate:1 1 udp 2122260223 192.168.1.2 54321 typ host".to_string(),
+                sdp_mid: "0".to_string(),
+                sdp_m_line_index: 0,
+            }],
+        }
+    }
+
+    fn sdp_of(connection_string: &ConnectionString) -> String {
+        serde_json::from_str::<SessionDescription>(&connection_string.offer).unwrap().sdp
+    }
+
+    #[test]
+    fn compact_codes_round_trip() {
+        let original = connection_string();
+        let code = encode(&original).unwrap();
+        assert!(code.starts_with(COMPACT_PREFIX));
+
+        let decoded = decode(&code).unwrap();
+        assert_eq!(decoded.ice_candidates, original.ice_candidates);
+        assert_eq!(sdp_of(&decoded), OFFER_SDP.replace("a=extmap-allow-mixed\r\n", ""));
+    }
+
+    #[test]
+    fn legacy_codes_still_decode() {
+        let original = connection_string();
+        let code = general_purpose::STANDARD.encode(serde_json::to_string(&original).unwrap());
+
+        let decoded = decode(&code).unwrap();
+        assert_eq!(decoded.offer, original.offer);
+        assert_eq!(decoded.ice_candidates, original.ice_candidates);
+    }
+
+    #[test]
+    fn truncated_codes_fail_the_checksum() {
+        let code = encode(&connection_string()).unwrap();
+        // Whole base64 quanta, so what is left still decodes to bytes
+        let truncated = &code[..code.len() - 4];
+
+        assert_eq!(decode(truncated).unwrap_err(), OfferError::ChecksumMismatch);
+    }
+
+    #[test]
+    fn oversized_codes_are_not_inflated() {
+        let mut bytes = miniz_oxide::deflate::compress_to_vec(&vec![b' '; MAX_INFLATED_LEN + 1], 9);
+        bytes.extend_from_slice(&crc32fast::hash(&bytes).to_be_bytes());
+        let code = format!("{}{}", COMPACT_PREFIX, general_purpose::URL_SAFE_NO_PAD.encode(bytes));
+
+        assert_eq!(decode(&code).unwrap_err(), OfferError::SerializationError);
+    }
+}
@@ -0,0 +1,400 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use js_sys::{Array, Object, Reflect, Uint8Array};
+use sha2::{Digest, Sha256};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{console, CryptoKey, SubtleCrypto};
+use yew::Callback;
+
+use crate::webrtc_chat::protocol::{self, Frame};
+use crate::webrtc_chat::send_queue::{Priority, SendQueue};
+
+const IV_LEN: usize = 12;
+const SAFETY_NUMBER_GROUPS: usize = 4;
+
+type WireSender = Rc<dyn Fn(&Frame, Priority)>;
+
+/// Encryption for one data channel link, hop by hop.
+///
+/// In a room each guest only has a link to the host, which opens what it
+/// relays and seals it again for the next link. Each side generates an ECDH
+/// key pair, sends its public key in the clear and derives a shared AES-GCM
+/// key. Frames queued before that finishes are held back, then every frame
+/// is sealed individually and sent, most urgent first like the data
+/// channel's own queue.
+///
+/// Every sealed frame carries a sequence number that counts up per
+/// direction. It is authenticated along with the sender's public key, and a
+/// frame whose number is not above the last one opened is dropped, so frames
+/// cannot be replayed, reordered or reflected back within a session.
+pub struct SecureChannel {
+    inner: Rc<RefCell<Inner>>,
+}
+
+struct Inner {
+    private_key: Option<CryptoKey>,
+    public_key: Option<Vec<u8>>,
+    remote_public_key: Option<Vec<u8>>,
+    key: Option<CryptoKey>,
+    safety_number: Option<String>,
+    outbox: SendQueue<Frame>,
+    inbox: VecDeque<(u64, Vec<u8>, Vec<u8>)>,
+    sent_seq: u64,     // last sequence number sealed, 0 before the first
+    received_seq: u64, // last sequence number opened
+    is_deriving: bool,
+    is_sending: bool,
+    is_receiving: bool,
+    send_wire: WireSender,
+    on_frame: Callback<Frame>,
+    on_ready: Callback<String>,
+    on_drained: Callback<()>,
+}
+
+impl SecureChannel {
+    /// Starts the key exchange. `send_wire` puts a frame on the data channel
+    /// as is, with the priority of what it seals, `on_frame` gets every
+    /// decrypted frame and `on_ready` the safety number once both keys are known.
+    /// `on_drained` follows each time the last queued frame was sealed.
+    pub fn start(
+        send_wire: impl Fn(&Frame, Priority) + 'static,
+        on_frame: Callback<Frame>,
+        on_ready: Callback<String>,
+        on_drained: Callback<()>,
+    ) -> SecureChannel {
+        let inner = Rc::new(RefCell::new(Inner {
+            private_key: None,
+            public_key: None,
+            remote_public_key: None,
+            key: None,
+            safety_number: None,
+            outbox: SendQueue::default(),
+            inbox: VecDeque::new(),
+            sent_seq: 0,
+            received_seq: 0,
+            is_deriving: false,
+            is_sending: false,
+            is_receiving: false,
+            send_wire: Rc::new(send_wire),
+            on_frame,
+            on_ready,
+            on_drained,
+        }));
+
+        let task_inner = inner.clone();
+        spawn_local(async move {
+            match generate_key_pair().await {
+                Ok((private_key, public_key)) => {
+                    let send_wire = {
+                        let mut inner = task_inner.borrow_mut();
+                        inner.private_key = Some(private_key);
+                        inner.public_key = Some(public_key.clone());
+                        inner.send_wire.clone()
+                    };
+                    send_wire(&Frame::KeyExchange { public_key }, Priority::Control);
+                    derive(task_inner);
+                }
+                Err(err) => console::log_2(&"Failed to generate a key pair".into(), &err),
+            }
+        });
+
+        SecureChannel { inner }
+    }
+
+    pub fn accept_public_key(&self, public_key: Vec<u8>) {
+        {
+            let mut inner = self.inner.borrow_mut();
+            match &inner.remote_public_key {
+                // A second, different key mid-session is never legitimate
+                Some(known) if *known != public_key => {
+                    console::log_1(&"Ignoring a changed public key from the peer".into());
+                    return;
+                }
+                Some(_) => return,
+                None => inner.remote_public_key = Some(public_key),
+            }
+        }
+        derive(self.inner.clone());
+    }
+
+    pub fn send(&self, frame: Frame) {
+        self.inner.borrow_mut().outbox.push(Priority::of(&frame), frame);
+        pump_outbox(self.inner.clone());
+    }
+
+    pub fn receive(&self, seq: u64, iv: Vec<u8>, data: Vec<u8>) {
+        self.inner.borrow_mut().inbox.push_back((seq, iv, data));
+        pump_inbox(self.inner.clone());
+    }
+
+    pub fn safety_number(&self) -> Option<String> {
+        self.inner.borrow().safety_number.clone()
+    }
+
+    /// Frames not yet sealed and handed to the data channel.
+    pub fn queued(&self) -> usize {
+        let inner = self.inner.borrow();
+        inner.outbox.len() + inner.is_sending as usize
+    }
+}
+
+/// Short number both users read out to each other. The keys are sorted before
+/// hashing, so both sides of the link see the same digits.
+pub fn safety_number(local_public_key: &[u8], remote_public_key: &[u8]) -> String {
+    let (first, second) = if local_public_key <= remote_public_key {
+        (local_public_key, remote_public_key)
+    } else {
+        (remote_public_key, local_public_key)
+    };
+    let digest = Sha256::new().chain_update(first).chain_update(second).finalize();
+
+    digest
+        .chunks(5)
+        .take(SAFETY_NUMBER_GROUPS)
+        .map(|chunk| {
+            let value = chunk.iter().fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+            format!("{:05}", value % 100_000)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Data authenticated along with a sealed frame but not sent in it: who
+/// sealed it and its place in that direction's sequence.
+fn additional_data(sender_public_key: &[u8], seq: u64) -> Vec<u8> {
+    let mut data = sender_public_key.to_vec();
+    data.extend_from_slice(&seq.to_be_bytes());
+    data
+}
+
+fn derive(inner: Rc<RefCell<Inner>>) {
+    let (private_key, public_key, remote_public_key) = {
+        let mut state = inner.borrow_mut();
+        if state.key.is_some() || state.is_deriving {
+            return;
+        }
+        let (Some(private_key), Some(public_key), Some(remote_public_key)) = (
+            state.private_key.clone(),
+            state.public_key.clone(),
+            state.remote_public_key.clone(),
+        ) else {
+            return;
+        };
+        state.is_deriving = true;
+        (private_key, public_key, remote_public_key)
+    };
+
+    spawn_local(async move {
+        match derive_key(&private_key, &remote_public_key).await {
+            Ok(key) => {
+                let number = safety_number(&public_key, &remote_public_key);
+                let on_ready = {
+                    let mut state = inner.borrow_mut();
+                    state.key = Some(key);
+                    state.safety_number = Some(number.clone());
+                    state.is_deriving = false;
+                    state.on_ready.clone()
+                };
+                on_ready.emit(number);
+                pump_outbox(inner.clone());
+                pump_inbox(inner);
+            }
+            Err(err) => {
+                inner.borrow_mut().is_deriving = false;
+                console::log_2(&"Failed to derive the session key".into(), &err);
+            }
+        }
+    });
+}
+
+// One encryption in flight at a time keeps frames of the same priority in
+// the order they were sent; a receipt still overtakes queued file chunks.
+fn pump_outbox(inner: Rc<RefCell<Inner>>) {
+    let (key, seq, additional_data, frame, send_wire) = {
+        let mut state = inner.borrow_mut();
+        if state.is_sending {
+            return;
+        }
+        let (Some(key), Some(public_key)) = (state.key.clone(), state.public_key.clone()) else {
+            return;
+        };
+        let Some(frame) = state.outbox.pop() else {
+            return;
+        };
+        state.is_sending = true;
+        state.sent_seq += 1;
+        let seq = state.sent_seq;
+        (key, seq, additional_data(&public_key, seq), frame, state.send_wire.clone())
+    };
+
+    spawn_local(async move {
+        match encrypt(&key, &additional_data, &protocol::encode(&frame)).await {
+            Ok((iv, data)) => send_wire(&Frame::Encrypted { seq, iv, data }, Priority::of(&frame)),
+            Err(err) => console::log_2(&"Failed to encrypt a frame".into(), &err),
+        }
+        let on_drained = {
+            let mut state = inner.borrow_mut();
+            state.is_sending = false;
+            state.outbox.is_empty().then(|| state.on_drained.clone())
+        };
+        match on_drained {
+            Some(on_drained) => on_drained.emit(()),
+            None => pump_outbox(inner),
+        }
+    });
+}
+
+fn pump_inbox(inner: Rc<RefCell<Inner>>) {
+    let (key, seq, iv, data, additional_data, on_frame) = {
+        let mut state = inner.borrow_mut();
+        if state.is_receiving {
+            return;
+        }
+        let (Some(key), Some(remote_public_key)) = (state.key.clone(), state.remote_public_key.clone()) else {
+            return;
+        };
+        let (seq, iv, data) = loop {
+            let Some((seq, iv, data)) = state.inbox.pop_front() else {
+                return;
+            };
+            if seq > state.received_seq {
+                break (seq, iv, data);
+            }
+            console::log_1(&format!("Dropping replayed or reordered frame {}", seq).into());
+        };
+        state.is_receiving = true;
+        (key, seq, iv, data, additional_data(&remote_public_key, seq), state.on_frame.clone())
+    };
+
+    spawn_local(async move {
+        match decrypt(&key, &iv, &additional_data, &data).await {
+            Ok(plaintext) => {
+                // Only an authenticated number moves the sequence on
+                inner.borrow_mut().received_seq = seq;
+                match protocol::decode(&plaintext) {
+                    Ok(frame) => on_frame.emit(frame),
+                    Err(error) => console::log_1(&format!("Dropping frame: {:?}", error).into()),
+                }
+            }
+            // Tampered with, given another number, or sealed in another
+            // session or direction
+            Err(err) => console::log_2(&"Failed to decrypt a frame".into(), &err),
+        }
+        inner.borrow_mut().is_receiving = false;
+        pump_inbox(inner);
+    });
+}
+
+fn subtle() -> Result<SubtleCrypto, JsValue> {
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("No window"))?;
+    Ok(window.crypto()?.subtle())
+}
+
+fn algorithm(entries: &[(&str, JsValue)]) -> Result<Object, JsValue> {
+    let object = Object::new();
+    for (name, value) in entries {
+        Reflect::set(&object, &(*name).into(), value)?;
+    }
+    Ok(object)
+}
+
+fn ecdh_algorithm() -> Result<Object, JsValue> {
+    algorithm(&[("name", "ECDH".into()), ("namedCurve", "P-256".into())])
+}
+
+async fn generate_key_pair() -> Result<(CryptoKey, Vec<u8>), JsValue> {
+    let subtle = subtle()?;
+    let usages = Array::of1(&"deriveKey".into());
+
+    // The private key never leaves WebCrypto; public keys are always exportable
+    let key_pair =
+        JsFuture::from(subtle.generate_key_with_object(&ecdh_algorithm()?, false, &usages)?).await?;
+    let private_key = Reflect::get(&key_pair, &"privateKey".into())?.unchecked_into::<CryptoKey>();
+    let public_key = Reflect::get(&key_pair, &"publicKey".into())?.unchecked_into::<CryptoKey>();
+
+    let raw = JsFuture::from(subtle.export_key("raw", &public_key)?).await?;
+    Ok((private_key, Uint8Array::new(&raw).to_vec()))
+}
+
+async fn derive_key(private_key: &CryptoKey, remote_public_key: &[u8]) -> Result<CryptoKey, JsValue> {
+    let subtle = subtle()?;
+
+    let key_data: Object = Uint8Array::from(remote_public_key).into();
+    let remote_key = JsFuture::from(subtle.import_key_with_object(
+        "raw",
+        &key_data,
+        &ecdh_algorithm()?,
+        true,
+        &Array::new(),
+    )?)
+    .await?;
+
+    let derive_algorithm = algorithm(&[("name", "ECDH".into()), ("public", remote_key)])?;
+    let key_type = algorithm(&[("name", "AES-GCM".into()), ("length", 256.into())])?;
+    let usages = Array::of2(&"encrypt".into(), &"decrypt".into());
+
+    let key = JsFuture::from(subtle.derive_key_with_object_and_object(
+        &derive_algorithm,
+        private_key,
+        &key_type,
+        false,
+        &usages,
+    )?)
+    .await?;
+    Ok(key.unchecked_into())
+}
+
+fn aes_gcm_algorithm(iv: &[u8], additional_data: &[u8]) -> Result<Object, JsValue> {
+    algorithm(&[
+        ("name", "AES-GCM".into()),
+        ("iv", Uint8Array::from(iv).into()),
+        ("additionalData", Uint8Array::from(additional_data).into()),
+    ])
+}
+
+async fn encrypt(key: &CryptoKey, additional_data: &[u8], plaintext: &str) -> Result<(Vec<u8>, Vec<u8>), JsValue> {
+    let iv: [u8; IV_LEN] = rand::random();
+    let params = aes_gcm_algorithm(&iv, additional_data)?;
+
+    let sealed =
+        JsFuture::from(subtle()?.encrypt_with_object_and_u8_array(&params, key, plaintext.as_bytes())?)
+            .await?;
+    Ok((iv.to_vec(), Uint8Array::new(&sealed).to_vec()))
+}
+
+async fn decrypt(key: &CryptoKey, iv: &[u8], additional_data: &[u8], data: &[u8]) -> Result<String, JsValue> {
+    let params = aes_gcm_algorithm(iv, additional_data)?;
+
+    let opened = JsFuture::from(subtle()?.decrypt_with_object_and_u8_array(&params, key, data)?).await?;
+    String::from_utf8(Uint8Array::new(&opened).to_vec())
+        .map_err(|_| JsValue::from_str("Decrypted frame is not UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn safety_number_is_the_same_on_both_sides() {
+        let alice = [4u8, 1, 2, 3];
+        let bob = [4u8, 9, 8, 7];
+
+        let number = safety_number(&alice, &bob);
+        assert_eq!(number, safety_number(&bob, &alice));
+        assert_eq!(number.len(), SAFETY_NUMBER_GROUPS * 6 - 1);
+        assert!(number.split(' ').all(|group| group.len() == 5));
+        assert_ne!(number, safety_number(&alice, &[4u8, 9, 8, 6]));
+    }
+
+    #[test]
+    fn sealed_frames_are_bound_to_their_sender_and_number() {
+        let alice = [4u8, 1, 2, 3];
+        let bob = [4u8, 9, 8, 7];
+
+        assert_eq!(additional_data(&alice, 1), [4, 1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 1]);
+        assert_ne!(additional_data(&alice, 1), additional_data(&alice, 2));
+        assert_ne!(additional_data(&alice, 1), additional_data(&bob, 1));
+    }
+}
@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+
+use js_sys::{Array, JSON};
+use serde_json::Value;
+use wasm_bindgen::JsValue;
+
+/// How often the open diagnostics panel asks for fresh stats.
+pub const STATS_POLL_MS: u32 = 1_000;
+
+/// Samples kept for the rolling chart, one per poll.
+pub const CHART_SAMPLES: usize = 60;
+
+/// One end of the candidate pair ICE settled on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandidateInfo {
+    /// `host`, `srflx`, `prflx` or `relay`.
+    pub candidate_type: String,
+    pub protocol: String,
+    pub address: Option<String>,
+}
+
+impl CandidateInfo {
+    /// What the candidate type means for someone debugging their network.
+    pub fn description(&self) -> &'static str {
+        match self.candidate_type.as_str() {
+            "host" => "direct, same network",
+            "srflx" | "prflx" => "direct, through NAT",
+            "relay" => "relayed through TURN",
+            _ => "unknown",
+        }
+    }
+}
+
+/// What `getStats()` says about one peer connection at one moment.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConnectionStats {
+    /// Milliseconds since the epoch.
+    pub timestamp: f64,
+    pub local_candidate: Option<CandidateInfo>,
+    pub remote_candidate: Option<CandidateInfo>,
+    pub rtt_ms: Option<f64>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Bytes queued on the data channel that have not been sent yet.
+    pub buffered_amount: u32,
+}
+
+impl ConnectionStats {
+    /// Picks the selected candidate pair out of the report entries. Chrome
+    /// names it on the transport entry, Firefox flags the pair itself.
+    pub fn from_report(entries: &[Value], timestamp: f64, buffered_amount: u32) -> ConnectionStats {
+        let of_type = |kind: &'static str| entries.iter().filter(move |entry| entry["type"] == kind);
+        let by_id = |id: &Value| entries.iter().find(|entry| !id.is_null() && entry["id"] == *id);
+
+        let selected_id = of_type("transport")
+            .map(|transport| &transport["selectedCandidatePairId"])
+            .find(|id| id.is_string());
+        let pair = selected_id.and_then(by_id).or_else(|| {
+            of_type("candidate-pair").find(|pair| {
+                pair["selected"] == true || (pair["nominated"] == true && pair["state"] == "succeeded")
+            })
+        });
+
+        let Some(pair) = pair else {
+            return ConnectionStats { timestamp, buffered_amount, ..ConnectionStats::default() };
+        };
+
+        ConnectionStats {
+            timestamp,
+            local_candidate: by_id(&pair["localCandidateId"]).and_then(candidate_info),
+            remote_candidate: by_id(&pair["remoteCandidateId"]).and_then(candidate_info),
+            rtt_ms: pair["currentRoundTripTime"].as_f64().map(|seconds| seconds * 1000.0),
+            bytes_sent: pair["bytesSent"].as_u64().unwrap_or_default(),
+            bytes_received: pair["bytesReceived"].as_u64().unwrap_or_default(),
+            buffered_amount,
+        }
+    }
+}
+
+/// One point of the rolling chart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsSample {
+    pub rtt_ms: Option<f64>,
+    pub send_kbps: f64,
+    pub receive_kbps: f64,
+    pub buffered_amount: u32,
+}
+
+/// The latest stats of a peer plus the last `CHART_SAMPLES` rates derived
+/// from them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsHistory {
+    pub latest: Option<ConnectionStats>,
+    pub samples: VecDeque<StatsSample>,
+}
+
+impl StatsHistory {
+    pub fn push(&mut self, stats: ConnectionStats) {
+        // Byte counters only grow, so a drop means the connection was replaced
+        let (send_kbps, receive_kbps) = match &self.latest {
+            Some(previous) if stats.timestamp > previous.timestamp => {
+                let seconds = (stats.timestamp - previous.timestamp) / 1000.0;
+                let kbps = |now: u64, before: u64| now.saturating_sub(before) as f64 * 8.0 / 1000.0 / seconds;
+                (kbps(stats.bytes_sent, previous.bytes_sent), kbps(stats.bytes_received, previous.bytes_received))
+            }
+            _ => (0.0, 0.0),
+        };
+
+        self.samples.push_back(StatsSample {
+            rtt_ms: stats.rtt_ms,
+            send_kbps,
+            receive_kbps,
+            buffered_amount: stats.buffered_amount,
+        });
+        while self.samples.len() > CHART_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.latest = Some(stats);
+    }
+
+    /// One series of the chart, oldest first.
+    pub fn series(&self, value: impl Fn(&StatsSample) -> f64) -> Vec<f64> {
+        self.samples.iter().map(value).collect()
+    }
+}
+
+/// SVG polyline points for `values`, scaled so `max` touches the top and
+/// the newest value sits at the right edge.
+pub fn chart_points(values: &[f64], max: f64, width: f64, height: f64) -> String {
+    let step = width / (CHART_SAMPLES - 1) as f64;
+    let offset = CHART_SAMPLES.saturating_sub(values.len()) as f64 * step;
+
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let y = if max > 0.0 { height - value.clamp(0.0, max) / max * height } else { height };
+            format!("{:.1},{:.1}", offset + index as f64 * step, y)
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// "1.2 MB" style byte counts.
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{} B", bytes),
+        1_000..1_000_000 => format!("{:.1} kB", bytes as f64 / 1e3),
+        1_000_000..1_000_000_000 => format!("{:.1} MB", bytes as f64 / 1e6),
+        _ => format!("{:.2} GB", bytes as f64 / 1e9),
+    }
+}
+
+/// Turns the maplike `RTCStatsReport` into plain JSON entries.
+pub fn report_entries(report: &JsValue) -> Result<Vec<Value>, JsValue> {
+    let json = String::from(JSON::stringify(&Array::from(report))?);
+    let entries: Vec<(String, Value)> =
+        serde_json::from_str(&json).map_err(|err| JsValue::from_str(&err.to_string()))?;
+    Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+}
+
+fn candidate_info(entry: &Value) -> Option<CandidateInfo> {
+    Some(CandidateInfo {
+        candidate_type: entry["candidateType"].as_str()?.to_string(),
+        protocol: entry["protocol"].as_str().unwrap_or("udp").to_string(),
+        address: entry["address"].as_str().or(entry["ip"].as_str()).map(String::from),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn report(pair_flags: Value) -> Vec<Value> {
+        let mut pair = json!({
+            "id": "CP1", "type": "candidate-pair", "localCandidateId": "L1", "remoteCandidateId": "R1",
+            "currentRoundTripTime": 0.042, "bytesSent": 1200, "bytesReceived": 3400,
+        });
+        pair.as_object_mut().unwrap().extend(pair_flags.as_object().unwrap().clone());
+
+        vec![
+            json!({ "id": "CP0", "type": "candidate-pair", "localCandidateId": "L0", "remoteCandidateId": "R1" }),
+            pair,
+            json!({ "id": "L1", "type": "local-candidate", "candidateType": "relay", "protocol": "udp", "address": "203.0.113.7" }),
+            json!({ "id": "R1", "type": "remote-candidate", "candidateType": "srflx", "protocol": "udp" }),
+        ]
+    }
+
+    #[test]
+    fn selected_pair_is_found_on_the_transport_or_the_pair() {
+        let mut chrome = report(json!({}));
+        chrome.push(json!({ "id": "T1", "type": "transport", "selectedCandidatePairId": "CP1" }));
+        let firefox = report(json!({ "selected": true }));
+
+        for entries in [chrome, firefox] {
+            let stats = ConnectionStats::from_report(&entries, 1.0, 16);
+            assert_eq!(stats.local_candidate.unwrap().candidate_type, "relay");
+            assert_eq!(stats.remote_candidate.unwrap().description(), "direct, through NAT");
+            assert_eq!(stats.rtt_ms, Some(42.0));
+            assert_eq!((stats.bytes_sent, stats.bytes_received, stats.buffered_amount), (1200, 3400, 16));
+        }
+
+        let unconnected = ConnectionStats::from_report(&report(json!({})), 1.0, 0);
+        assert_eq!(unconnected.local_candidate, None);
+    }
+
+    #[test]
+    fn history_derives_rates_and_keeps_a_window() {
+        let mut history = StatsHistory::default();
+        for second in 0..=CHART_SAMPLES as u64 {
+            history.push(ConnectionStats {
+                timestamp: second as f64 * 1000.0,
+                bytes_sent: second * 1000,
+                ..ConnectionStats::default()
+            });
+        }
+
+        assert_eq!(history.samples.len(), CHART_SAMPLES);
+        assert_eq!(history.samples.back().unwrap().send_kbps, 8.0);
+        assert_eq!(chart_points(&[0.0, 5.0, 10.0], 10.0, 59.0, 20.0), "57.0,20.0 58.0,10.0 59.0,0.0");
+        assert_eq!(format_bytes(1_500_000), "1.5 MB");
+    }
+}
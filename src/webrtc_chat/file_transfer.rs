@@ -0,0 +1,196 @@
+use std::collections::VecDeque;
+
+use rand::Rng;
+use web_sys::Url;
+
+use crate::utils::file_sharing::{FileHandler, FileInfo};
+use crate::webrtc_chat::protocol::Frame;
+use crate::webrtc_chat::voice::VoiceInfo;
+
+pub const MAX_FILE_SIZE_MB: u64 = 25;
+
+// Raw bytes per chunk. Base64 plus the envelope keeps each frame well under
+// the 64 KiB message size that browsers reliably accept on a data channel.
+pub const FILE_CHUNK_SIZE: usize = 16 * 1024;
+
+/// A file moving over the data channel, tracked the same way on both sides.
+///
+/// Outgoing transfers drain `pending_chunks`; incoming ones fill `received`
+/// until every chunk listed in the manifest has arrived.
+pub struct FileTransfer {
+    pub id: String,
+    pub info: FileInfo,
+    pub chunk_count: u32,
+    pub completed_chunks: u32,
+    pub download_url: Option<String>,
+    pub voice: Option<VoiceInfo>,
+    pending_chunks: VecDeque<Vec<u8>>,
+    received: Vec<Option<Vec<u8>>>,
+}
+
+impl FileTransfer {
+    pub fn outgoing(info: FileInfo) -> FileTransfer {
+        let pending_chunks: VecDeque<Vec<u8>> =
+            FileHandler::chunk_file(&info, FILE_CHUNK_SIZE).into();
+        let id = format!(
+            "file_{}_{:08x}",
+            js_sys::Date::now() as u64,
+            rand::rng().random::<u32>()
+        );
+
+        FileTransfer {
+            id,
+            chunk_count: pending_chunks.len() as u32,
+            completed_chunks: 0,
+            // Shown as a preview while it is sent
+            download_url: info.is_image().then(|| FileHandler::create_download_url(&info).ok()).flatten(),
+            voice: None,
+            info,
+            pending_chunks,
+            received: Vec::new(),
+        }
+    }
+
+    /// Checks the manifest a peer sent before reserving room for its chunks.
+    pub fn incoming(
+        id: String,
+        name: String,
+        size: u64,
+        file_type: String,
+        chunk_count: u32,
+    ) -> Result<FileTransfer, String> {
+        if size > MAX_FILE_SIZE_MB * 1024 * 1024 {
+            return Err(format!(
+                "\"{}\" is larger than the {} MB limit and was not accepted",
+                name, MAX_FILE_SIZE_MB
+            ));
+        }
+        if u64::from(chunk_count) != size.div_ceil(FILE_CHUNK_SIZE as u64) {
+            return Err(format!("Transfer {} announced {} chunks for {} bytes", id, chunk_count, size));
+        }
+
+        Ok(FileTransfer {
+            id,
+            info: FileInfo {
+                name,
+                size,
+                file_type,
+                data: Vec::new(),
+            },
+            chunk_count,
+            completed_chunks: 0,
+            download_url: None,
+            voice: None,
+            pending_chunks: VecDeque::new(),
+            received: vec![None; chunk_count as usize],
+        })
+    }
+
+    /// A recording we made; it can be played back right away.
+    pub fn outgoing_voice(info: FileInfo, voice: VoiceInfo) -> Result<FileTransfer, String> {
+        let download_url = FileHandler::create_download_url(&info)?;
+        let mut transfer = FileTransfer::outgoing(info);
+        transfer.download_url = Some(download_url);
+        transfer.voice = Some(voice);
+        Ok(transfer)
+    }
+
+    pub fn manifest(&self) -> Frame {
+        Frame::FileManifest {
+            transfer_id: self.id.clone(),
+            name: self.info.name.clone(),
+            size: self.info.size,
+            file_type: self.info.file_type.clone(),
+            chunk_count: self.chunk_count,
+            voice: self.voice.clone(),
+        }
+    }
+
+    pub fn next_chunk(&mut self) -> Option<Frame> {
+        let data = self.pending_chunks.pop_front()?;
+        let index = self.completed_chunks;
+        self.completed_chunks += 1;
+
+        Some(Frame::FileChunk {
+            transfer_id: self.id.clone(),
+            index,
+            data,
+        })
+    }
+
+    /// Stores a chunk and, once the last one is in, rebuilds the file and
+    /// creates its download URL. Returns an error for chunks the manifest
+    /// did not announce.
+    pub fn accept_chunk(&mut self, index: u32, data: Vec<u8>) -> Result<(), String> {
+        let slot = self
+            .received
+            .get_mut(index as usize)
+            .ok_or_else(|| format!("Chunk {} is outside of transfer {}", index, self.id))?;
+
+        if slot.replace(data).is_none() {
+            self.completed_chunks += 1;
+        }
+
+        if self.is_complete() && self.download_url.is_none() {
+            let chunks = self.received.iter_mut().filter_map(Option::take).collect();
+            self.info.data = FileHandler::reconstruct_file(chunks);
+            self.download_url = Some(FileHandler::create_download_url(&self.info)?);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed_chunks >= self.chunk_count
+    }
+
+    pub fn progress_percent(&self) -> u32 {
+        if self.chunk_count == 0 {
+            return 100;
+        }
+
+        self.completed_chunks * 100 / self.chunk_count
+    }
+}
+
+impl Drop for FileTransfer {
+    // The file stays in memory for as long as its object URL exists
+    fn drop(&mut self) {
+        if let Some(url) = &self.download_url {
+            let _ = Url::revoke_object_url(url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(size: u64, chunk_count: u32) -> Result<FileTransfer, String> {
+        FileTransfer::incoming("t".into(), "f.bin".into(), size, "application/octet-stream".into(), chunk_count)
+    }
+
+    #[test]
+    fn manifests_must_match_the_chunking() {
+        let chunk = FILE_CHUNK_SIZE as u64;
+        assert!(manifest(chunk, 1).is_ok());
+        assert!(manifest(chunk + 1, 2).is_ok());
+        assert!(manifest(chunk + 1, 1).is_err());
+        assert!(manifest(1, u32::MAX).is_err());
+    }
+
+    #[test]
+    fn oversized_manifests_are_rejected() {
+        let limit = MAX_FILE_SIZE_MB * 1024 * 1024;
+        let chunks = |size: u64| size.div_ceil(FILE_CHUNK_SIZE as u64) as u32;
+        assert!(manifest(limit, chunks(limit)).is_ok());
+        assert!(manifest(limit + 1, chunks(limit + 1)).is_err());
+    }
+
+    #[test]
+    fn chunks_outside_the_manifest_are_rejected() {
+        let mut transfer = manifest(10, 1).unwrap();
+        assert!(transfer.accept_chunk(1, vec![0; 10]).is_err());
+        assert_eq!(transfer.progress_percent(), 0);
+    }
+}
@@ -0,0 +1,218 @@
+use gloo_storage::{LocalStorage, Storage};
+use js_sys::{Array, Function, Promise, JSON};
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    console, Event, IdbDatabase, IdbObjectStoreParameters, IdbRequest, IdbTransaction, IdbTransactionMode,
+};
+
+use crate::webrtc_chat::chat_model::MessageSender;
+
+const IDENTITY_KEY: &str = "webrtc_chat_identity";
+
+const DB_NAME: &str = "webrtc_chat_history";
+const DB_VERSION: u32 = 1;
+const CONVERSATIONS: &str = "conversations";
+const MESSAGES: &str = "messages";
+const BY_CONVERSATION: &str = "by_conversation";
+
+/// One chat partner, keyed by the identity they sent when connecting.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub last_activity: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StoredMessage {
+    pub conversation_id: String,
+    pub id: String,
+    pub sender: MessageSender,
+    pub timestamp: u64,
+    pub content: String,
+    #[serde(default)]
+    pub edited_at: Option<u64>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    /// Participant id of who sent it, for messages from others in a room.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Emoji to the participant ids that reacted with it.
+    #[serde(default)]
+    pub reactions: BTreeMap<String, BTreeSet<String>>,
+    /// Id of the transfer the message showed. The file itself is not kept,
+    /// but the message stays a file message that cannot be edited.
+    #[serde(default)]
+    pub attachment: Option<String>,
+}
+
+/// Random id for this browser, created on first use and kept in local storage
+/// so the other side can find our shared history again after a reload.
+pub fn local_identity() -> String {
+    LocalStorage::get::<String>(IDENTITY_KEY).unwrap_or_else(|_| {
+        let identity = format!("id_{:016x}", rand::random::<u64>());
+        if LocalStorage::set(IDENTITY_KEY, identity.clone()).is_err() {
+            console::log_1(&"Could not store the chat identity, history will not survive a reload".into());
+        }
+        identity
+    })
+}
+
+pub async fn save(conversation: &Conversation, messages: &[StoredMessage]) -> Result<(), JsValue> {
+    let db = open().await?;
+    let transaction = db.transaction_with_str_sequence_and_mode(
+        &Array::of2(&CONVERSATIONS.into(), &MESSAGES.into()),
+        IdbTransactionMode::Readwrite,
+    )?;
+
+    transaction.object_store(CONVERSATIONS)?.put(&to_js(conversation)?)?;
+    let store = transaction.object_store(MESSAGES)?;
+    for message in messages {
+        store.put(&to_js(message)?)?;
+    }
+
+    transaction_done(&transaction).await
+}
+
+/// Most recently active first.
+pub async fn load_conversations() -> Result<Vec<Conversation>, JsValue> {
+    let db = open().await?;
+    let transaction = db.transaction_with_str(CONVERSATIONS)?;
+    let all = request_done(&transaction.object_store(CONVERSATIONS)?.get_all()?).await?;
+
+    let mut conversations: Vec<Conversation> = from_js_array(&all)?;
+    conversations.sort_by_key(|conversation| std::cmp::Reverse(conversation.last_activity));
+    Ok(conversations)
+}
+
+/// Oldest first.
+pub async fn load_messages(conversation_id: &str) -> Result<Vec<StoredMessage>, JsValue> {
+    let db = open().await?;
+    let transaction = db.transaction_with_str(MESSAGES)?;
+    let index = transaction.object_store(MESSAGES)?.index(BY_CONVERSATION)?;
+    let all = request_done(&index.get_all_with_key(&conversation_id.into())?).await?;
+
+    let mut messages: Vec<StoredMessage> = from_js_array(&all)?;
+    messages.sort_by_key(|message| message.timestamp);
+    Ok(messages)
+}
+
+pub async fn load_all_messages() -> Result<Vec<StoredMessage>, JsValue> {
+    let db = open().await?;
+    let transaction = db.transaction_with_str(MESSAGES)?;
+    let all = request_done(&transaction.object_store(MESSAGES)?.get_all()?).await?;
+
+    let mut messages: Vec<StoredMessage> = from_js_array(&all)?;
+    messages.sort_by_key(|message| message.timestamp);
+    Ok(messages)
+}
+
+pub async fn delete_conversation(conversation_id: &str) -> Result<(), JsValue> {
+    let db = open().await?;
+    let transaction = db.transaction_with_str_sequence_and_mode(
+        &Array::of2(&CONVERSATIONS.into(), &MESSAGES.into()),
+        IdbTransactionMode::Readwrite,
+    )?;
+
+    transaction.object_store(CONVERSATIONS)?.delete(&conversation_id.into())?;
+    let store = transaction.object_store(MESSAGES)?;
+    let keys = request_done(&store.index(BY_CONVERSATION)?.get_all_keys_with_key(&conversation_id.into())?).await?;
+    for key in keys.unchecked_into::<Array>().iter() {
+        store.delete(&key)?;
+    }
+
+    transaction_done(&transaction).await
+}
+
+async fn open() -> Result<IdbDatabase, JsValue> {
+    let factory = web_sys::window()
+        .ok_or_else(|| JsValue::from_str("No window"))?
+        .indexed_db()?
+        .ok_or_else(|| JsValue::from_str("IndexedDB is not available"))?;
+    let request = factory.open_with_u32(DB_NAME, DB_VERSION)?;
+
+    let upgrade_request = request.clone();
+    let on_upgrade_needed = Closure::once(move |_: Event| {
+        let Ok(db) = upgrade_request.result() else {
+            return;
+        };
+        let db = db.unchecked_into::<IdbDatabase>();
+
+        let conversations = IdbObjectStoreParameters::new();
+        conversations.set_key_path(&"id".into());
+        let messages = IdbObjectStoreParameters::new();
+        messages.set_key_path(&Array::of2(&"conversation_id".into(), &"id".into()));
+
+        let created = db
+            .create_object_store_with_optional_parameters(CONVERSATIONS, &conversations)
+            .and_then(|_| db.create_object_store_with_optional_parameters(MESSAGES, &messages))
+            .and_then(|store| store.create_index_with_str(BY_CONVERSATION, "conversation_id"));
+        if let Err(err) = created {
+            console::log_2(&"Failed to create the history database".into(), &err);
+        }
+    });
+    request.set_onupgradeneeded(Some(on_upgrade_needed.as_ref().unchecked_ref()));
+
+    // The upgrade handler only runs before success, so it can be dropped after
+    let db = request_done(&request).await?;
+    Ok(db.unchecked_into())
+}
+
+fn request_done(request: &IdbRequest) -> JsFuture {
+    let request = request.clone();
+    JsFuture::from(Promise::new(&mut |resolve: Function, reject: Function| {
+        let success_request = request.clone();
+        let error_request = request.clone();
+        request.set_onsuccess(Some(
+            Closure::once_into_js(move |_: Event| {
+                let _ = resolve.call1(&JsValue::NULL, &success_request.result().unwrap_or(JsValue::UNDEFINED));
+            })
+            .unchecked_ref(),
+        ));
+        request.set_onerror(Some(
+            Closure::once_into_js(move |_: Event| {
+                let error = error_request.error().ok().flatten().map(JsValue::from).unwrap_or(JsValue::UNDEFINED);
+                let _ = reject.call1(&JsValue::NULL, &error);
+            })
+            .unchecked_ref(),
+        ));
+    }))
+}
+
+async fn transaction_done(transaction: &IdbTransaction) -> Result<(), JsValue> {
+    let transaction = transaction.clone();
+    JsFuture::from(Promise::new(&mut |resolve: Function, reject: Function| {
+        transaction.set_oncomplete(Some(
+            Closure::once_into_js(move |_: Event| {
+                let _ = resolve.call0(&JsValue::NULL);
+            })
+            .unchecked_ref(),
+        ));
+        transaction.set_onerror(Some(
+            Closure::once_into_js(move |_: Event| {
+                let _ = reject.call1(&JsValue::NULL, &"History transaction failed".into());
+            })
+            .unchecked_ref(),
+        ));
+    }))
+    .await
+    .map(|_| ())
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(value).map_err(|err| JsValue::from_str(&err.to_string()))?;
+    JSON::parse(&json)
+}
+
+fn from_js_array<T: DeserializeOwned>(array: &JsValue) -> Result<Vec<T>, JsValue> {
+    let json = String::from(JSON::stringify(array)?);
+    serde_json::from_str(&json).map_err(|err| JsValue::from_str(&err.to_string()))
+}
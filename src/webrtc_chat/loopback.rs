@@ -0,0 +1,475 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use gloo_timers::callback::Timeout;
+use serde::{Deserialize, Serialize};
+use web_sys::{
+    console, MediaStream, RtcDataChannelState, RtcIceConnectionState, RtcIceGatheringState,
+};
+use yew::html::Scope;
+use yew::Callback;
+
+use crate::config::WebRtcDefaults;
+use crate::webrtc_chat::chat_model::{ChatModel, ConnectionString, Msg};
+use crate::webrtc_chat::connection_code;
+use crate::webrtc_chat::diagnostics::{CandidateInfo, ConnectionStats};
+use crate::webrtc_chat::protocol::{self, Frame};
+use crate::webrtc_chat::room::PeerId;
+use crate::webrtc_chat::send_queue::Priority;
+use crate::webrtc_chat::signaling::SignalData;
+use crate::webrtc_chat::web_rtc_manager::{
+    ConnectionState, IceCandidate, NetworkManager, OfferError, State, WebRtcError,
+};
+
+// Delay between simulated ICE / data channel transitions. Zero still yields to
+// the event loop, which is all the wizard needs to see each step separately.
+const SIMULATED_LATENCY_MS: u32 = 0;
+
+/// Ways a paired session can be made to fail, for exercising error paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopbackFault {
+    /// ICE checks never succeed, so the data channel never opens.
+    IceFailed,
+    /// The data channel opens and is then closed by the remote side.
+    ChannelClosed,
+    /// The session connects, then ICE drops until it is restarted.
+    IceDropped,
+}
+
+thread_local! {
+    static NEXT_SESSION_ID: Cell<u32> = const { Cell::new(1) };
+    static HOSTS: RefCell<HashMap<u32, Weak<RefCell<LoopbackManager>>>> = RefCell::new(HashMap::new());
+    static GUESTS: RefCell<HashMap<u32, Weak<RefCell<LoopbackManager>>>> = RefCell::new(HashMap::new());
+    static NEXT_FAULT: Cell<Option<LoopbackFault>> = const { Cell::new(None) };
+}
+
+const SESSION_SDP_PREFIX: &str = "loopback-session:";
+
+// Stand-in for an RTCSessionDescription: the SDP body only names the session.
+#[derive(Serialize, Deserialize)]
+struct LoopbackDescription {
+    #[serde(rename = "type")]
+    kind: String,
+    sdp: String,
+}
+
+/// In-process `NetworkManager` that pairs two `ChatModel`s without a browser
+/// peer connection. Offers and answers still travel as base64 connection codes
+/// so the wizard runs exactly as it does over WebRTC.
+pub struct LoopbackManager {
+    state: State,
+    session_id: Option<u32>,
+    offer: Option<String>,
+    peer: Weak<RefCell<LoopbackManager>>,
+    peer_id: PeerId,
+    parent_link: Scope<ChatModel<Self>>,
+    bytes_sent: Cell<u64>,
+    signaling: Option<Callback<SignalData>>,
+    making_offer: bool,     // an in-band offer is out and unanswered
+    renegotiations: u32,    // in-band offer/answer exchanges completed
+}
+
+impl NetworkManager for LoopbackManager {
+    fn new(link: &Scope<ChatModel<Self>>, peer_id: PeerId) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(LoopbackManager {
+            state: State::Default,
+            session_id: None,
+            offer: None,
+            peer: Weak::new(),
+            peer_id,
+            parent_link: link.clone(),
+            bytes_sent: Cell::new(0),
+            signaling: None,
+            making_offer: false,
+            renegotiations: 0,
+        }))
+    }
+
+    // Delivered right away, so nothing ever queues.
+    fn send_message(&self, message_content: &str, _priority: Priority) -> Result<(), WebRtcError> {
+        let is_open = self
+            .connection_state()
+            .and_then(|connection_state| connection_state.data_channel_state)
+            == Some(RtcDataChannelState::Open);
+
+        match self.peer.upgrade() {
+            Some(peer) if is_open => {
+                self.bytes_sent.set(self.bytes_sent.get() + message_content.len() as u64);
+                let message_content = message_content.to_string();
+                Timeout::new(SIMULATED_LATENCY_MS, move || {
+                    peer.borrow().receive(&message_content);
+                })
+                .forget();
+                Ok(())
+            }
+            _ => Err(WebRtcError::ChannelClosed),
+        }
+    }
+
+    fn queued_frames(&self) -> usize {
+        0
+    }
+
+    fn get_state(&self) -> State {
+        self.state.clone()
+    }
+
+    fn set_state(&mut self, new_state: State) {
+        self.state = new_state;
+    }
+
+    fn get_offer(&self) -> Option<String> {
+        self.offer.clone()
+    }
+
+    fn get_ice_candidates(&self) -> Vec<IceCandidate> {
+        Vec::new()
+    }
+
+    // There is no ICE to configure in-process
+    fn set_ice_settings(&mut self, _ice_settings: WebRtcDefaults) {}
+
+    fn validate_offer(
+        web_rtc_manager: Rc<RefCell<LoopbackManager>>,
+        str: &str,
+    ) -> Result<(), WebRtcError> {
+        let session_id = LoopbackManager::parse_code(str, "offer")?;
+
+        let host = HOSTS
+            .with(|hosts| hosts.borrow().get(&session_id).and_then(Weak::upgrade))
+            .ok_or(OfferError::InvalidOffer)?;
+
+        {
+            let mut manager = web_rtc_manager.borrow_mut();
+            manager.session_id = Some(session_id);
+            manager.peer = Rc::downgrade(&host);
+            manager.offer = Some(LoopbackManager::describe("answer", session_id));
+        }
+
+        GUESTS.with(|guests| {
+            guests
+                .borrow_mut()
+                .insert(session_id, Rc::downgrade(&web_rtc_manager))
+        });
+
+        LoopbackManager::update_connection_state(&web_rtc_manager, |connection_state| {
+            connection_state.ice_gathering_state = Some(RtcIceGatheringState::Complete);
+        });
+
+        Ok(())
+    }
+
+    fn validate_answer(
+        web_rtc_manager: Rc<RefCell<LoopbackManager>>,
+        str: &str,
+    ) -> Result<(), WebRtcError> {
+        let session_id = LoopbackManager::parse_code(str, "answer")?;
+
+        if web_rtc_manager.borrow().session_id != Some(session_id) {
+            return Err(OfferError::InvalidOffer.into());
+        }
+
+        let guest = GUESTS
+            .with(|guests| guests.borrow_mut().remove(&session_id))
+            .and_then(|guest| guest.upgrade())
+            .ok_or(OfferError::InvalidOffer)?;
+
+        web_rtc_manager.borrow_mut().peer = Rc::downgrade(&guest);
+
+        LoopbackManager::connect(
+            vec![web_rtc_manager, guest],
+            NEXT_FAULT.with(|fault| fault.take()),
+        );
+
+        Ok(())
+    }
+
+    fn start_web_rtc(web_rtc_manager: Rc<RefCell<LoopbackManager>>) -> Result<(), WebRtcError> {
+        // A joining side has nothing to do until it sees an offer
+        if !matches!(web_rtc_manager.borrow().state, State::Server(_)) {
+            return Ok(());
+        }
+
+        let session_id = NEXT_SESSION_ID.with(|next| next.replace(next.get() + 1));
+
+        {
+            let mut manager = web_rtc_manager.borrow_mut();
+            manager.session_id = Some(session_id);
+            manager.offer = Some(LoopbackManager::describe("offer", session_id));
+        }
+
+        HOSTS.with(|hosts| {
+            hosts
+                .borrow_mut()
+                .insert(session_id, Rc::downgrade(&web_rtc_manager))
+        });
+
+        LoopbackManager::update_connection_state(&web_rtc_manager, |connection_state| {
+            connection_state.ice_gathering_state = Some(RtcIceGatheringState::Complete);
+        });
+        LoopbackManager::signal_offer(&web_rtc_manager);
+
+        Ok(())
+    }
+
+    fn restart_ice(web_rtc_manager: Rc<RefCell<LoopbackManager>>) -> Result<(), WebRtcError> {
+        let peer = web_rtc_manager.borrow().peer.upgrade().ok_or(WebRtcError::ChannelClosed)?;
+
+        LoopbackManager::connect(vec![web_rtc_manager, peer], None);
+        Ok(())
+    }
+
+    // Media never leaves the tab, so the peer is handed the very same stream
+    fn set_local_media(
+        web_rtc_manager: Rc<RefCell<LoopbackManager>>,
+        stream: Option<MediaStream>,
+    ) -> Result<(), WebRtcError> {
+        let Some(stream) = stream else {
+            return Ok(());
+        };
+        let peer = web_rtc_manager.borrow().peer.upgrade().ok_or(WebRtcError::ChannelClosed)?;
+
+        let peer = peer.borrow();
+        peer.parent_link.send_message(Msg::RemoteMedia(peer.peer_id, stream));
+        Ok(())
+    }
+
+    // Both ends live in this tab: a direct pair with no round trip to speak of
+    fn request_stats(web_rtc_manager: Rc<RefCell<LoopbackManager>>) {
+        let manager = web_rtc_manager.borrow();
+        let candidate = CandidateInfo {
+            candidate_type: "host".to_string(),
+            protocol: "loopback".to_string(),
+            address: None,
+        };
+        let bytes_received = manager
+            .peer
+            .upgrade()
+            .map(|peer| peer.borrow().bytes_sent.get())
+            .unwrap_or_default();
+
+        let stats = ConnectionStats {
+            timestamp: js_sys::Date::now(),
+            local_candidate: Some(candidate.clone()),
+            remote_candidate: Some(candidate),
+            rtt_ms: Some(0.0),
+            bytes_sent: manager.bytes_sent.get(),
+            bytes_received,
+            buffered_amount: 0,
+        };
+        manager.parent_link.send_message(Msg::StatsReceived(manager.peer_id, stats));
+    }
+
+    fn use_signaling(&mut self, signal: Callback<SignalData>) {
+        self.signaling = Some(signal);
+    }
+
+    // Descriptions go through the same code path as pasted connection codes;
+    // there are no candidates to trickle in-process.
+    fn receive_signal(web_rtc_manager: Rc<RefCell<LoopbackManager>>, data: SignalData) {
+        let SignalData::Description { description } = data else {
+            return;
+        };
+        let code = match connection_code::encode(&ConnectionString { offer: description, ice_candidates: Vec::new() }) {
+            Ok(code) => code,
+            Err(error) => {
+                console::log_1(&format!("Loopback: bad signaled description: {:?}", error).into());
+                return;
+            }
+        };
+
+        let is_host = matches!(web_rtc_manager.borrow().state, State::Server(_));
+        let result = if is_host {
+            LoopbackManager::validate_answer(web_rtc_manager, &code)
+        } else {
+            LoopbackManager::validate_offer(web_rtc_manager.clone(), &code)
+                .map(|()| LoopbackManager::signal_offer(&web_rtc_manager))
+        };
+        if let Err(error) = result {
+            console::log_1(&format!("Loopback: signaled description refused: {:?}", error).into());
+        }
+    }
+
+    // Settles colliding offers the way `WebRTCManager` does: the host
+    // ignores the guest's offer, the polite guest drops its own and answers.
+    // There are no candidates in-process.
+    fn receive_renegotiation(web_rtc_manager: Rc<RefCell<LoopbackManager>>, data: SignalData) {
+        let SignalData::Description { description } = data else {
+            return;
+        };
+        let Ok(description) = serde_json::from_str::<LoopbackDescription>(&description) else {
+            console::log_1(&"Loopback: bad in-band description".into());
+            return;
+        };
+
+        let mut manager = web_rtc_manager.borrow_mut();
+        match description.kind.as_str() {
+            "offer" if manager.making_offer && !matches!(manager.state, State::Client(_)) => {
+                console::log_1(&"Loopback: ignoring the peer's offer, ours was first".into());
+            }
+            "offer" => {
+                manager.making_offer = false;
+                manager.renegotiations += 1;
+                let answer = LoopbackManager::describe("answer", manager.session_id.unwrap_or_default());
+                manager
+                    .parent_link
+                    .send_message(Msg::SendSecureFrame(manager.peer_id, Frame::Description { description: answer }));
+            }
+            "answer" if manager.making_offer => {
+                manager.making_offer = false;
+                manager.renegotiations += 1;
+            }
+            kind => console::log_1(&format!("Loopback: unexpected in-band {}", kind).into()),
+        }
+    }
+}
+
+impl LoopbackManager {
+    /// Makes the next session that completes its offer/answer exchange fail.
+    pub fn inject_fault(fault: LoopbackFault) {
+        NEXT_FAULT.with(|next| next.set(Some(fault)));
+    }
+
+    /// Offers over the data channel like `WebRTCManager::negotiate` does
+    /// once a session is up. Nothing actually changes in-process.
+    pub fn negotiate(web_rtc_manager: &Rc<RefCell<LoopbackManager>>) {
+        let mut manager = web_rtc_manager.borrow_mut();
+        if manager.making_offer {
+            return;
+        }
+        manager.making_offer = true;
+        let offer = LoopbackManager::describe("offer", manager.session_id.unwrap_or_default());
+        manager
+            .parent_link
+            .send_message(Msg::SendSecureFrame(manager.peer_id, Frame::Description { description: offer }));
+    }
+
+    pub fn is_negotiating(&self) -> bool {
+        self.making_offer
+    }
+
+    pub fn renegotiations(&self) -> u32 {
+        self.renegotiations
+    }
+
+    // Sends our offer or answer to the peer when paired through a signaling server.
+    fn signal_offer(web_rtc_manager: &Rc<RefCell<LoopbackManager>>) {
+        let (signaling, offer) = {
+            let manager = web_rtc_manager.borrow();
+            (manager.signaling.clone(), manager.offer.clone())
+        };
+        if let (Some(signal), Some(description)) = (signaling, offer) {
+            signal.emit(SignalData::Description { description });
+        }
+    }
+
+    fn describe(kind: &str, session_id: u32) -> String {
+        serde_json::to_string(&LoopbackDescription {
+            kind: kind.to_string(),
+            sdp: format!("{}{}", SESSION_SDP_PREFIX, session_id),
+        })
+        .expect("descriptions always serialize")
+    }
+
+    fn parse_code(str: &str, expected_kind: &str) -> Result<u32, OfferError> {
+        let connection_string = connection_code::decode(str)?;
+        let description =
+            serde_json::from_str::<LoopbackDescription>(&connection_string.offer)
+                .map_err(|_| OfferError::InvalidOffer)?;
+
+        if description.kind != expected_kind {
+            return Err(OfferError::InvalidOffer);
+        }
+
+        description
+            .sdp
+            .trim()
+            .strip_prefix(SESSION_SDP_PREFIX)
+            .and_then(|session_id| session_id.parse().ok())
+            .ok_or(OfferError::InvalidOffer)
+    }
+
+    fn connection_state(&self) -> Option<&ConnectionState> {
+        match &self.state {
+            State::Server(connection_state) | State::Client(connection_state) => {
+                Some(connection_state)
+            }
+            State::Default => None,
+        }
+    }
+
+    fn receive(&self, message_content: &str) {
+        match protocol::decode(message_content) {
+            Ok(frame) => self.parent_link.send_message(Msg::ReceiveFrame(self.peer_id, frame)),
+            Err(error) => console::log_1(&format!("Dropping frame: {:?}", error).into()),
+        }
+    }
+
+    fn update_connection_state(
+        web_rtc_manager: &Rc<RefCell<LoopbackManager>>,
+        update: impl FnOnce(&mut ConnectionState),
+    ) {
+        let new_state = {
+            let mut manager = web_rtc_manager.borrow_mut();
+            match &mut manager.state {
+                State::Server(connection_state) | State::Client(connection_state) => {
+                    update(connection_state)
+                }
+                State::Default => return,
+            }
+            manager.state.clone()
+        };
+
+        let manager = web_rtc_manager.borrow();
+        manager
+            .parent_link
+            .send_message(Msg::UpdateWebRTCState(manager.peer_id, new_state));
+    }
+
+    // Walks both ends through the same transitions a browser would report:
+    // checking, then either connected with an open channel or failed.
+    fn connect(peers: Vec<Rc<RefCell<LoopbackManager>>>, fault: Option<LoopbackFault>) {
+        for peer in &peers {
+            LoopbackManager::update_connection_state(peer, |connection_state| {
+                connection_state.ice_connection_state = Some(RtcIceConnectionState::Checking);
+            });
+        }
+
+        Timeout::new(SIMULATED_LATENCY_MS, move || {
+            for peer in &peers {
+                LoopbackManager::update_connection_state(peer, |connection_state| {
+                    if fault == Some(LoopbackFault::IceFailed) {
+                        connection_state.ice_connection_state = Some(RtcIceConnectionState::Failed);
+                    } else {
+                        connection_state.ice_connection_state =
+                            Some(RtcIceConnectionState::Connected);
+                        connection_state.data_channel_state = Some(RtcDataChannelState::Open);
+                    }
+                });
+            }
+
+            if fault == Some(LoopbackFault::ChannelClosed) {
+                Timeout::new(SIMULATED_LATENCY_MS, move || {
+                    for peer in &peers {
+                        LoopbackManager::update_connection_state(peer, |connection_state| {
+                            connection_state.data_channel_state = Some(RtcDataChannelState::Closed);
+                        });
+                    }
+                })
+                .forget();
+            } else if fault == Some(LoopbackFault::IceDropped) {
+                Timeout::new(SIMULATED_LATENCY_MS, move || {
+                    for peer in &peers {
+                        LoopbackManager::update_connection_state(peer, |connection_state| {
+                            connection_state.ice_connection_state =
+                                Some(RtcIceConnectionState::Disconnected);
+                        });
+                    }
+                })
+                .forget();
+            }
+        })
+        .forget();
+    }
+}
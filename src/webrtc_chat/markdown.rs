@@ -0,0 +1,350 @@
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag, TagEnd};
+use yew::prelude::*;
+
+const LINK_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+// Shortcodes as typed on GitHub and Slack, sorted by name.
+const EMOJI_SHORTCODES: [(&str, &str); 32] = [
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("100", "💯"),
+    ("bug", "🐛"),
+    ("check", "✅"),
+    ("clap", "👏"),
+    ("coffee", "☕"),
+    ("cry", "😢"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("grin", "😁"),
+    ("heart", "❤️"),
+    ("joy", "😂"),
+    ("laughing", "😆"),
+    ("ok_hand", "👌"),
+    ("party", "🥳"),
+    ("pray", "🙏"),
+    ("rocket", "🚀"),
+    ("sad", "😞"),
+    ("see_no_evil", "🙈"),
+    ("shrug", "🤷"),
+    ("smile", "😄"),
+    ("sparkles", "✨"),
+    ("sunglasses", "😎"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("wink", "😉"),
+    ("x", "❌"),
+];
+
+/// A run of message text after autolinking and shortcode replacement.
+#[derive(Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Link { href: String, text: String },
+}
+
+/// Renders a chat message as Markdown. The text comes from a peer, so it is
+/// never handed to the browser as HTML: raw HTML shows up as the text it is,
+/// links keep only http, https and mailto targets, and images become links
+/// so nothing loads until the user clicks. `on_copy` gets a code block's text.
+pub fn render(content: &str, on_copy: &Callback<String>) -> Html {
+    // The root has no tag; every start tag opens a level, its end closes it
+    let mut stack: Vec<(Option<Tag>, Vec<Html>)> = vec![(None, vec![])];
+    let mut code_block: Option<(String, String)> = None; // (language, text)
+    let mut link_depth = 0;
+
+    for event in parse(content) {
+        let html = match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().unwrap_or_default().to_string(),
+                    CodeBlockKind::Indented => String::new(),
+                };
+                code_block = Some((language, String::new()));
+                continue;
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                let Some((language, code)) = code_block.take() else {
+                    continue;
+                };
+                render_code_block(&language, code.trim_end_matches('\n'), on_copy)
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = code_block.as_mut() {
+                    code.push_str(&text);
+                }
+                continue;
+            }
+            Event::Start(tag) => {
+                if matches!(tag, Tag::Link { .. } | Tag::Image { .. }) {
+                    link_depth += 1;
+                }
+                stack.push((Some(tag), vec![]));
+                continue;
+            }
+            Event::End(_) => {
+                if stack.len() == 1 {
+                    continue;
+                }
+                let Some((Some(tag), children)) = stack.pop() else {
+                    continue;
+                };
+                if matches!(tag, Tag::Link { .. } | Tag::Image { .. }) {
+                    link_depth -= 1;
+                }
+                render_tag(tag, children)
+            }
+            // Text already inside a link is not linked again
+            Event::Text(text) if link_depth > 0 => html! { replace_shortcodes(&text) },
+            Event::Text(text) => render_inlines(inline_segments(&text)),
+            Event::Code(code) => html! {
+                <code class="px-1 rounded bg-black bg-opacity-10 font-mono text-sm">{ code.to_string() }</code>
+            },
+            Event::SoftBreak | Event::HardBreak => html! { <br/> },
+            Event::Rule => html! { <hr class="my-2 border-current opacity-50"/> },
+            Event::TaskListMarker(checked) => html! { { if checked { "☑ " } else { "☐ " } } },
+            _ => continue,
+        };
+
+        if let Some((_, children)) = stack.last_mut() {
+            children.push(html);
+        }
+    }
+
+    // Unclosed tags cannot happen with pulldown-cmark, but never lose text
+    let children = stack.into_iter().flat_map(|(_, children)| children);
+    html! {
+        <div class="space-y-2 break-words">{ for children }</div>
+    }
+}
+
+/// The parser's events with raw HTML turned into plain text.
+fn parse(content: &str) -> impl Iterator<Item = Event<'_>> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    Parser::new_ext(content, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        event => event,
+    })
+}
+
+fn render_tag(tag: Tag, children: Vec<Html>) -> Html {
+    let children = html! { for children };
+    match tag {
+        Tag::Paragraph => html! { <p>{ children }</p> },
+        Tag::Heading { .. } => html! { <p class="font-bold">{ children }</p> },
+        Tag::BlockQuote(_) => html! {
+            <blockquote class="border-l-4 border-current opacity-80 pl-2">{ children }</blockquote>
+        },
+        Tag::List(Some(start)) => html! {
+            <ol class="list-decimal pl-5" start={start.to_string()}>{ children }</ol>
+        },
+        Tag::List(None) => html! { <ul class="list-disc pl-5">{ children }</ul> },
+        Tag::Item => html! { <li>{ children }</li> },
+        Tag::Emphasis => html! { <em>{ children }</em> },
+        Tag::Strong => html! { <strong>{ children }</strong> },
+        Tag::Strikethrough => html! { <del>{ children }</del> },
+        Tag::HtmlBlock => html! { <p class="whitespace-pre-wrap">{ children }</p> },
+        Tag::Link { dest_url, title, .. } if is_safe_url(&dest_url) => render_link(&dest_url, &title, children),
+        Tag::Image { dest_url, title, .. } if is_safe_url(&dest_url) => {
+            render_link(&dest_url, &title, html! { <>{"🖼️ "}{ children }</> })
+        }
+        _ => children,
+    }
+}
+
+fn render_link(href: &str, title: &CowStr, children: Html) -> Html {
+    html! {
+        <a
+            href={href.to_string()}
+            title={(!title.is_empty()).then(|| title.to_string())}
+            target="_blank"
+            rel="noopener noreferrer"
+            class="underline break-all"
+        >
+            { children }
+        </a>
+    }
+}
+
+fn render_inlines(inlines: Vec<Inline>) -> Html {
+    html! {
+        for inlines.into_iter().map(|inline| match inline {
+            Inline::Text(text) => html! { text },
+            Inline::Link { href, text } => render_link(&href, &CowStr::Borrowed(""), html! { text }),
+        })
+    }
+}
+
+fn render_code_block(language: &str, code: &str, on_copy: &Callback<String>) -> Html {
+    let copied = code.to_string();
+    html! {
+        <div class="rounded bg-black bg-opacity-10 text-sm">
+            <div class="flex justify-between items-center px-2 pt-1 text-xs opacity-75">
+                <span>{ language.to_string() }</span>
+                <button
+                    class="hover:underline"
+                    title="Copy code"
+                    onclick={on_copy.reform(move |_: MouseEvent| copied.clone())}
+                >
+                    {"📋 Copy"}
+                </button>
+            </div>
+            <pre class="p-2 overflow-x-auto font-mono"><code>{ code.to_string() }</code></pre>
+        </div>
+    }
+}
+
+pub fn is_safe_url(url: &str) -> bool {
+    let url = url.trim_start().to_ascii_lowercase();
+    LINK_SCHEMES.iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// Splits text into plain runs and bare `http(s)://` or `www.` links, with
+/// emoji shortcodes replaced in the plain runs.
+pub fn inline_segments(text: &str) -> Vec<Inline> {
+    let mut inlines = vec![];
+    let mut rest = text;
+
+    while let Some(start) = find_link_start(rest) {
+        let link_len = link_length(&rest[start..]);
+        if start > 0 {
+            inlines.push(Inline::Text(replace_shortcodes(&rest[..start])));
+        }
+        let link = &rest[start..start + link_len];
+        let href = if link.starts_with("www.") { format!("https://{}", link) } else { link.to_string() };
+        inlines.push(Inline::Link { href, text: link.to_string() });
+        rest = &rest[start + link_len..];
+    }
+
+    if !rest.is_empty() {
+        inlines.push(Inline::Text(replace_shortcodes(rest)));
+    }
+    inlines
+}
+
+// A link starts at a word boundary and needs something after its prefix.
+fn find_link_start(text: &str) -> Option<usize> {
+    text.char_indices()
+        .filter(|(i, _)| text[..*i].chars().next_back().is_none_or(|c| !c.is_alphanumeric()))
+        .map(|(i, _)| i)
+        .find(|i| {
+            ["https://", "http://", "www."]
+                .iter()
+                .any(|prefix| text[*i..].starts_with(prefix) && link_length(&text[*i..]) > prefix.len())
+        })
+}
+
+// Up to the next whitespace, minus punctuation that more likely ends the
+// sentence than the URL. A closing paren stays if the URL opened one.
+fn link_length(text: &str) -> usize {
+    let end = text.find(|c: char| c.is_whitespace() || c == '<' || c == '>').unwrap_or(text.len());
+    let mut link = &text[..end];
+    loop {
+        let trimmed = link.trim_end_matches(['.', ',', ';', ':', '!', '?', '\'', '"']);
+        let trimmed = if trimmed.ends_with(')') && trimmed.matches('(').count() < trimmed.matches(')').count() {
+            &trimmed[..trimmed.len() - 1]
+        } else {
+            trimmed
+        };
+        if trimmed.len() == link.len() {
+            return link.len();
+        }
+        link = trimmed;
+    }
+}
+
+/// Replaces known `:name:` shortcodes; unknown ones stay as typed.
+pub fn replace_shortcodes(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(open) = rest.find(':') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let emoji = after.find(':').and_then(|close| {
+            let name = &after[..close];
+            EMOJI_SHORTCODES
+                .binary_search_by(|(code, _)| code.cmp(&name))
+                .ok()
+                .map(|index| (EMOJI_SHORTCODES[index].1, close))
+        });
+        match emoji {
+            Some((emoji, close)) => {
+                out.push_str(emoji);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_html_is_only_ever_text() {
+        let content = "<script>alert(1)</script>\n\nhi <img src=x onerror=alert(1)> there";
+        let events: Vec<_> = parse(content).collect();
+
+        assert!(!events.iter().any(|event| matches!(event, Event::Html(_) | Event::InlineHtml(_))));
+        assert!(events.contains(&Event::Text("<script>alert(1)</script>\n".into())));
+        assert!(events.contains(&Event::Text("<img src=x onerror=alert(1)>".into())));
+    }
+
+    #[test]
+    fn only_web_and_mail_links_are_kept() {
+        assert!(is_safe_url("https://example.com"));
+        assert!(is_safe_url("HTTP://example.com"));
+        assert!(is_safe_url("mailto:alice@example.com"));
+        assert!(!is_safe_url("javascript:alert(1)"));
+        assert!(!is_safe_url(" JavaScript:alert(1)"));
+        assert!(!is_safe_url("data:text/html,<script>alert(1)</script>"));
+        assert!(!is_safe_url("/relative"));
+    }
+
+    #[test]
+    fn bare_urls_become_links() {
+        assert_eq!(
+            inline_segments("see https://example.com/a_(b), or www.rust-lang.org."),
+            vec![
+                Inline::Text("see ".to_string()),
+                Inline::Link {
+                    href: "https://example.com/a_(b)".to_string(),
+                    text: "https://example.com/a_(b)".to_string()
+                },
+                Inline::Text(", or ".to_string()),
+                Inline::Link {
+                    href: "https://www.rust-lang.org".to_string(),
+                    text: "www.rust-lang.org".to_string()
+                },
+                Inline::Text(".".to_string()),
+            ]
+        );
+        assert_eq!(
+            inline_segments("(https://example.com) http:// nothttps://x"),
+            vec![
+                Inline::Text("(".to_string()),
+                Inline::Link { href: "https://example.com".to_string(), text: "https://example.com".to_string() },
+                Inline::Text(") http:// nothttps://x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn shortcodes_become_emoji() {
+        assert!(EMOJI_SHORTCODES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(replace_shortcodes("ship it :rocket: :+1:"), "ship it 🚀 👍");
+        assert_eq!(replace_shortcodes("at 10:30: :nope: :tada:"), "at 10:30: :nope: 🎉");
+        assert_eq!(inline_segments(":fire: https://x.io :fire:")[2], Inline::Text(" 🔥".to_string()));
+    }
+}
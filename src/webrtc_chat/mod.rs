@@ -1,2 +1,20 @@
+pub mod call;
 pub mod chat_model;
-pub mod web_rtc_manager;
\ No newline at end of file
+pub mod connection_code;
+pub mod crypto;
+pub mod diagnostics;
+pub mod file_transfer;
+pub mod history;
+#[cfg(all(test, target_arch = "wasm32"))]
+pub mod loopback;
+pub mod markdown;
+pub mod protocol;
+pub mod reconnect;
+pub mod room;
+pub mod send_queue;
+pub mod signaling;
+pub mod step_timeout;
+pub mod transcript;
+pub mod voice;
+pub mod web_rtc_manager;
+pub mod whiteboard;
\ No newline at end of file
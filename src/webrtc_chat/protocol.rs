@@ -0,0 +1,309 @@
+use serde::{Deserialize, Serialize};
+
+use crate::webrtc_chat::room::Participant;
+use crate::webrtc_chat::voice::VoiceInfo;
+use crate::webrtc_chat::web_rtc_manager::IceCandidate;
+use crate::webrtc_chat::whiteboard::BoardOp;
+
+/// Version written into every envelope. Peers reject frames carrying any other version.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Ordered, so a late `Delivered` never downgrades a message already `Read`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CallAction {
+    Invite { video: bool },
+    Accept,
+    /// Also sent back when we are already in a call.
+    Decline,
+    HangUp,
+    /// Lets the other side show that we muted or turned the camera off.
+    MediaState { muted: bool, camera_off: bool },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlAction {
+    Ping { sent_at: u64 },
+    Pong { sent_at: u64 },
+    Bye,
+}
+
+/// Everything that can travel over the data channel.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Frame {
+    Chat {
+        id: String,
+        content: String,
+        timestamp: u64,
+        /// Participant id of the original author, set when the host relays a
+        /// guest's message to the rest of the room.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+        /// Id of the message this one replies to.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reply_to: Option<String>,
+    },
+    Typing {
+        is_typing: bool,
+        /// Participant id of who is typing, set when the host relays it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    Receipt {
+        message_id: String,
+        kind: ReceiptKind,
+    },
+    /// New text for a chat message. Only its author may edit it; when edits
+    /// race, the later `edited_at` wins and equal times fall back to the text.
+    Edit {
+        message_id: String,
+        content: String,
+        edited_at: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    /// Deletes a chat message for everyone. Wins over any edit.
+    Delete {
+        message_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    /// Adds or removes the author's `emoji` on a message.
+    Reaction {
+        message_id: String,
+        emoji: String,
+        add: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    FileManifest {
+        transfer_id: String,
+        name: String,
+        size: u64,
+        file_type: String,
+        chunk_count: u32,
+        /// Set when the file is a recorded voice message.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        voice: Option<VoiceInfo>,
+    },
+    FileChunk {
+        transfer_id: String,
+        index: u32,
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+    Control {
+        action: ControlAction,
+    },
+    /// A change to the shared whiteboard.
+    Board {
+        op: BoardOp,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        author: Option<String>,
+    },
+    /// Call setup. The media itself is negotiated with `Description` frames.
+    Call {
+        action: CallAction,
+    },
+    /// JSON `RTCSessionDescriptionInit`, sent to renegotiate a session that
+    /// is already connected (e.g. an ICE restart or adding call media).
+    /// Sealed like any other frame, so a relay cannot swap the session.
+    Description {
+        description: String,
+    },
+    /// A local candidate of the latest description, trickled sealed as it is found.
+    Candidate {
+        candidate: IceCandidate,
+    },
+    /// Sent by both sides first, so history can be keyed by who we talk to.
+    Identity {
+        identity: String,
+    },
+    /// Sent by a guest once its channel to the host opens.
+    Hello {
+        nickname: String,
+    },
+    /// The host's view of the room, sent to each guest with its own id.
+    Roster {
+        you: String,
+        participants: Vec<Participant>,
+    },
+    /// Raw P-256 public key for the link's ECDH exchange.
+    KeyExchange {
+        #[serde(with = "base64_bytes")]
+        public_key: Vec<u8>,
+    },
+    /// AES-GCM sealed envelope of any other frame. `seq` counts up per
+    /// direction of the link and is authenticated with the frame.
+    Encrypted {
+        seq: u64,
+        #[serde(with = "base64_bytes")]
+        iv: Vec<u8>,
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+}
+
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum DecodeError {
+    Malformed,
+    UnsupportedVersion(u8),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    v: u8,
+    frame: Frame,
+}
+
+// Read on its own first so that a frame from a newer peer is reported as a
+// version mismatch rather than as garbage.
+#[derive(Deserialize)]
+struct Header {
+    v: u8,
+}
+
+pub fn encode(frame: &Frame) -> String {
+    let envelope = Envelope {
+        v: PROTOCOL_VERSION,
+        frame: frame.clone(),
+    };
+
+    serde_json::to_string(&envelope).expect("frames always serialize")
+}
+
+pub fn decode(raw: &str) -> Result<Frame, DecodeError> {
+    let header = serde_json::from_str::<Header>(raw).map_err(|_| DecodeError::Malformed)?;
+
+    if header.v != PROTOCOL_VERSION {
+        return Err(DecodeError::UnsupportedVersion(header.v));
+    }
+
+    serde_json::from_str::<Envelope>(raw)
+        .map(|envelope| envelope.frame)
+        .map_err(|_| DecodeError::Malformed)
+}
+
+mod base64_bytes {
+    use base64::{engine::general_purpose, Engine as _};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webrtc_chat::room::ParticipantStatus;
+    use crate::webrtc_chat::whiteboard::{Item, Shape};
+
+    fn every_frame() -> Vec<Frame> {
+        let author = Some("peer-1".to_string());
+        vec![
+            Frame::Chat { id: "m1".into(), content: "hi ✓".into(), timestamp: 1, author: author.clone(), reply_to: Some("m0".into()) },
+            Frame::Chat { id: "m2".into(), content: String::new(), timestamp: 2, author: None, reply_to: None },
+            Frame::Typing { is_typing: true, author: author.clone() },
+            Frame::Receipt { message_id: "m1".into(), kind: ReceiptKind::Read },
+            Frame::Edit { message_id: "m1".into(), content: "hey".into(), edited_at: 3, author: author.clone() },
+            Frame::Delete { message_id: "m1".into(), author: None },
+            Frame::Reaction { message_id: "m1".into(), emoji: "👍".into(), add: false, author: author.clone() },
+            Frame::FileManifest {
+                transfer_id: "t1".into(),
+                name: "note.ogg".into(),
+                size: 3,
+                file_type: "audio/ogg".into(),
+                chunk_count: 1,
+                voice: Some(VoiceInfo { duration_ms: 1200, waveform: vec![0, 128, 255] }),
+            },
+            Frame::FileChunk { transfer_id: "t1".into(), index: 0, data: vec![0, 1, 255] },
+            Frame::Control { action: ControlAction::Ping { sent_at: 4 } },
+            Frame::Control { action: ControlAction::Bye },
+            Frame::Board {
+                op: BoardOp::Add {
+                    item: Item { id: "b1".into(), shape: Shape::Stroke { points: vec![[1, 2], [3, 4]] }, color: 0xff0000, width: 4 },
+                },
+                author: author.clone(),
+            },
+            Frame::Call { action: CallAction::MediaState { muted: true, camera_off: false } },
+            Frame::Call { action: CallAction::Invite { video: true } },
+            Frame::Description { description: r#"{"type":"offer","sdp":"v=0\r\n"}"#.into() },
+            Frame::Candidate {
+                candidate: IceCandidate { candidate: "candidate:1 1 udp 1 10.0.0.1 9 typ host".into(), sdp_mid: "0".into(), sdp_m_line_index: 0 },
+            },
+            Frame::Identity { identity: "id-1".into() },
+            Frame::Hello { nickname: "Ada".into() },
+            Frame::Roster {
+                you: "peer-1".into(),
+                participants: vec![Participant {
+                    id: "host".into(),
+                    nickname: "Host".into(),
+                    color: "#2563eb".into(),
+                    status: ParticipantStatus::Reconnecting,
+                }],
+            },
+            Frame::KeyExchange { public_key: vec![4; 65] },
+            Frame::Encrypted { seq: 1, iv: vec![7; 12], data: vec![] },
+        ]
+    }
+
+    // Fails to compile when a variant is added, as a reminder to cover it above.
+    fn is_covered(frame: &Frame) -> bool {
+        match frame {
+            Frame::Chat { .. }
+            | Frame::Typing { .. }
+            | Frame::Receipt { .. }
+            | Frame::Edit { .. }
+            | Frame::Delete { .. }
+            | Frame::Reaction { .. }
+            | Frame::FileManifest { .. }
+            | Frame::FileChunk { .. }
+            | Frame::Control { .. }
+            | Frame::Board { .. }
+            | Frame::Call { .. }
+            | Frame::Description { .. }
+            | Frame::Candidate { .. }
+            | Frame::Identity { .. }
+            | Frame::Hello { .. }
+            | Frame::Roster { .. }
+            | Frame::KeyExchange { .. }
+            | Frame::Encrypted { .. } => true,
+        }
+    }
+
+    #[test]
+    fn every_frame_round_trips() {
+        for frame in every_frame() {
+            assert!(is_covered(&frame));
+            assert_eq!(decode(&encode(&frame)), Ok(frame));
+        }
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let raw = encode(&Frame::Hello { nickname: "Ada".into() });
+        let newer = raw.replace(&format!(r#""v":{}"#, PROTOCOL_VERSION), r#""v":2"#);
+
+        assert_eq!(decode(&newer), Err(DecodeError::UnsupportedVersion(2)));
+        // Even when the rest of the frame is not understood
+        assert_eq!(decode(r#"{"v":9,"frame":{"type":"from_the_future"}}"#), Err(DecodeError::UnsupportedVersion(9)));
+        assert_eq!(decode(r#"{"frame":{"type":"hello","nickname":"Ada"}}"#), Err(DecodeError::Malformed));
+    }
+}
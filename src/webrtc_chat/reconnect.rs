@@ -0,0 +1,78 @@
+pub const MAX_RECONNECT_ATTEMPTS: u32 = 5;
+
+const BASE_DELAY_MS: u32 = 1_000;
+const MAX_DELAY_MS: u32 = 16_000;
+
+/// Progress of an automatic reconnection after ICE dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reconnect {
+    /// `attempt` restarts have been tried; the next one fires at `retry_at`
+    /// (milliseconds since the epoch, as returned by `Date.now()`).
+    Waiting { attempt: u32, retry_at: f64 },
+    /// Every attempt failed or the data channel closed; only re-pairing helps now.
+    GaveUp,
+}
+
+impl Reconnect {
+    pub fn start(now: f64) -> Reconnect {
+        Reconnect::Waiting {
+            attempt: 0,
+            retry_at: now + backoff_delay_ms(0) as f64,
+        }
+    }
+
+    /// Moves to the next attempt, or gives up once the budget is spent.
+    pub fn next(&self, now: f64) -> Reconnect {
+        match self {
+            Reconnect::Waiting { attempt, .. } if attempt + 1 < MAX_RECONNECT_ATTEMPTS => {
+                Reconnect::Waiting {
+                    attempt: attempt + 1,
+                    retry_at: now + backoff_delay_ms(attempt + 1) as f64,
+                }
+            }
+            _ => Reconnect::GaveUp,
+        }
+    }
+
+    pub fn seconds_until_retry(&self, now: f64) -> u32 {
+        match self {
+            Reconnect::Waiting { retry_at, .. } => ((retry_at - now).max(0.0) / 1000.0).ceil() as u32,
+            Reconnect::GaveUp => 0,
+        }
+    }
+}
+
+/// Doubles from one second up to sixteen.
+pub fn backoff_delay_ms(attempt: u32) -> u32 {
+    BASE_DELAY_MS
+        .saturating_mul(1 << attempt.min(16))
+        .min(MAX_DELAY_MS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let delays: Vec<u32> = (0..7).map(backoff_delay_ms).collect();
+        assert_eq!(delays, [1_000, 2_000, 4_000, 8_000, 16_000, 16_000, 16_000]);
+        assert_eq!(backoff_delay_ms(u32::MAX), MAX_DELAY_MS);
+    }
+
+    #[test]
+    fn gives_up_after_the_last_attempt() {
+        let mut reconnect = Reconnect::start(0.0);
+        assert_eq!(reconnect, Reconnect::Waiting { attempt: 0, retry_at: 1_000.0 });
+        assert_eq!(reconnect.seconds_until_retry(250.0), 1);
+
+        for attempt in 1..MAX_RECONNECT_ATTEMPTS {
+            reconnect = reconnect.next(10_000.0);
+            let retry_at = 10_000.0 + backoff_delay_ms(attempt) as f64;
+            assert_eq!(reconnect, Reconnect::Waiting { attempt, retry_at });
+        }
+
+        assert_eq!(reconnect.next(20_000.0), Reconnect::GaveUp);
+        assert_eq!(Reconnect::GaveUp.seconds_until_retry(0.0), 0);
+    }
+}
@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+use web_sys::{MediaStream, RtcDataChannelState, RtcIceConnectionState};
+
+use crate::webrtc_chat::crypto::SecureChannel;
+use crate::webrtc_chat::protocol::Frame;
+use crate::webrtc_chat::web_rtc_manager::{ConnectionState, NetworkManager, State};
+
+/// Local handle for one peer connection. Only unique within this browser tab.
+pub type PeerId = u32;
+
+/// Participant id the host uses for itself in the roster.
+pub const HOST_PARTICIPANT_ID: &str = "host";
+
+const MAX_NICKNAME_LEN: usize = 32;
+
+const PARTICIPANT_COLORS: [&str; 8] = [
+    "#2563eb", "#db2777", "#16a34a", "#d97706", "#7c3aed", "#0891b2", "#dc2626", "#4b5563",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParticipantStatus {
+    Connecting,
+    Connected,
+    Reconnecting,
+    Left,
+}
+
+impl ParticipantStatus {
+    pub fn from_state(state: &State) -> ParticipantStatus {
+        let connection_state = match state {
+            State::Server(connection_state) | State::Client(connection_state) => connection_state,
+            State::Default => return ParticipantStatus::Connecting,
+        };
+
+        if connection_state.data_channel_state != Some(RtcDataChannelState::Open) {
+            return ParticipantStatus::Connecting;
+        }
+
+        if is_ice_dropped(connection_state) {
+            ParticipantStatus::Reconnecting
+        } else {
+            ParticipantStatus::Connected
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ParticipantStatus::Connecting => "connecting",
+            ParticipantStatus::Connected => "connected",
+            ParticipantStatus::Reconnecting => "reconnecting",
+            ParticipantStatus::Left => "left",
+        }
+    }
+}
+
+/// One entry of the roster the host shares with everyone in the room.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Participant {
+    pub id: String,
+    pub nickname: String,
+    pub color: String,
+    pub status: ParticipantStatus,
+}
+
+/// A peer connection the host (or a guest, for its single link to the host)
+/// has opened a data channel on.
+pub struct RoomPeer<T: NetworkManager + 'static> {
+    pub id: PeerId,
+    pub manager: Rc<RefCell<T>>,
+    pub nickname: Option<String>,
+    /// Stable identity the peer sent when connecting, see `history::local_identity`.
+    pub identity: Option<String>,
+    pub secure: SecureChannel,
+    /// Whether the user confirmed the safety number for this link.
+    pub verified: bool,
+    /// Audio and video the peer sends during a call.
+    pub remote_stream: Option<MediaStream>,
+}
+
+impl<T: NetworkManager + 'static> RoomPeer<T> {
+    pub fn new(id: PeerId, manager: Rc<RefCell<T>>, secure: SecureChannel) -> RoomPeer<T> {
+        RoomPeer {
+            id,
+            manager,
+            nickname: None,
+            identity: None,
+            secure,
+            verified: false,
+            remote_stream: None,
+        }
+    }
+
+    /// Sends a frame encrypted for this link.
+    pub fn send(&self, frame: &Frame) {
+        self.secure.send(frame.clone());
+    }
+
+    pub fn state(&self) -> State {
+        self.manager.borrow().get_state()
+    }
+
+    /// Frames waiting on this link, whether still to be sealed or sealed and
+    /// waiting for the data channel's buffer.
+    pub fn queued_frames(&self) -> usize {
+        self.secure.queued() + self.manager.borrow().queued_frames()
+    }
+
+    pub fn is_open(&self) -> bool {
+        match self.state() {
+            State::Server(connection_state) | State::Client(connection_state) => {
+                connection_state.data_channel_state == Some(RtcDataChannelState::Open)
+            }
+            State::Default => false,
+        }
+    }
+
+    pub fn is_ice_dropped(&self) -> bool {
+        match self.state() {
+            State::Server(connection_state) | State::Client(connection_state) => {
+                is_ice_dropped(&connection_state)
+            }
+            State::Default => false,
+        }
+    }
+
+    pub fn to_participant(&self) -> Participant {
+        Participant {
+            id: participant_id(self.id),
+            nickname: self
+                .nickname
+                .clone()
+                .unwrap_or_else(|| format!("Guest {}", self.id)),
+            color: participant_color(self.id).to_string(),
+            status: ParticipantStatus::from_state(&self.state()),
+        }
+    }
+}
+
+pub fn participant_id(peer_id: PeerId) -> String {
+    format!("peer-{}", peer_id)
+}
+
+/// The host is always color zero; guests follow in the order they joined.
+pub fn participant_color(peer_id: PeerId) -> &'static str {
+    PARTICIPANT_COLORS[peer_id as usize % PARTICIPANT_COLORS.len()]
+}
+
+pub fn random_nickname() -> String {
+    format!("Guest-{:04}", rand::random_range(0..10_000))
+}
+
+/// Trims and shortens a nickname received from a peer.
+pub fn clean_nickname(nickname: &str) -> Option<String> {
+    let nickname: String = nickname.trim().chars().take(MAX_NICKNAME_LEN).collect();
+    (!nickname.is_empty()).then_some(nickname)
+}
+
+fn is_ice_dropped(connection_state: &ConnectionState) -> bool {
+    matches!(
+        connection_state.ice_connection_state,
+        Some(RtcIceConnectionState::Disconnected) | Some(RtcIceConnectionState::Failed)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nicknames_are_trimmed_and_shortened() {
+        assert_eq!(clean_nickname("  Ada  ").as_deref(), Some("Ada"));
+        assert_eq!(clean_nickname(" \t\n"), None);
+
+        let long = clean_nickname(&"é".repeat(40)).unwrap();
+        assert_eq!(long.chars().count(), MAX_NICKNAME_LEN);
+    }
+
+    #[test]
+    fn participants_are_named_and_colored_by_peer_id() {
+        assert_eq!(participant_id(3), "peer-3");
+        assert_ne!(participant_id(3), HOST_PARTICIPANT_ID);
+
+        assert_eq!(participant_color(0), PARTICIPANT_COLORS[0]);
+        assert_ne!(participant_color(1), participant_color(2));
+        assert_eq!(participant_color(1), participant_color(1 + PARTICIPANT_COLORS.len() as PeerId));
+    }
+}
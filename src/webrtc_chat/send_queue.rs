@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+use crate::webrtc_chat::protocol::Frame;
+
+/// Sending stops while the data channel holds more than this, so SCTP never
+/// has more than a few round trips of data to push out.
+pub const MAX_BUFFERED_AMOUNT: u32 = 1024 * 1024;
+
+/// `bufferedamountlow` fires once the channel drains below this.
+pub const BUFFERED_AMOUNT_LOW: u32 = 256 * 1024;
+
+/// File transfers stop producing chunks while this many frames wait.
+pub const MAX_QUEUED_FRAMES: usize = 32;
+
+/// Ordered lowest first; higher priorities leave the queue first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// File chunks, which may take minutes to send.
+    Bulk,
+    /// Chat messages and what changes them.
+    Normal,
+    /// Small frames that keep the session going: typing, receipts, keys, calls.
+    Control,
+}
+
+impl Priority {
+    pub fn of(frame: &Frame) -> Priority {
+        match frame {
+            Frame::FileChunk { .. } => Priority::Bulk,
+            Frame::Chat { .. }
+            | Frame::Edit { .. }
+            | Frame::Delete { .. }
+            | Frame::Reaction { .. }
+            | Frame::FileManifest { .. }
+            | Frame::Board { .. }
+            // The sealed frame's own priority is not visible from outside
+            | Frame::Encrypted { .. } => Priority::Normal,
+            Frame::Typing { .. }
+            | Frame::Receipt { .. }
+            | Frame::Control { .. }
+            | Frame::Call { .. }
+            | Frame::Description { .. }
+            | Frame::Candidate { .. }
+            | Frame::Identity { .. }
+            | Frame::Hello { .. }
+            | Frame::Roster { .. }
+            | Frame::KeyExchange { .. } => Priority::Control,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Priority::Control => 0,
+            Priority::Normal => 1,
+            Priority::Bulk => 2,
+        }
+    }
+}
+
+/// Encoded frames waiting for room in the data channel's buffer, first in
+/// first out within each priority. `SecureChannel` queues plain frames the
+/// same way while they wait to be sealed.
+#[derive(Debug)]
+pub struct SendQueue<T = String> {
+    queues: [VecDeque<T>; 3],
+}
+
+impl<T> Default for SendQueue<T> {
+    fn default() -> Self {
+        SendQueue { queues: Default::default() }
+    }
+}
+
+impl<T> SendQueue<T> {
+    pub fn push(&mut self, priority: Priority, payload: T) {
+        self.queues[priority.index()].push_back(payload);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.iter().all(VecDeque::is_empty)
+    }
+
+    /// Sends frames in priority order while `has_room` says the buffer can
+    /// take more. A frame `send` refuses is dropped and stops the flush: the
+    /// channel will not take it later either.
+    pub fn flush<E>(
+        &mut self,
+        mut has_room: impl FnMut() -> bool,
+        mut send: impl FnMut(&T) -> Result<(), E>,
+    ) -> Result<(), E> {
+        while has_room() {
+            let Some(payload) = self.pop() else {
+                break;
+            };
+            send(&payload)?;
+        }
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.queues.iter_mut().find_map(VecDeque::pop_front)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::webrtc_chat::protocol::ReceiptKind;
+    use crate::webrtc_chat::web_rtc_manager::IceCandidate;
+
+    #[test]
+    fn control_frames_jump_ahead_of_bulk_data() {
+        let mut queue = SendQueue::default();
+        queue.push(Priority::Bulk, "chunk 1".to_string());
+        queue.push(Priority::Bulk, "chunk 2".to_string());
+        queue.push(Priority::Normal, "chat".to_string());
+        queue.push(Priority::Control, "receipt".to_string());
+
+        let mut sent = vec![];
+        queue
+            .flush(|| true, |payload| {
+                sent.push(payload.to_string());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+
+        assert_eq!(sent, ["receipt", "chat", "chunk 1", "chunk 2"]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn flush_stops_when_the_buffer_is_full() {
+        let mut queue = SendQueue::default();
+        for i in 0..5 {
+            queue.push(Priority::Bulk, format!("chunk {}", i));
+        }
+
+        let buffered = Cell::new(0);
+        queue
+            .flush(|| buffered.get() < 3, |_| {
+                buffered.set(buffered.get() + 1);
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(queue.len(), 2);
+
+        assert_eq!(queue.flush(|| true, |_| Err("closed")), Err("closed"));
+        assert_eq!(queue.pop().as_deref(), Some("chunk 4"));
+    }
+
+    #[test]
+    fn plain_frames_queue_by_priority_too() {
+        let chunk = |index| Frame::FileChunk { transfer_id: "t1".to_string(), index, data: vec![] };
+        let receipt = Frame::Receipt { message_id: "m1".to_string(), kind: ReceiptKind::Read };
+
+        let mut outbox = SendQueue::<Frame>::default();
+        for frame in [chunk(0), chunk(1), receipt.clone()] {
+            outbox.push(Priority::of(&frame), frame);
+        }
+
+        assert_eq!(outbox.pop(), Some(receipt));
+        assert_eq!(outbox.pop(), Some(chunk(0)));
+        assert_eq!(outbox.len(), 1);
+    }
+
+    #[test]
+    fn frames_are_classified_by_urgency() {
+        let receipt = Frame::Receipt { message_id: "m1".to_string(), kind: ReceiptKind::Read };
+        let chunk = Frame::FileChunk { transfer_id: "t1".to_string(), index: 0, data: vec![1, 2, 3] };
+        let typing = Frame::Typing { is_typing: true, author: None };
+        let candidate = Frame::Candidate {
+            candidate: IceCandidate {
+                candidate: "candidate:1 1 udp 2122260223 192.0.2.1 54321 typ host".to_string(),
+                sdp_mid: "0".to_string(),
+                sdp_m_line_index: 0,
+            },
+        };
+
+        assert_eq!(Priority::of(&receipt), Priority::Control);
+        assert_eq!(Priority::of(&typing), Priority::Control);
+        assert_eq!(Priority::of(&candidate), Priority::Control);
+        assert_eq!(Priority::of(&chunk), Priority::Bulk);
+        assert!(Priority::Control > Priority::Normal && Priority::Normal > Priority::Bulk);
+    }
+}
@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo::events::EventListener;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{console, CloseEvent, MessageEvent, WebSocket};
+use yew::Callback;
+
+use crate::webrtc_chat::room::PeerId;
+use crate::webrtc_chat::web_rtc_manager::{IceCandidate, NetworkManager};
+
+/// Id the signaling server gives each connected browser.
+pub type ClientId = u32;
+
+/// What two browsers exchange through the server to set up a connection.
+/// The server relays it untouched.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SignalData {
+    /// JSON `RTCSessionDescriptionInit`, sent before gathering finishes.
+    Description { description: String },
+    /// One trickled candidate.
+    Candidate { candidate: IceCandidate },
+}
+
+#[derive(Debug, Clone)]
+pub enum SignalingEvent {
+    /// We joined; `peers` were in the room already.
+    Welcome { peers: Vec<ClientId> },
+    PeerJoined(ClientId),
+    PeerLeft(ClientId),
+    Signal { from: ClientId, data: SignalData },
+    /// The server refused something we sent.
+    Error(String),
+    Closed(String),
+}
+
+// Mirrors the relay protocol in signaling-server/src/rooms.rs.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage<'a> {
+    Join { room: &'a str },
+    Signal { to: ClientId, data: &'a SignalData },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    Welcome { peers: Vec<ClientId> },
+    PeerJoined { id: ClientId },
+    PeerLeft { id: ClientId },
+    Signal { from: ClientId, data: SignalData },
+    Error { message: String },
+}
+
+impl From<ServerMessage> for SignalingEvent {
+    fn from(message: ServerMessage) -> SignalingEvent {
+        match message {
+            ServerMessage::Welcome { peers } => SignalingEvent::Welcome { peers },
+            ServerMessage::PeerJoined { id } => SignalingEvent::PeerJoined(id),
+            ServerMessage::PeerLeft { id } => SignalingEvent::PeerLeft(id),
+            ServerMessage::Signal { from, data } => SignalingEvent::Signal { from, data },
+            ServerMessage::Error { message } => SignalingEvent::Error(message),
+        }
+    }
+}
+
+/// A WebSocket to the signaling server, joined to one room. Dropping it
+/// leaves the room; connections already set up through it keep running.
+pub struct SignalingClient {
+    socket: WebSocket,
+    _on_open: EventListener,
+    _on_message: EventListener,
+    _on_close: EventListener,
+}
+
+impl SignalingClient {
+    pub fn connect(url: &str, room: &str, on_event: Callback<SignalingEvent>) -> Result<SignalingClient, JsValue> {
+        let socket = WebSocket::new(url)?;
+
+        let join = serde_json::to_string(&ClientMessage::Join { room })
+            .map_err(|err| JsValue::from_str(&err.to_string()))?;
+        let open_socket = socket.clone();
+        let on_open = EventListener::once(&socket, "open", move |_| {
+            if let Err(err) = open_socket.send_with_str(&join) {
+                console::log_2(&"Failed to join the signaling room".into(), &err);
+            }
+        });
+
+        let message_event = on_event.clone();
+        let on_message = EventListener::new(&socket, "message", move |event| {
+            let Some(text) = event.unchecked_ref::<MessageEvent>().data().as_string() else {
+                return;
+            };
+            match serde_json::from_str::<ServerMessage>(&text) {
+                Ok(message) => message_event.emit(message.into()),
+                Err(err) => console::log_1(&format!("Unexpected signaling message: {}", err).into()),
+            }
+        });
+
+        // A socket that never opens only reports a close, without a reason
+        let on_close = EventListener::once(&socket, "close", move |event| {
+            let reason = event.unchecked_ref::<CloseEvent>().reason();
+            on_event.emit(SignalingEvent::Closed(if reason.is_empty() {
+                "the signaling server closed the connection".to_string()
+            } else {
+                reason
+            }));
+        });
+
+        Ok(SignalingClient { socket, _on_open: on_open, _on_message: on_message, _on_close: on_close })
+    }
+
+    pub fn send_signal(&self, to: ClientId, data: &SignalData) {
+        let message = serde_json::to_string(&ClientMessage::Signal { to, data }).expect("signals always serialize");
+        if let Err(err) = self.socket.send_with_str(&message) {
+            console::log_2(&"Failed to send a signal".into(), &err);
+        }
+    }
+}
+
+impl Drop for SignalingClient {
+    fn drop(&mut self) {
+        let _ = self.socket.close();
+    }
+}
+
+/// A connection being set up through the server, until its data channel
+/// opens and it joins the room's peers.
+pub struct SignaledConnection<T: NetworkManager + 'static> {
+    pub remote_id: ClientId,
+    pub peer_id: PeerId,
+    pub manager: Rc<RefCell<T>>,
+}
+
+/// Our membership of a signaling room.
+pub struct SignalingSession<T: NetworkManager + 'static> {
+    pub client: SignalingClient,
+    pub room: String,
+    /// The host offers a connection to everyone in the room; guests answer.
+    pub is_host: bool,
+    pub connections: Vec<SignaledConnection<T>>,
+}
+
+impl<T: NetworkManager + 'static> SignalingSession<T> {
+    pub fn by_remote_id(&self, remote_id: ClientId) -> Option<&SignaledConnection<T>> {
+        self.connections.iter().find(|connection| connection.remote_id == remote_id)
+    }
+
+    pub fn by_peer_id(&self, peer_id: PeerId) -> Option<&SignaledConnection<T>> {
+        self.connections.iter().find(|connection| connection.peer_id == peer_id)
+    }
+}
+
+/// Room names are typed by hand on both sides, so keep them short and
+/// easy to read out.
+pub fn random_room_name() -> String {
+    const WORDS: [&str; 12] = [
+        "amber", "birch", "cedar", "delta", "ember", "fjord", "grove", "harbor", "iris", "juniper", "kelp", "lotus",
+    ];
+    let word = WORDS[rand::random_range(0..WORDS.len())];
+    format!("{}-{:04}", word, rand::random_range(0..10_000))
+}
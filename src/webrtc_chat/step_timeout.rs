@@ -0,0 +1,222 @@
+use crate::webrtc_chat::chat_model::ConnectionStep;
+
+/// Tries per step, counting the first, before the wizard stops retrying on its own.
+pub const MAX_STEP_ATTEMPTS: u32 = 3;
+
+const RETRY_DELAY_MS: f64 = 5_000.0;
+
+/// What the connection wizard is waiting on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WaitKind {
+    /// Our own offer or answer is still collecting ICE candidates.
+    Gathering,
+    /// The code is out; the other side has to paste it and send one back.
+    Answer,
+    /// Codes are exchanged and ICE is checking candidate pairs.
+    Connecting,
+}
+
+impl WaitKind {
+    /// After this long the wizard says what might be holding things up.
+    pub fn still_waiting_ms(self) -> f64 {
+        match self {
+            WaitKind::Gathering => 5_000.0,
+            WaitKind::Answer => 120_000.0,
+            WaitKind::Connecting => 10_000.0,
+        }
+    }
+
+    /// After this long the step is abandoned. An offer nobody answered by
+    /// then is stale: its candidates may no longer be reachable.
+    pub fn expires_ms(self) -> f64 {
+        match self {
+            WaitKind::Gathering => 15_000.0,
+            WaitKind::Answer => 300_000.0,
+            WaitKind::Connecting => 30_000.0,
+        }
+    }
+}
+
+/// Which wait, if any, the wizard is in. `has_code` is whether our offer or
+/// answer exists yet, `ice_checking` whether the remote candidates arrived.
+pub fn expected_wait(step: &ConnectionStep, is_host: bool, has_code: bool, ice_checking: bool) -> Option<WaitKind> {
+    match step {
+        ConnectionStep::GeneratingCode => Some(WaitKind::Gathering),
+        ConnectionStep::SharingCode | ConnectionStep::WaitingForAnswer => {
+            if !has_code {
+                Some(WaitKind::Gathering)
+            } else if is_host && ice_checking {
+                Some(WaitKind::Connecting)
+            } else {
+                // A guest cannot tell whether the host used its answer yet
+                Some(WaitKind::Answer)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Progress of one wizard step against its deadline. Times are milliseconds
+/// since the epoch, as returned by `Date.now()`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StepTimeout {
+    /// `attempt` earlier tries expired; this one started at `started_at`.
+    Waiting { kind: WaitKind, attempt: u32, started_at: f64 },
+    /// The step expired and is tried again at `retry_at`.
+    Retrying { kind: WaitKind, attempt: u32, retry_at: f64 },
+    /// Every attempt expired; only the user can start over.
+    GaveUp { kind: WaitKind },
+}
+
+impl StepTimeout {
+    pub fn start(kind: WaitKind, now: f64) -> StepTimeout {
+        StepTimeout::Waiting { kind, attempt: 0, started_at: now }
+    }
+
+    pub fn kind(&self) -> WaitKind {
+        match self {
+            StepTimeout::Waiting { kind, .. }
+            | StepTimeout::Retrying { kind, .. }
+            | StepTimeout::GaveUp { kind } => *kind,
+        }
+    }
+
+    pub fn attempt(&self) -> u32 {
+        match self {
+            StepTimeout::Waiting { attempt, .. } | StepTimeout::Retrying { attempt, .. } => *attempt,
+            StepTimeout::GaveUp { .. } => MAX_STEP_ATTEMPTS,
+        }
+    }
+
+    /// Follows the wizard into its next wait. The attempt count carries over,
+    /// so a regenerated offer that stalls again still uses up the budget.
+    pub fn follow(&self, kind: WaitKind, now: f64) -> StepTimeout {
+        match self {
+            StepTimeout::Waiting { kind: current, .. } | StepTimeout::Retrying { kind: current, .. }
+                if *current == kind =>
+            {
+                self.clone()
+            }
+            StepTimeout::GaveUp { kind: current } if *current == kind => self.clone(),
+            _ => StepTimeout::Waiting {
+                kind,
+                attempt: self.attempt().min(MAX_STEP_ATTEMPTS - 1),
+                started_at: now,
+            },
+        }
+    }
+
+    /// Moves past any deadline that has passed. The flag is set when the
+    /// step should be retried right now.
+    pub fn advance(&self, now: f64) -> (StepTimeout, bool) {
+        match self {
+            StepTimeout::Waiting { kind, attempt, started_at } if now - started_at >= kind.expires_ms() => {
+                if attempt + 1 < MAX_STEP_ATTEMPTS {
+                    let retrying = StepTimeout::Retrying {
+                        kind: *kind,
+                        attempt: *attempt,
+                        retry_at: now + RETRY_DELAY_MS,
+                    };
+                    (retrying, false)
+                } else {
+                    (StepTimeout::GaveUp { kind: *kind }, false)
+                }
+            }
+            StepTimeout::Retrying { retry_at, .. } if now >= *retry_at => (self.retry_now(now), true),
+            _ => (self.clone(), false),
+        }
+    }
+
+    /// Skips the countdown, or starts over with a fresh budget after giving up.
+    pub fn retry_now(&self, now: f64) -> StepTimeout {
+        match self {
+            StepTimeout::Waiting { kind, attempt, .. } | StepTimeout::Retrying { kind, attempt, .. } => {
+                StepTimeout::Waiting {
+                    kind: *kind,
+                    attempt: (attempt + 1).min(MAX_STEP_ATTEMPTS - 1),
+                    started_at: now,
+                }
+            }
+            StepTimeout::GaveUp { kind } => StepTimeout::start(*kind, now),
+        }
+    }
+
+    pub fn is_still_waiting(&self, now: f64) -> bool {
+        match self {
+            StepTimeout::Waiting { kind, started_at, .. } => now - started_at >= kind.still_waiting_ms(),
+            _ => false,
+        }
+    }
+
+    /// Until the step expires while waiting, or until the retry fires.
+    pub fn seconds_left(&self, now: f64) -> u32 {
+        let deadline = match self {
+            StepTimeout::Waiting { kind, started_at, .. } => started_at + kind.expires_ms(),
+            StepTimeout::Retrying { retry_at, .. } => *retry_at,
+            StepTimeout::GaveUp { .. } => now,
+        };
+        ((deadline - now).max(0.0) / 1000.0).ceil() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wizard_steps_map_to_waits() {
+        let wait = |step, is_host, has_code, ice_checking| expected_wait(&step, is_host, has_code, ice_checking);
+
+        assert_eq!(wait(ConnectionStep::GeneratingCode, true, false, false), Some(WaitKind::Gathering));
+        assert_eq!(wait(ConnectionStep::SharingCode, true, true, false), Some(WaitKind::Answer));
+        assert_eq!(wait(ConnectionStep::WaitingForAnswer, true, true, true), Some(WaitKind::Connecting));
+        assert_eq!(wait(ConnectionStep::SharingCode, false, false, false), Some(WaitKind::Gathering));
+        assert_eq!(wait(ConnectionStep::SharingCode, false, true, true), Some(WaitKind::Answer));
+        assert_eq!(wait(ConnectionStep::WaitingForConnection, false, false, false), None);
+        assert_eq!(wait(ConnectionStep::Connected, true, true, true), None);
+    }
+
+    #[test]
+    fn escalates_then_retries_after_a_countdown() {
+        let timeout = StepTimeout::start(WaitKind::Gathering, 0.0);
+        assert!(!timeout.is_still_waiting(4_999.0));
+        assert!(timeout.is_still_waiting(5_000.0));
+        assert_eq!(timeout.seconds_left(5_000.0), 10);
+
+        let (retrying, retry) = timeout.advance(15_000.0);
+        assert!(!retry);
+        assert_eq!(retrying.seconds_left(15_000.0), 5);
+        assert_eq!(retrying.advance(19_000.0), (retrying.clone(), false));
+
+        let (waiting, retry) = retrying.advance(20_000.0);
+        assert!(retry);
+        assert_eq!(waiting, StepTimeout::Waiting { kind: WaitKind::Gathering, attempt: 1, started_at: 20_000.0 });
+    }
+
+    #[test]
+    fn gives_up_once_every_attempt_expired() {
+        let mut timeout = StepTimeout::start(WaitKind::Connecting, 0.0);
+        let mut now = 0.0;
+        let mut retries = 0;
+
+        while !matches!(timeout, StepTimeout::GaveUp { .. }) {
+            now += 1_000.0;
+            let (next, retry) = timeout.advance(now);
+            retries += retry as u32;
+            timeout = next;
+        }
+
+        assert_eq!(retries, MAX_STEP_ATTEMPTS - 1);
+        assert_eq!(timeout.retry_now(now), StepTimeout::start(WaitKind::Connecting, now));
+    }
+
+    #[test]
+    fn following_the_wizard_keeps_the_attempt_count() {
+        let regenerated = StepTimeout::Waiting { kind: WaitKind::Answer, attempt: 1, started_at: 0.0 }.retry_now(10.0);
+        let gathering = regenerated.follow(WaitKind::Gathering, 20.0);
+        assert_eq!(gathering, StepTimeout::Waiting { kind: WaitKind::Gathering, attempt: 2, started_at: 20.0 });
+
+        // The same wait keeps its deadline
+        assert_eq!(gathering.follow(WaitKind::Gathering, 30.0), gathering);
+    }
+}
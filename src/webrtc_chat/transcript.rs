@@ -0,0 +1,333 @@
+use serde::{Deserialize, Serialize};
+
+/// Bumped when the JSON shape changes, so older exports are refused rather than misread.
+pub const TRANSCRIPT_VERSION: u32 = 1;
+
+const REPLY_SNIPPET_LEN: usize = 60;
+
+/// A chat session as it is written to disk, independent of the live `Message`s.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub version: u32,
+    pub title: String,
+    pub exported_at: u64,
+    pub messages: Vec<TranscriptMessage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TranscriptMessage {
+    pub id: String,
+    pub sender: String, // display name at export time
+    pub from_me: bool,
+    pub timestamp: u64,
+    pub content: String,
+    #[serde(default)]
+    pub edited: bool,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub reply_to: Option<String>,
+    #[serde(default)]
+    pub attachment: Option<String>, // file name
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TranscriptFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl TranscriptFormat {
+    pub const ALL: [TranscriptFormat; 3] = [TranscriptFormat::Markdown, TranscriptFormat::Json, TranscriptFormat::Html];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TranscriptFormat::Markdown => "Markdown",
+            TranscriptFormat::Json => "JSON",
+            TranscriptFormat::Html => "HTML",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Markdown => "md",
+            TranscriptFormat::Json => "json",
+            TranscriptFormat::Html => "html",
+        }
+    }
+
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            TranscriptFormat::Markdown => "text/markdown",
+            TranscriptFormat::Json => "application/json",
+            TranscriptFormat::Html => "text/html",
+        }
+    }
+}
+
+impl Transcript {
+    pub fn new(title: String, exported_at: u64, messages: Vec<TranscriptMessage>) -> Transcript {
+        Transcript {
+            version: TRANSCRIPT_VERSION,
+            title,
+            exported_at,
+            messages,
+        }
+    }
+
+    /// Reads a JSON export back. Anything else, or an export from a newer
+    /// version, is refused with a message for the user.
+    pub fn from_json(json: &str) -> Result<Transcript, String> {
+        let transcript: Transcript =
+            serde_json::from_str(json).map_err(|err| format!("Not a chat transcript: {}", err))?;
+        if transcript.version > TRANSCRIPT_VERSION {
+            return Err(format!(
+                "This transcript was exported by a newer version (format {})",
+                transcript.version
+            ));
+        }
+        Ok(transcript)
+    }
+
+    pub fn render(&self, format: TranscriptFormat) -> String {
+        match format {
+            TranscriptFormat::Markdown => self.to_markdown(),
+            TranscriptFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            TranscriptFormat::Html => self.to_html(),
+        }
+    }
+
+    pub fn file_name(&self, format: TranscriptFormat) -> String {
+        let stamp: String = format_timestamp(self.exported_at)
+            .chars()
+            .take(16)
+            .map(|c| if c.is_ascii_digit() { c } else { '-' })
+            .collect();
+        format!("chat-{}.{}", stamp, format.extension())
+    }
+
+    /// The message a reply points at, if it is part of the transcript.
+    pub fn parent_of(&self, message: &TranscriptMessage) -> Option<&TranscriptMessage> {
+        let parent_id = message.reply_to.as_ref()?;
+        self.messages.iter().find(|parent| &parent.id == parent_id)
+    }
+
+    fn to_markdown(&self) -> String {
+        let mut out = format!(
+            "# {}\n\nExported {} · {} messages\n",
+            self.title,
+            format_timestamp(self.exported_at),
+            self.messages.len()
+        );
+
+        for message in &self.messages {
+            out.push_str(&format!("\n**{}** · {}", message.sender, format_timestamp(message.timestamp)));
+            if message.edited && !message.deleted {
+                out.push_str(" (edited)");
+            }
+            out.push('\n');
+
+            if let Some(parent) = self.parent_of(message) {
+                out.push_str(&format!("> ↪ **{}**: {}\n", parent.sender, parent.snippet()));
+            }
+            if message.deleted {
+                out.push_str("_This message was deleted_\n");
+                continue;
+            }
+            if let Some(name) = &message.attachment {
+                out.push_str(&format!("📎 {}\n", name));
+            }
+            if !message.content.is_empty() {
+                out.push_str(&message.content);
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    fn to_html(&self) -> String {
+        let mut body = String::new();
+        for message in &self.messages {
+            let mut meta = format!("{} · {}", escape_html(&message.sender), format_timestamp(message.timestamp));
+            if message.edited && !message.deleted {
+                meta.push_str(" (edited)");
+            }
+
+            body.push_str(&format!(
+                "<div class=\"message {}\">\n<div class=\"meta\">{}</div>\n",
+                if message.from_me { "me" } else { "other" },
+                meta
+            ));
+            if let Some(parent) = self.parent_of(message) {
+                body.push_str(&format!(
+                    "<blockquote>↪ {}: {}</blockquote>\n",
+                    escape_html(&parent.sender),
+                    escape_html(&parent.snippet())
+                ));
+            }
+            if message.deleted {
+                body.push_str("<div class=\"content deleted\">This message was deleted</div>\n");
+            } else {
+                if let Some(name) = &message.attachment {
+                    body.push_str(&format!("<div class=\"attachment\">📎 {}</div>\n", escape_html(name)));
+                }
+                body.push_str(&format!("<div class=\"content\">{}</div>\n", escape_html(&message.content)));
+            }
+            body.push_str("</div>\n");
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{style}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p class=\"exported\">Exported {exported} · {count} messages</p>\n{body}</body>\n</html>\n",
+            title = escape_html(&self.title),
+            style = HTML_STYLE,
+            exported = format_timestamp(self.exported_at),
+            count = self.messages.len(),
+            body = body,
+        )
+    }
+}
+
+impl TranscriptMessage {
+    fn snippet(&self) -> String {
+        if self.deleted {
+            return "deleted message".to_string();
+        }
+        let text = self.content.lines().next().unwrap_or_default();
+        if text.chars().count() > REPLY_SNIPPET_LEN {
+            format!("{}…", text.chars().take(REPLY_SNIPPET_LEN).collect::<String>())
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+const HTML_STYLE: &str = "body { font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; color: #1f2937; }
+.exported { color: #6b7280; font-size: 0.875rem; }
+.message { margin: 0.75rem 0; padding: 0.75rem 1rem; border-radius: 1rem; max-width: 80%; }
+.me { margin-left: auto; background: #3b82f6; color: white; }
+.other { background: #e5e7eb; }
+.meta { font-size: 0.75rem; font-weight: bold; opacity: 0.75; }
+.content { white-space: pre-wrap; word-break: break-word; }
+.deleted { font-style: italic; opacity: 0.75; }
+blockquote { margin: 0.25rem 0; padding-left: 0.5rem; border-left: 3px solid currentColor; font-size: 0.875rem; opacity: 0.8; }
+";
+
+/// `2024-05-01 14:03:09 UTC`, from milliseconds since the epoch. Exports
+/// must read the same wherever they are opened, so no local time zone.
+pub fn format_timestamp(timestamp: u64) -> String {
+    let seconds = timestamp / 1000;
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+// Howard Hinnant's days-to-civil algorithm for the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(id: &str, sender: &str, content: &str) -> TranscriptMessage {
+        TranscriptMessage {
+            id: id.to_string(),
+            sender: sender.to_string(),
+            from_me: sender == "Me",
+            timestamp: 1_714_572_189_000,
+            content: content.to_string(),
+            edited: false,
+            deleted: false,
+            reply_to: None,
+            attachment: None,
+        }
+    }
+
+    fn transcript() -> Transcript {
+        let mut reply = message("2", "Alice", "Agreed, <b>ship it</b>");
+        reply.reply_to = Some("1".to_string());
+        reply.edited = true;
+        let mut deleted = message("3", "Me", "oops");
+        deleted.deleted = true;
+
+        Transcript::new(
+            "Pairing with Alice".to_string(),
+            1_714_572_300_000,
+            vec![message("1", "Me", "Use the retry queue?"), reply, deleted],
+        )
+    }
+
+    #[test]
+    fn timestamps_are_utc() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_714_572_189_000), "2024-05-01 14:03:09 UTC");
+        assert_eq!(format_timestamp(951_782_400_000), "2000-02-29 00:00:00 UTC");
+        assert_eq!(transcript().file_name(TranscriptFormat::Json), "chat-2024-05-01-14-05.json");
+    }
+
+    #[test]
+    fn markdown_labels_senders_and_replies() {
+        let markdown = transcript().render(TranscriptFormat::Markdown);
+
+        assert!(markdown.starts_with("# Pairing with Alice\n\nExported 2024-05-01 14:05:00 UTC · 3 messages\n"));
+        assert!(markdown.contains("\n**Alice** · 2024-05-01 14:03:09 UTC (edited)\n> ↪ **Me**: Use the retry queue?\nAgreed, <b>ship it</b>\n"));
+        assert!(markdown.contains("_This message was deleted_"));
+        assert!(!markdown.contains("oops"));
+    }
+
+    #[test]
+    fn html_escapes_message_text() {
+        let html = transcript().render(TranscriptFormat::Html);
+
+        assert!(html.contains("<div class=\"content\">Agreed, &lt;b&gt;ship it&lt;/b&gt;</div>"));
+        assert!(!html.contains("<b>ship it</b>"));
+        assert!(!html.contains("oops"));
+    }
+
+    #[test]
+    fn json_round_trips_and_refuses_other_files() {
+        let original = transcript();
+        let imported = Transcript::from_json(&original.render(TranscriptFormat::Json)).unwrap();
+        assert_eq!(imported, original);
+
+        assert!(Transcript::from_json("{\"title\": \"not a transcript\"}").is_err());
+
+        let mut newer = original;
+        newer.version = TRANSCRIPT_VERSION + 1;
+        assert!(Transcript::from_json(&newer.render(TranscriptFormat::Json)).is_err());
+    }
+}
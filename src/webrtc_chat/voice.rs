@@ -0,0 +1,231 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use gloo::events::EventListener;
+use js_sys::{Array, Uint8Array};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    AudioBuffer, AudioContext, Blob, BlobEvent, BlobPropertyBag, MediaRecorder, MediaStream,
+    MediaStreamConstraints, MediaStreamTrack, RecordingState,
+};
+use yew::Callback;
+
+/// Bars drawn for one voice message.
+pub const WAVEFORM_BARS: usize = 48;
+
+/// Recording stops on its own after this long.
+pub const MAX_VOICE_MS: u32 = 120_000;
+
+/// Shorter recordings are treated as an accidental tap and dropped.
+pub const MIN_VOICE_MS: u32 = 500;
+
+// MediaRecorder hands over a blob this often, so a stop loses little audio.
+const TIME_SLICE_MS: i32 = 250;
+
+/// What the receiver needs to draw the player before the audio has arrived.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct VoiceInfo {
+    pub duration_ms: u32,
+    /// Peak level of each bar, 0 to 255.
+    pub waveform: Vec<u8>,
+}
+
+/// A finished recording, encoded the way the browser's MediaRecorder chose.
+#[derive(Debug, Clone)]
+pub struct VoiceClip {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+    pub info: VoiceInfo,
+}
+
+/// Records one voice message from a microphone (or any other audio) stream.
+///
+/// `stop` only asks the recorder to finish; the clip arrives on `on_done`
+/// a moment later, so keep the recorder alive until then.
+pub struct VoiceRecorder {
+    recorder: MediaRecorder,
+    stream: MediaStream,
+    _on_data: EventListener,
+    _on_stop: EventListener,
+}
+
+impl VoiceRecorder {
+    pub fn start(
+        stream: MediaStream,
+        on_done: Callback<Result<VoiceClip, String>>,
+    ) -> Result<VoiceRecorder, JsValue> {
+        let recorder = MediaRecorder::new_with_media_stream(&stream)?;
+        let chunks: Rc<RefCell<Vec<Blob>>> = Rc::new(RefCell::new(vec![]));
+        let started_at = js_sys::Date::now();
+
+        let data_chunks = chunks.clone();
+        let on_data = EventListener::new(&recorder, "dataavailable", move |event| {
+            if let Some(blob) = event.unchecked_ref::<BlobEvent>().data() {
+                data_chunks.borrow_mut().push(blob);
+            }
+        });
+
+        let stop_recorder = recorder.clone();
+        let on_stop = EventListener::new(&recorder, "stop", move |_| {
+            let elapsed_ms = (js_sys::Date::now() - started_at) as u32;
+            let blobs = chunks.take();
+            let mime_type = stop_recorder.mime_type();
+            let on_done = on_done.clone();
+            spawn_local(async move {
+                on_done.emit(finish(blobs, mime_type, elapsed_ms).await);
+            });
+        });
+
+        recorder.start_with_time_slice(TIME_SLICE_MS)?;
+
+        Ok(VoiceRecorder { recorder, stream, _on_data: on_data, _on_stop: on_stop })
+    }
+
+    pub fn stop(&self) {
+        if self.recorder.state() != RecordingState::Inactive
+            && let Err(err) = self.recorder.stop()
+        {
+            web_sys::console::log_2(&"Failed to stop the recorder".into(), &err);
+        }
+        stop_tracks(&self.stream);
+    }
+}
+
+impl Drop for VoiceRecorder {
+    fn drop(&mut self) {
+        // Turns the browser's microphone indicator off
+        stop_tracks(&self.stream);
+    }
+}
+
+pub async fn open_microphone() -> Result<MediaStream, String> {
+    let media_devices = web_sys::window()
+        .ok_or("no window")?
+        .navigator()
+        .media_devices()
+        .map_err(|_| "microphone access is not supported")?;
+
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_audio(&JsValue::TRUE);
+    constraints.set_video(&JsValue::FALSE);
+
+    let promise = media_devices
+        .get_user_media_with_constraints(&constraints)
+        .map_err(|_| "microphone access is not supported")?;
+
+    JsFuture::from(promise)
+        .await
+        .map(|stream| stream.unchecked_into::<MediaStream>())
+        .map_err(|_| "permission denied or no microphone found".to_string())
+}
+
+pub fn stop_tracks(stream: &MediaStream) {
+    for track in stream.get_tracks().iter() {
+        track.unchecked_into::<MediaStreamTrack>().stop();
+    }
+}
+
+/// File name used for the transfer, with an extension matching the codec.
+pub fn file_name(mime_type: &str) -> String {
+    let extension = match mime_type.split(';').next().unwrap_or_default() {
+        "audio/mp4" => "m4a",
+        "audio/ogg" => "ogg",
+        _ => "webm",
+    };
+    format!("Voice message.{}", extension)
+}
+
+/// "1:07" style duration for the player.
+pub fn format_duration(duration_ms: u32) -> String {
+    let seconds = duration_ms.div_ceil(1000);
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Splits the samples into `bars` equal slices and scales the peak of each
+/// so the loudest slice reaches 255.
+pub fn waveform_bars(samples: &[f32], bars: usize) -> Vec<u8> {
+    if samples.is_empty() || bars == 0 {
+        return vec![];
+    }
+
+    let slice_len = samples.len().div_ceil(bars);
+    let peaks: Vec<f32> = samples
+        .chunks(slice_len)
+        .map(|slice| slice.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs())))
+        .collect();
+    let loudest = peaks.iter().cloned().fold(0.0f32, f32::max);
+    if loudest == 0.0 {
+        return vec![0; peaks.len()];
+    }
+
+    peaks
+        .iter()
+        .map(|peak| (peak / loudest * 255.0).round() as u8)
+        .collect()
+}
+
+async fn finish(blobs: Vec<Blob>, mime_type: String, elapsed_ms: u32) -> Result<VoiceClip, String> {
+    let parts = Array::new();
+    for blob in &blobs {
+        parts.push(blob);
+    }
+    let options = BlobPropertyBag::new();
+    options.set_type(&mime_type);
+    let blob = Blob::new_with_blob_sequence_and_options(&parts, &options)
+        .map_err(|_| "Failed to assemble the recording")?;
+    let buffer = JsFuture::from(blob.array_buffer())
+        .await
+        .map_err(|_| "Failed to read the recording")?;
+    let data = Uint8Array::new(&buffer).to_vec();
+
+    // Decoding is only needed for the waveform; a clip the browser cannot
+    // decode is still sent, with a flat line and the wall clock duration
+    let info = match decode(&data).await {
+        Ok(audio) => VoiceInfo {
+            duration_ms: (audio.duration() * 1000.0) as u32,
+            waveform: audio
+                .get_channel_data(0)
+                .map(|samples| waveform_bars(&samples, WAVEFORM_BARS))
+                .unwrap_or_default(),
+        },
+        Err(err) => {
+            web_sys::console::log_2(&"Could not decode the recording".into(), &err);
+            VoiceInfo { duration_ms: elapsed_ms, waveform: vec![] }
+        }
+    };
+
+    Ok(VoiceClip { data, mime_type, info })
+}
+
+async fn decode(data: &[u8]) -> Result<AudioBuffer, JsValue> {
+    let context = AudioContext::new()?;
+    // decodeAudioData detaches the buffer it is given, so hand it a copy
+    let buffer = Uint8Array::from(data).buffer();
+    let decoded = JsFuture::from(context.decode_audio_data(&buffer)?).await;
+    let _ = context.close();
+    Ok(decoded?.unchecked_into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waveform_is_scaled_to_the_loudest_bar() {
+        let samples = [0.1, -0.2, 0.0, 0.05, -0.4, 0.4, 0.0, 0.0];
+
+        assert_eq!(waveform_bars(&samples, 4), vec![128, 32, 255, 0]);
+        assert_eq!(waveform_bars(&[0.0; 10], 5), vec![0; 5]);
+        assert!(waveform_bars(&[], 4).is_empty());
+    }
+
+    #[test]
+    fn durations_round_up_to_whole_seconds() {
+        assert_eq!(format_duration(400), "0:01");
+        assert_eq!(format_duration(67_000), "1:07");
+        assert_eq!(file_name("audio/webm;codecs=opus"), "Voice message.webm");
+        assert_eq!(file_name("audio/mp4"), "Voice message.m4a");
+    }
+}
@@ -1,28 +1,41 @@
 use wasm_bindgen::{JsCast, JsValue};
 
-use crate::webrtc_chat::chat_model::{ChatModel, ConnectionString, Msg, Message, MessageSender};
+use crate::config::{Config, WebRtcDefaults};
+use crate::webrtc_chat::chat_model::{ChatModel, ConnectionString, Msg};
+use crate::webrtc_chat::connection_code;
+use crate::webrtc_chat::diagnostics::{self, ConnectionStats};
+use crate::webrtc_chat::protocol::{self, Frame};
+use crate::webrtc_chat::room::PeerId;
+use crate::webrtc_chat::send_queue::{Priority, SendQueue, BUFFERED_AMOUNT_LOW, MAX_BUFFERED_AMOUNT};
+use crate::webrtc_chat::signaling::SignalData;
 
 use std::cell::RefCell;
+use std::fmt;
 use std::rc::Rc;
-use std::str;
 
-use base64;
 use js_sys::{Array, Object, Reflect, JSON};
 use serde::{Deserialize, Serialize};
+use gloo_timers::future::TimeoutFuture;
 use wasm_bindgen::closure::Closure;
-use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
 use web_sys::{
-    console, RtcConfiguration, RtcDataChannel, RtcDataChannelEvent, RtcDataChannelInit,
-    RtcDataChannelState, RtcIceCandidate, RtcIceCandidateInit, RtcIceConnectionState,
-    RtcIceGatheringState, RtcPeerConnection, RtcPeerConnectionIceEvent, RtcSessionDescriptionInit,
+    console, MediaStream, MediaStreamTrack, RtcConfiguration, RtcDataChannel, RtcDataChannelEvent,
+    RtcDataChannelInit, RtcDataChannelState, RtcIceCandidateInit,
+    RtcIceConnectionState, RtcIceGatheringState, RtcIceTransportPolicy, RtcOfferOptions,
+    RtcPeerConnection, RtcPeerConnectionIceEvent, RtcRtpTransceiver, RtcRtpTransceiverDirection,
+    RtcRtpTransceiverInit, RtcSdpType, RtcSessionDescriptionInit, RtcSignalingState, RtcTrackEvent,
 };
 
 use yew::html::Scope;
+use yew::Callback;
 
 type SingleArgClosure = Closure<dyn FnMut(JsValue)>;
 type SingleArgJsFn = Box<dyn FnMut(JsValue)>;
 
-const STUN_SERVER: &str = "stun:stun.l.google.com:19302";
+// How long an in-band offer waits for the peer's answer before it is rolled
+// back, so a lost answer does not block every later renegotiation.
+const ANSWER_POLL_MS: u32 = 100;
+const ANSWER_MAX_POLLS: u32 = 100;
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct ConnectionState {
@@ -47,9 +60,58 @@ pub enum OfferError {
     InvalidString,
     SerializationError,
     InvalidOffer,
+    ChecksumMismatch,
     //InvalidCandidate,
 }
 
+/// Why a connection could not be set up or used.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum WebRtcError {
+    /// There is no data channel, or it is no longer open.
+    ChannelClosed,
+    /// The browser refused a step of the offer/answer exchange.
+    NegotiationFailed(String),
+    /// A connection code or session description cannot be used.
+    InvalidSdp(OfferError),
+    /// The peer did not answer in time.
+    Timeout,
+}
+
+impl fmt::Display for WebRtcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebRtcError::ChannelClosed => write!(f, "The connection is closed"),
+            WebRtcError::NegotiationFailed(reason) => write!(f, "Could not set up the connection: {}", reason),
+            WebRtcError::InvalidSdp(error) => {
+                let reason = match error {
+                    OfferError::InvalidBase64 | OfferError::InvalidString => "it is not a connection code",
+                    OfferError::SerializationError | OfferError::InvalidOffer => "it does not hold a valid offer",
+                    OfferError::ChecksumMismatch => "it was not copied completely",
+                };
+                write!(f, "This connection code cannot be used: {}", reason)
+            }
+            WebRtcError::Timeout => write!(f, "The other side did not answer in time"),
+        }
+    }
+}
+
+impl From<OfferError> for WebRtcError {
+    fn from(error: OfferError) -> WebRtcError {
+        WebRtcError::InvalidSdp(error)
+    }
+}
+
+// Browser exceptions are `DOMException`s; their message is all we show
+impl From<JsValue> for WebRtcError {
+    fn from(error: JsValue) -> WebRtcError {
+        let message = error
+            .as_string()
+            .or_else(|| Reflect::get(&error, &"message".into()).ok().and_then(|message| message.as_string()))
+            .unwrap_or_else(|| format!("{:?}", error));
+        WebRtcError::NegotiationFailed(message)
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub enum State {
     Default,
@@ -57,58 +119,107 @@ pub enum State {
     Client(ConnectionState),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct IceCandidate {
-    candidate: String,
-    sdp_mid: String,
-    sdp_m_line_index: u16,
+    pub candidate: String,
+    pub sdp_mid: String,
+    pub sdp_m_line_index: u16,
 }
 
 pub trait NetworkManager {
-    fn new(link: &Scope<ChatModel<Self>>) -> Rc<RefCell<Self>>
+    fn new(link: &Scope<ChatModel<Self>>, peer_id: PeerId) -> Rc<RefCell<Self>>
     where
         Self: Sized;
-    fn send_message(&self, message_content: &str);
+    /// Queues `message_content` behind anything more urgent and sends what
+    /// the channel's buffer has room for.
+    fn send_message(&self, message_content: &str, priority: Priority) -> Result<(), WebRtcError>;
+    /// Frames waiting for the buffer to drain; `Msg::SendQueueDrained` follows each drain.
+    fn queued_frames(&self) -> usize;
     fn get_state(&self) -> State;
     fn set_state(&mut self, new_state: State);
     fn get_offer(&self) -> Option<String>;
     fn get_ice_candidates(&self) -> Vec<IceCandidate>;
-    fn validate_offer(web_rtc_manager: Rc<RefCell<Self>>, str: &str) -> Result<(), OfferError>;
-    fn validate_answer(web_rtc_manager: Rc<RefCell<Self>>, str: &str) -> Result<(), OfferError>;
-    fn start_web_rtc(web_rtc_manager: Rc<RefCell<Self>>) -> Result<(), JsValue>;
+    fn set_ice_settings(&mut self, ice_settings: WebRtcDefaults);
+    /// Checks a pasted code right away; failures of the asynchronous steps
+    /// that follow arrive as `Msg::ConnectionError`.
+    fn validate_offer(web_rtc_manager: Rc<RefCell<Self>>, str: &str) -> Result<(), WebRtcError>;
+    fn validate_answer(web_rtc_manager: Rc<RefCell<Self>>, str: &str) -> Result<(), WebRtcError>;
+    fn start_web_rtc(web_rtc_manager: Rc<RefCell<Self>>) -> Result<(), WebRtcError>;
+    fn restart_ice(web_rtc_manager: Rc<RefCell<Self>>) -> Result<(), WebRtcError>;
+    /// Sends the audio and video tracks of `stream` to the peer, or stops
+    /// sending media with `None`. The first call on a connection adds an
+    /// audio and a video section and renegotiates over the data channel;
+    /// later calls, mute and screen sharing only swap tracks.
+    fn set_local_media(web_rtc_manager: Rc<RefCell<Self>>, stream: Option<MediaStream>) -> Result<(), WebRtcError>;
+    /// Reads the connection's stats; they arrive as `Msg::StatsReceived`.
+    fn request_stats(web_rtc_manager: Rc<RefCell<Self>>);
+    /// Exchanges the session through `signal` instead of a connection code:
+    /// the offer or answer goes out as soon as it is made and candidates
+    /// trickle after it. Call before `start_web_rtc`.
+    fn use_signaling(&mut self, signal: Callback<SignalData>);
+    /// Applies a description or candidate the peer sent through the
+    /// signaling server, answering offers.
+    fn receive_signal(web_rtc_manager: Rc<RefCell<Self>>, data: SignalData);
+    /// Applies a description or candidate the peer sent over the secure
+    /// channel to renegotiate a connected session. Ours go out as
+    /// `Msg::SendSecureFrame`, so they are sealed like every other frame.
+    fn receive_renegotiation(web_rtc_manager: Rc<RefCell<Self>>, data: SignalData);
 }
 
 pub struct WebRTCManager {
     state: State,
     rtc_peer_connection: Option<RtcPeerConnection>,
     data_channel: Option<RtcDataChannel>,
-    exit_offer_or_answer_early: bool,
     ice_candidates: Vec<IceCandidate>,
     offer: Option<String>,
+    ice_settings: WebRtcDefaults,
+    remote_stream: Option<MediaStream>, // every track the peer sends us
+    signaling: Option<Callback<SignalData>>,
+    pending_candidates: Vec<IceCandidate>, // trickled before the remote description
+    making_offer: bool,                    // an in-band offer is being made
+    ignore_offer: bool,                    // the last remote offer collided with ours and lost
+    send_queue: RefCell<SendQueue>,
+    peer_id: PeerId,
     parent_link: Scope<ChatModel<Self>>,
 }
 
 impl NetworkManager for WebRTCManager {
-    fn new(link: &Scope<ChatModel<Self>>) -> Rc<RefCell<Self>> {
+    fn new(link: &Scope<ChatModel<Self>>, peer_id: PeerId) -> Rc<RefCell<Self>> {
+        let config_str = include_str!("../../config.json");
+        let config: Config = serde_json::from_str(config_str).expect("Failed to parse config.json");
+
         Rc::new(RefCell::new(WebRTCManager {
             state: State::Default,
             rtc_peer_connection: None,
             data_channel: None,
             ice_candidates: Vec::new(),
             offer: None,
+            ice_settings: config.webrtc_defaults,
+            remote_stream: None,
+            signaling: None,
+            pending_candidates: Vec::new(),
+            making_offer: false,
+            ignore_offer: false,
+            send_queue: RefCell::new(SendQueue::default()),
+            peer_id,
             parent_link: link.clone(),
-            exit_offer_or_answer_early: false,
         }))
     }
 
-    fn send_message(&self, message_content: &str) {
-        self.data_channel
+    fn send_message(&self, message_content: &str, priority: Priority) -> Result<(), WebRtcError> {
+        let data_channel = self
+            .data_channel
             .as_ref()
-            .expect("must have a data channel")
-            .send_with_str(message_content)
-            .expect("channel is open");
+            .filter(|data_channel| data_channel.ready_state() == RtcDataChannelState::Open)
+            .ok_or(WebRtcError::ChannelClosed)?;
+
+        let mut send_queue = self.send_queue.borrow_mut();
+        send_queue.push(priority, message_content.to_string());
+        flush_send_queue(data_channel, &mut send_queue)
+    }
 
-        //TODO error handling ?
+    fn queued_frames(&self) -> usize {
+        self.send_queue.borrow().len()
     }
 
     fn get_state(&self) -> State {
@@ -127,211 +238,114 @@ impl NetworkManager for WebRTCManager {
         self.ice_candidates.clone()
     }
 
+    fn set_ice_settings(&mut self, ice_settings: WebRtcDefaults) {
+        self.ice_settings = ice_settings;
+    }
+
     fn validate_offer(
         web_rtc_manager: Rc<RefCell<WebRTCManager>>,
         str: &str,
-    ) -> Result<(), OfferError> {
-        let connection_string = WebRTCManager::parse_base64_str_to_connection(str);
-
-        if connection_string.is_err() {
-            return Err(connection_string.err().unwrap());
-        }
-
-        let connection_string = connection_string.ok().unwrap();
-
-        let remote_description_js_value: JsValue =
-            JSON::parse(&connection_string.offer).expect("Expected valid json");
-
+    ) -> Result<(), WebRtcError> {
+        let connection_string = WebRTCManager::parse_base64_str_to_connection(str)?;
         let remote_description =
-            remote_description_js_value.unchecked_into::<RtcSessionDescriptionInit>();
-
-        let web_rtc_manager_rc_clone = web_rtc_manager.clone();
-
-        let create_answer_function: Box<dyn FnMut(JsValue)> = Box::new(move |a: JsValue| {
-            let connection_string = Rc::new(connection_string.clone());
-            let clone = web_rtc_manager_rc_clone.clone();
-
-            let set_candidates_function: SingleArgJsFn = Box::new(move |_: JsValue| {
-                WebRTCManager::set_candidates(clone.clone(), &*connection_string);
-            });
-
-            let set_candidates_closure = Closure::wrap(set_candidates_function);
-            let web_rtc_manager_rc_clone_for_error_handler = web_rtc_manager_rc_clone.clone();
-
-            let create_answer_exception_handler =
-                Closure::wrap(Box::new(move |_send_channel: JsValue| {
-                    web_rtc_manager_rc_clone_for_error_handler
-                        .borrow_mut()
-                        .exit_offer_or_answer_early = true;
-
-                    console::log_1(&"Exception handler !".into());
-                    console::log_1(&a);
-
-                    web_sys::Window::alert_with_message(
-                        &web_sys::window().unwrap(),
-                        &"Promise create_answer encountered an exception. See console for details"
-                            .to_string(),
-                    )
-                    .expect("alert should work");
-                }) as SingleArgJsFn);
-
-            let web_rtc_manager_rc_clone_clone = web_rtc_manager_rc_clone.clone();
-
-            let set_local_description_closure = Closure::wrap(Box::new(move |answer: JsValue| {
-                let answer = answer.unchecked_into::<RtcSessionDescriptionInit>();
-
-                let set_local_description_exception_handler = WebRTCManager::get_exception_handler(
-                    web_rtc_manager_rc_clone_clone.clone(),
-                    "set_local_description closure has encountered an exception".into(),
-                );
-
+            JSON::parse(&connection_string.offer).map_err(|_| OfferError::InvalidOffer)?;
+        let rtc_peer_connection = WebRTCManager::peer_connection(&web_rtc_manager)?;
+
+        spawn_local(async move {
+            let result = async {
+                JsFuture::from(rtc_peer_connection.set_remote_description(
+                    remote_description.unchecked_ref::<RtcSessionDescriptionInit>(),
+                ))
+                .await
+                .map_err(|_| OfferError::InvalidOffer)?;
+
+                let answer = JsFuture::from(rtc_peer_connection.create_answer()).await?;
                 console::log_1(&"setting local description".into());
+                JsFuture::from(rtc_peer_connection.set_local_description(answer.unchecked_ref()))
+                    .await?;
+                web_rtc_manager.borrow_mut().offer = Some(String::from(JSON::stringify(&answer)?));
 
-                let _promise = web_rtc_manager_rc_clone_clone
-                    .borrow()
-                    .rtc_peer_connection
-                    .as_ref()
-                    .unwrap()
-                    .set_local_description(&answer)
-                    .catch(&set_local_description_exception_handler);
-
-                console::log_1(&answer.clone().into());
-
-                web_rtc_manager_rc_clone_clone.borrow_mut().offer =
-                    Some(String::from(JSON::stringify(&answer).unwrap()));
-            }) as SingleArgJsFn);
+                for candidate in &connection_string.ice_candidates {
+                    WebRTCManager::add_remote_candidate(&web_rtc_manager, &rtc_peer_connection, candidate);
+                }
+                Ok::<(), WebRtcError>(())
+            }
+            .await;
 
-            // TODO: .await this
-            _ = JsFuture::from(
-                web_rtc_manager_rc_clone
-                    .borrow()
-                    .rtc_peer_connection
-                    .as_ref()
-                    .unwrap()
-                    .create_answer()
-                    .then(&set_local_description_closure)
-                    .catch(&create_answer_exception_handler)
-                    .then(&set_candidates_closure),
-            );
-
-            set_candidates_closure.forget();
-            set_local_description_closure.forget();
+            if let Err(error) = result {
+                WebRTCManager::report(&web_rtc_manager, error);
+            }
         });
 
-        let create_answer_closure = Closure::wrap(create_answer_function);
-
-        let web_rtc_manager_rc_clone = web_rtc_manager.clone();
-        let set_remote_description_exception_handler =
-            Closure::wrap(Box::new(move |_send_channel: JsValue| {
-                web_rtc_manager_rc_clone
-                    .borrow_mut()
-                    .exit_offer_or_answer_early = true;
-            }) as SingleArgJsFn);
-
-        let _promise = web_rtc_manager
-            .borrow()
-            .rtc_peer_connection
-            .as_ref()
-            .unwrap()
-            .set_remote_description(&remote_description)
-            .catch(&set_remote_description_exception_handler)
-            .then(&create_answer_closure);
-
-        create_answer_closure.forget();
-
         Ok(())
     }
 
     fn validate_answer(
         web_rtc_manager: Rc<RefCell<WebRTCManager>>,
         str: &str,
-    ) -> Result<(), OfferError> {
-        let connection_string = WebRTCManager::parse_base64_str_to_connection(str);
-
-        if connection_string.is_err() {
-            return Err(connection_string.err().unwrap());
-        }
-
-        let connection_string = connection_string.ok().unwrap();
-
-        let remote_description_js_value: JsValue =
-            JSON::parse(&connection_string.offer).expect("Expected valid json");
-
+    ) -> Result<(), WebRtcError> {
+        let connection_string = WebRTCManager::parse_base64_str_to_connection(str)?;
         let remote_description =
-            remote_description_js_value.unchecked_into::<RtcSessionDescriptionInit>();
-
-        let web_rtc_manager_rc_clone = web_rtc_manager.clone();
-
-        let set_remote_description_exception_handler = Closure::wrap(Box::new(move |a: JsValue| {
-            web_rtc_manager_rc_clone
-                .borrow_mut()
-                .exit_offer_or_answer_early = true;
-
-            console::log_1(&"Exception handler !".into());
-            console::log_1(&a);
-
-            web_sys::Window::alert_with_message(
-                &web_sys::window().unwrap(),
-                &"Promise set_remote_description encountered an exception. See console for details"
-                    .to_string(),
-            )
-            .expect("alert should work");
-
-            web_rtc_manager_rc_clone
-                .borrow()
-                .parent_link
-                .send_message(Msg::ResetWebRTC);
-        }) as SingleArgJsFn);
-
-        let connection_string = Rc::new(connection_string);
-        let web_rtc_manager_rc_clone = web_rtc_manager.clone();
-        let set_candidates_function: SingleArgJsFn = Box::new(move |_: JsValue| {
-            WebRTCManager::set_candidates(web_rtc_manager_rc_clone.clone(), &*connection_string);
+            JSON::parse(&connection_string.offer).map_err(|_| OfferError::InvalidOffer)?;
+        let rtc_peer_connection = WebRTCManager::peer_connection(&web_rtc_manager)?;
+
+        spawn_local(async move {
+            let result = JsFuture::from(rtc_peer_connection.set_remote_description(
+                remote_description.unchecked_ref::<RtcSessionDescriptionInit>(),
+            ))
+            .await;
+
+            match result {
+                Ok(_) => {
+                    for candidate in &connection_string.ice_candidates {
+                        WebRTCManager::add_remote_candidate(&web_rtc_manager, &rtc_peer_connection, candidate);
+                    }
+                }
+                // An answer the connection cannot take leaves it unusable
+                Err(error) => {
+                    console::log_2(&"Rejected answer".into(), &error);
+                    WebRTCManager::report(&web_rtc_manager, OfferError::InvalidOffer.into());
+                    web_rtc_manager.borrow().parent_link.send_message(Msg::ResetWebRTC);
+                }
+            }
         });
-        let set_candidates_closure = Closure::wrap(set_candidates_function);
-
-        let _promise = web_rtc_manager
-            .borrow()
-            .rtc_peer_connection
-            .as_ref()
-            .unwrap()
-            .set_remote_description(&remote_description)
-            .catch(&set_remote_description_exception_handler)
-            .then(&set_candidates_closure);
-
-        set_candidates_closure.forget();
 
         Ok(())
     }
 
-    fn start_web_rtc(web_rtc_manager: Rc<RefCell<WebRTCManager>>) -> Result<(), JsValue> {
+    fn start_web_rtc(web_rtc_manager: Rc<RefCell<WebRTCManager>>) -> Result<(), WebRtcError> {
         let rtc_peer_connection = {
+            let ice_settings = web_rtc_manager.borrow().ice_settings.clone();
+
             let ice_servers = Array::new();
-            {
+            for server in &ice_settings.ice_servers {
                 let server_entry = Object::new();
+                let urls: Array = server.urls.iter().map(JsValue::from).collect();
 
-                Reflect::set(&server_entry, &"urls".into(), &STUN_SERVER.into())?;
+                Reflect::set(&server_entry, &"urls".into(), &urls)?;
+                if let Some(username) = &server.username {
+                    Reflect::set(&server_entry, &"username".into(), &username.into())?;
+                }
+                if let Some(credential) = &server.credential {
+                    Reflect::set(&server_entry, &"credential".into(), &credential.into())?;
+                }
 
-                ice_servers.push(&*server_entry);
+                ice_servers.push(&server_entry);
             }
 
-            let mut rtc_configuration = RtcConfiguration::new();
-            rtc_configuration.ice_servers(&ice_servers);
+            let rtc_configuration = RtcConfiguration::new();
+            rtc_configuration.set_ice_servers(&ice_servers);
+            if ice_settings.relay_only {
+                rtc_configuration.set_ice_transport_policy(RtcIceTransportPolicy::Relay);
+            }
 
             RtcPeerConnection::new_with_configuration(&rtc_configuration)?
         };
 
-        let create_offer_exception_handler = WebRTCManager::get_exception_handler(
-            web_rtc_manager.clone(),
-            "create_offer closure has encountered an exception".into(),
-        );
-
         let state = web_rtc_manager.borrow().state.clone();
 
         match state {
             State::Server(_connection_state) => {
-                let web_rtc_manager_rc_clone = web_rtc_manager.clone();
-
                 let mut data_channel_init = RtcDataChannelInit::new();
                 data_channel_init.ordered(true);
 
@@ -340,39 +354,30 @@ impl NetworkManager for WebRTCManager {
 
                 WebRTCManager::set_data_channel(web_rtc_manager.clone(), data_channel);
 
-                let create_offer_function: SingleArgJsFn = Box::new(move |offer: JsValue| {
-                    let rtc_session_description: RtcSessionDescriptionInit =
-                        offer.unchecked_into::<RtcSessionDescriptionInit>();
-
-                    console::log_1(&rtc_session_description.clone().into());
-
-                    web_rtc_manager_rc_clone.borrow_mut().offer = Some(String::from(
-                        JSON::stringify(&rtc_session_description).unwrap(),
-                    ));
-
-                    let set_local_description_exception_handler =
-                        WebRTCManager::get_exception_handler(
-                            web_rtc_manager_rc_clone.clone(),
-                            "set_local_description closure has encountered an exception".into(),
-                        );
-
-                    let _promise = web_rtc_manager_rc_clone
-                        .borrow_mut()
-                        .rtc_peer_connection
-                        .as_ref()
-                        .unwrap()
-                        .set_local_description(&rtc_session_description)
-                        .catch(&set_local_description_exception_handler);
-                });
-
-                let create_offer_closure = Closure::wrap(create_offer_function);
-
-                let _create_offer_promise = rtc_peer_connection
-                    .create_offer()
-                    .then(&create_offer_closure)
-                    .catch(&create_offer_exception_handler);
+                let web_rtc_manager = web_rtc_manager.clone();
+                let rtc_peer_connection = rtc_peer_connection.clone();
+                spawn_local(async move {
+                    let result = async {
+                        let offer = JsFuture::from(rtc_peer_connection.create_offer()).await?;
+                        console::log_1(&offer);
+
+                        let description = String::from(JSON::stringify(&offer)?);
+                        web_rtc_manager.borrow_mut().offer = Some(description.clone());
+                        JsFuture::from(rtc_peer_connection.set_local_description(offer.unchecked_ref()))
+                            .await?;
+
+                        let signaling = web_rtc_manager.borrow().signaling.clone();
+                        if let Some(signal) = signaling {
+                            signal.emit(SignalData::Description { description });
+                        }
+                        Ok::<(), WebRtcError>(())
+                    }
+                    .await;
 
-                create_offer_closure.forget();
+                    if let Err(error) = result {
+                        WebRTCManager::report(&web_rtc_manager, error);
+                    }
+                });
             }
 
             State::Client(_connection_state) => {
@@ -392,8 +397,8 @@ impl NetworkManager for WebRTCManager {
                 on_data_channel_closure.forget();
             }
 
-            _ => {
-                panic!("Not implemented");
+            State::Default => {
+                return Err(WebRtcError::NegotiationFailed("choose to host or join first".into()));
             }
         };
 
@@ -413,14 +418,28 @@ impl NetworkManager for WebRTCManager {
 
                         let saved_candidate = IceCandidate {
                             candidate: candidate_str,
-                            sdp_mid: candidate.sdp_mid().unwrap(),
-                            sdp_m_line_index: candidate.sdp_m_line_index().unwrap(),
+                            sdp_mid: candidate.sdp_mid().unwrap_or_default(),
+                            sdp_m_line_index: candidate.sdp_m_line_index().unwrap_or_default(),
                         };
 
                         web_rtc_manager_argument
                             .borrow_mut()
                             .ice_candidates
-                            .push(saved_candidate);
+                            .push(saved_candidate.clone());
+
+                        // Trickled on the path the description took: sealed
+                        // in-band once connected, else through the signaling
+                        // server. A connection code only carries what was
+                        // gathered when it was made.
+                        let manager = web_rtc_manager_argument.borrow();
+                        if manager.is_channel_open() {
+                            manager.parent_link.send_message(Msg::SendSecureFrame(
+                                manager.peer_id,
+                                Frame::Candidate { candidate: saved_candidate },
+                            ));
+                        } else if let Some(signal) = &manager.signaling {
+                            signal.emit(SignalData::Candidate { candidate: saved_candidate });
+                        }
                     }
                 }
             }) as SingleArgJsFn);
@@ -428,9 +447,14 @@ impl NetworkManager for WebRTCManager {
         let on_ice_connection_state_change_closure =
             WebRTCManager::get_on_ice_connection_state_change_closure(web_rtc_manager.clone());
 
+        let on_track_closure = WebRTCManager::get_on_track_closure(web_rtc_manager.clone());
+
         let on_ice_gathering_state_change_closure =
             WebRTCManager::get_on_ice_gathering_state_change_closure(web_rtc_manager.clone());
 
+        let on_negotiation_needed_closure =
+            WebRTCManager::get_on_negotiation_needed_closure(web_rtc_manager.clone());
+
         rtc_peer_connection
             .set_onicecandidate(Some(on_ice_candidate_closure.as_ref().unchecked_ref()));
 
@@ -446,64 +470,238 @@ impl NetworkManager for WebRTCManager {
                 .unchecked_ref(),
         ));
 
+        rtc_peer_connection.set_ontrack(Some(on_track_closure.as_ref().unchecked_ref()));
+
+        rtc_peer_connection.set_onnegotiationneeded(Some(
+            on_negotiation_needed_closure
+                .as_ref()
+                .unchecked_ref(),
+        ));
+
         web_rtc_manager.borrow_mut().rtc_peer_connection = Some(rtc_peer_connection);
 
         on_ice_candidate_closure.forget();
+        on_track_closure.forget();
         on_ice_connection_state_change_closure.forget();
         on_ice_gathering_state_change_closure.forget();
+        on_negotiation_needed_closure.forget();
 
         Ok(())
     }
-}
 
-impl WebRTCManager {
-    // TODO : handle error when adding ice_candidate
-    fn set_candidates(
+    fn restart_ice(web_rtc_manager: Rc<RefCell<WebRTCManager>>) -> Result<(), WebRtcError> {
+        let options = RtcOfferOptions::new();
+        options.set_ice_restart(true);
+
+        WebRTCManager::negotiate(web_rtc_manager, options)
+    }
+
+    fn set_local_media(
         web_rtc_manager: Rc<RefCell<WebRTCManager>>,
-        connection_string: &ConnectionString,
-    ) {
-        if web_rtc_manager.borrow().exit_offer_or_answer_early {
+        stream: Option<MediaStream>,
+    ) -> Result<(), WebRtcError> {
+        let rtc_peer_connection = WebRTCManager::peer_connection(&web_rtc_manager)?;
+
+        // New sections and directions fire `negotiationneeded`, which offers them
+        for kind in ["audio", "video"] {
+            let track = stream.as_ref().and_then(|stream| {
+                let tracks = match kind {
+                    "audio" => stream.get_audio_tracks(),
+                    _ => stream.get_video_tracks(),
+                };
+                tracks.get(0).dyn_into::<MediaStreamTrack>().ok()
+            });
+            let transceiver = rtc_peer_connection
+                .get_transceivers()
+                .iter()
+                .map(|transceiver| transceiver.unchecked_into::<RtcRtpTransceiver>())
+                .find(|transceiver| transceiver.receiver().track().kind() == kind);
+
+            match transceiver {
+                // Swapping the track of an existing sender needs no new offer
+                Some(transceiver) => {
+                    let _ = transceiver.sender().replace_track(track.as_ref());
+                    if stream.is_some() && transceiver.direction() != RtcRtpTransceiverDirection::Sendrecv {
+                        transceiver.set_direction(RtcRtpTransceiverDirection::Sendrecv);
+                    }
+                }
+                // Video is added even to audio calls, so switching to the
+                // camera or a screen later is only a track swap
+                None if stream.is_some() => {
+                    let init = RtcRtpTransceiverInit::new();
+                    init.set_direction(RtcRtpTransceiverDirection::Sendrecv);
+                    let transceiver = rtc_peer_connection.add_transceiver_with_str_and_init(kind, &init);
+                    let _ = transceiver.sender().replace_track(track.as_ref());
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn request_stats(web_rtc_manager: Rc<RefCell<WebRTCManager>>) {
+        let manager = web_rtc_manager.borrow();
+        let Some(rtc_peer_connection) = manager.rtc_peer_connection.clone() else {
             return;
+        };
+        let buffered_amount = manager
+            .data_channel
+            .as_ref()
+            .map(RtcDataChannel::buffered_amount)
+            .unwrap_or_default();
+        let peer_id = manager.peer_id;
+        let parent_link = manager.parent_link.clone();
+
+        spawn_local(async move {
+            let entries = JsFuture::from(rtc_peer_connection.get_stats())
+                .await
+                .and_then(|report| diagnostics::report_entries(&report));
+            match entries {
+                Ok(entries) => {
+                    let stats = ConnectionStats::from_report(&entries, js_sys::Date::now(), buffered_amount);
+                    parent_link.send_message(Msg::StatsReceived(peer_id, stats));
+                }
+                Err(error) => console::log_2(&"Failed to read connection stats".into(), &error),
+            }
+        });
+    }
+
+    fn use_signaling(&mut self, signal: Callback<SignalData>) {
+        self.signaling = Some(signal);
+    }
+
+    fn receive_signal(web_rtc_manager: Rc<RefCell<WebRTCManager>>, data: SignalData) {
+        let rtc_peer_connection = match web_rtc_manager.borrow().rtc_peer_connection.clone() {
+            Some(rtc_peer_connection) => rtc_peer_connection,
+            None => return,
+        };
+
+        let description = match data {
+            SignalData::Candidate { candidate } => {
+                WebRTCManager::receive_candidate(&web_rtc_manager, candidate);
+                return;
+            }
+            SignalData::Description { description } => description,
+        };
+
+        spawn_local(async move {
+            let result = async {
+                let remote_description = JSON::parse(&description)?;
+                let is_offer = Reflect::get(&remote_description, &"type".into())?
+                    .as_string()
+                    .is_some_and(|kind| kind == "offer");
+
+                JsFuture::from(rtc_peer_connection.set_remote_description(
+                    remote_description.unchecked_ref::<RtcSessionDescriptionInit>(),
+                ))
+                .await?;
+                WebRTCManager::add_pending_candidates(&web_rtc_manager, &rtc_peer_connection);
+
+                if is_offer {
+                    let answer = JsFuture::from(rtc_peer_connection.create_answer()).await?;
+                    JsFuture::from(rtc_peer_connection.set_local_description(answer.unchecked_ref()))
+                        .await?;
+                    let answer = String::from(JSON::stringify(&answer)?);
+                    web_rtc_manager.borrow_mut().offer = Some(answer.clone());
+
+                    let signaling = web_rtc_manager.borrow().signaling.clone();
+                    if let Some(signal) = signaling {
+                        signal.emit(SignalData::Description { description: answer });
+                    }
+                }
+
+                Ok::<(), WebRtcError>(())
+            }
+            .await;
+
+            if let Err(error) = result {
+                WebRTCManager::report(&web_rtc_manager, error);
+            }
+        });
+    }
+
+    fn receive_renegotiation(web_rtc_manager: Rc<RefCell<WebRTCManager>>, data: SignalData) {
+        match data {
+            SignalData::Description { description } => {
+                WebRTCManager::handle_remote_description(web_rtc_manager, description)
+            }
+            SignalData::Candidate { candidate } => WebRTCManager::receive_candidate(&web_rtc_manager, candidate),
         }
+    }
+}
 
-        for candidate in &connection_string.ice_candidates {
-            let mut ice_candidate_init = RtcIceCandidateInit::new("");
+impl WebRTCManager {
+    fn peer_connection(web_rtc_manager: &Rc<RefCell<WebRTCManager>>) -> Result<RtcPeerConnection, WebRtcError> {
+        web_rtc_manager
+            .borrow()
+            .rtc_peer_connection
+            .clone()
+            .ok_or_else(|| WebRtcError::NegotiationFailed("the connection has not started".into()))
+    }
 
-            ice_candidate_init.candidate(&candidate.candidate);
-            ice_candidate_init.sdp_mid(Some(&candidate.sdp_mid));
-            ice_candidate_init.sdp_m_line_index(Some(candidate.sdp_m_line_index));
+    // Failures of the asynchronous steps are shown by the chat.
+    fn report(web_rtc_manager: &Rc<RefCell<WebRTCManager>>, error: WebRtcError) {
+        let manager = web_rtc_manager.borrow();
+        manager.parent_link.send_message(Msg::ConnectionError(manager.peer_id, error));
+    }
 
-            let ice_candidate = RtcIceCandidate::new(&ice_candidate_init).expect("valid candidate");
+    // One candidate that does not apply should not stop the others.
+    fn add_remote_candidate(
+        web_rtc_manager: &Rc<RefCell<WebRTCManager>>,
+        rtc_peer_connection: &RtcPeerConnection,
+        candidate: &IceCandidate,
+    ) {
+        let ice_candidate_init = RtcIceCandidateInit::new(&candidate.candidate);
+        ice_candidate_init.set_sdp_mid(Some(&candidate.sdp_mid));
+        ice_candidate_init.set_sdp_m_line_index(Some(candidate.sdp_m_line_index));
+
+        let promise = rtc_peer_connection
+            .add_ice_candidate_with_opt_rtc_ice_candidate_init(Some(&ice_candidate_init));
+        let web_rtc_manager = web_rtc_manager.clone();
+        spawn_local(async move {
+            if let Err(error) = JsFuture::from(promise).await
+                // Candidates for an offer we ignored have nothing to apply to
+                && !web_rtc_manager.borrow().ignore_offer
+            {
+                console::log_2(&"Failed to add a remote candidate".into(), &error);
+            }
+        });
+    }
 
-            let add_candidate_exception_handler = WebRTCManager::get_exception_handler(
-                web_rtc_manager.clone(),
-                "add_candidate closure has encountered an exception".into(),
-            );
+    // Candidates can overtake the description they belong to; those wait for it.
+    fn receive_candidate(web_rtc_manager: &Rc<RefCell<WebRTCManager>>, candidate: IceCandidate) {
+        let Some(rtc_peer_connection) = web_rtc_manager.borrow().rtc_peer_connection.clone() else {
+            return;
+        };
 
-            let _promise = web_rtc_manager
-                .borrow()
-                .rtc_peer_connection
-                .as_ref()
-                .unwrap()
-                .add_ice_candidate_with_opt_rtc_ice_candidate(Some(&ice_candidate))
-                .catch(&add_candidate_exception_handler);
+        if rtc_peer_connection.remote_description().is_some() {
+            WebRTCManager::add_remote_candidate(web_rtc_manager, &rtc_peer_connection, &candidate);
+        } else {
+            web_rtc_manager.borrow_mut().pending_candidates.push(candidate);
+        }
+    }
+
+    fn add_pending_candidates(web_rtc_manager: &Rc<RefCell<WebRTCManager>>, rtc_peer_connection: &RtcPeerConnection) {
+        let pending = std::mem::take(&mut web_rtc_manager.borrow_mut().pending_candidates);
+        for candidate in &pending {
+            WebRTCManager::add_remote_candidate(web_rtc_manager, rtc_peer_connection, candidate);
         }
     }
 
+    fn is_channel_open(&self) -> bool {
+        self.data_channel
+            .as_ref()
+            .is_some_and(|data_channel| data_channel.ready_state() == RtcDataChannelState::Open)
+    }
+
+    // Perfect negotiation: the guest is polite and gives way when both sides
+    // offer at once, the host ignores the guest's offer and keeps its own.
+    fn is_polite(&self) -> bool {
+        matches!(self.state, State::Client(_))
+    }
+
     fn parse_base64_str_to_connection(str: &str) -> Result<ConnectionString, OfferError> {
-        base64::decode(str)
-            .map_err(|_| OfferError::InvalidBase64)
-            .and_then(|a| {
-                let to_str = str::from_utf8(&a);
-                match to_str {
-                    Ok(a) => Ok(a.to_string()),
-                    Err(_) => Err(OfferError::InvalidString),
-                }
-            })
-            .and_then(|a_str| {
-                serde_json::from_str::<ConnectionString>(&a_str)
-                    .map_err(|_| OfferError::SerializationError)
-            })
+        connection_code::decode(str)
             .and_then(|connection_string| {
                 let remote_description = JSON::parse(&connection_string.offer);
                 if remote_description.is_err() {
@@ -520,12 +718,14 @@ impl WebRTCManager {
         web_rtc_manager: Rc<RefCell<WebRTCManager>>,
     ) -> SingleArgClosure {
         Closure::wrap(Box::new(move |_send_channel: JsValue| {
-            let state = web_rtc_manager
+            let Some(state) = web_rtc_manager
                 .borrow()
                 .data_channel
                 .as_ref()
-                .unwrap()
-                .ready_state();
+                .map(RtcDataChannel::ready_state)
+            else {
+                return;
+            };
 
             let self_state = web_rtc_manager.borrow().get_state();
 
@@ -548,7 +748,28 @@ impl WebRTCManager {
             web_rtc_manager
                 .borrow()
                 .parent_link
-                .send_message(Msg::UpdateWebRTCState(web_rtc_state));
+                .send_message(Msg::UpdateWebRTCState(web_rtc_manager.borrow().peer_id, web_rtc_state));
+        }) as SingleArgJsFn)
+    }
+
+    // Refills the channel's buffer once it drained below the threshold.
+    fn get_buffered_amount_low_closure(web_rtc_manager: Rc<RefCell<WebRTCManager>>) -> SingleArgClosure {
+        Closure::wrap(Box::new(move |_: JsValue| {
+            let flushed = {
+                let manager = web_rtc_manager.borrow();
+                let Some(data_channel) = manager.data_channel.as_ref() else {
+                    return;
+                };
+                flush_send_queue(data_channel, &mut manager.send_queue.borrow_mut())
+            };
+
+            match flushed {
+                Ok(()) => {
+                    let manager = web_rtc_manager.borrow();
+                    manager.parent_link.send_message(Msg::SendQueueDrained(manager.peer_id));
+                }
+                Err(err) => WebRTCManager::report(&web_rtc_manager, err),
+            }
         }) as SingleArgJsFn)
     }
 
@@ -556,13 +777,18 @@ impl WebRTCManager {
         Closure::wrap(Box::new(move |arg: JsValue| {
             let message_event = arg.unchecked_into::<web_sys::MessageEvent>();
 
-            let msg_content: String = message_event.data().as_string().unwrap();
-            let msg = Message::new(msg_content, MessageSender::Other);
+            // Every frame is text; anything else did not come from this app
+            let Some(msg_content) = message_event.data().as_string() else {
+                return;
+            };
 
-            web_rtc_manager
-                .borrow()
-                .parent_link
-                .send_message(Msg::NewMessage(msg));
+            match protocol::decode(&msg_content) {
+                Ok(frame) => web_rtc_manager
+                    .borrow()
+                    .parent_link
+                    .send_message(Msg::ReceiveFrame(web_rtc_manager.borrow().peer_id, frame)),
+                Err(error) => console::log_1(&format!("Dropping frame: {:?}", error).into()),
+            }
         }) as SingleArgJsFn)
     }
 
@@ -570,10 +796,13 @@ impl WebRTCManager {
         web_rtc_manager: Rc<RefCell<WebRTCManager>>,
     ) -> SingleArgClosure {
         Closure::wrap(Box::new(move |_ice_connection_state_event: JsValue| {
-            let ice_new_state: RtcIceConnectionState = {
-                let inner = web_rtc_manager.borrow();
-                let connection: &RtcPeerConnection = inner.rtc_peer_connection.as_ref().unwrap();
-                connection.ice_connection_state()
+            let Some(ice_new_state) = web_rtc_manager
+                .borrow()
+                .rtc_peer_connection
+                .as_ref()
+                .map(RtcPeerConnection::ice_connection_state)
+            else {
+                return;
             };
 
             let self_state = web_rtc_manager.borrow().get_state();
@@ -597,7 +826,7 @@ impl WebRTCManager {
             web_rtc_manager
                 .borrow()
                 .parent_link
-                .send_message(Msg::UpdateWebRTCState(web_rtc_state));
+                .send_message(Msg::UpdateWebRTCState(web_rtc_manager.borrow().peer_id, web_rtc_state));
         }) as SingleArgJsFn)
     }
 
@@ -605,10 +834,13 @@ impl WebRTCManager {
         web_rtc_manager: Rc<RefCell<WebRTCManager>>,
     ) -> SingleArgClosure {
         Closure::wrap(Box::new(move |_ice_gathering_state: JsValue| {
-            let ice_new_state: RtcIceGatheringState = {
-                let inner = web_rtc_manager.borrow();
-                let connection: &RtcPeerConnection = inner.rtc_peer_connection.as_ref().unwrap();
-                connection.ice_gathering_state()
+            let Some(ice_new_state) = web_rtc_manager
+                .borrow()
+                .rtc_peer_connection
+                .as_ref()
+                .map(RtcPeerConnection::ice_gathering_state)
+            else {
+                return;
             };
 
             let self_state = web_rtc_manager.borrow().get_state();
@@ -631,28 +863,180 @@ impl WebRTCManager {
             web_rtc_manager
                 .borrow()
                 .parent_link
-                .send_message(Msg::UpdateWebRTCState(web_rtc_state));
+                .send_message(Msg::UpdateWebRTCState(web_rtc_manager.borrow().peer_id, web_rtc_state));
         }) as SingleArgJsFn)
     }
 
-    fn get_exception_handler(
-        _web_rtc_manager: Rc<RefCell<WebRTCManager>>,
-        message: String,
-    ) -> SingleArgClosure {
-        Closure::wrap(Box::new(move |a: JsValue| {
-            // TODO
-            console::log_1(&"Exception handler !".into());
-            console::log_1(&JsValue::from_str(&message));
-            console::log_1(&a);
-
-            web_sys::Window::alert_with_message(
-                &web_sys::window().unwrap(),
-                &"Promise encountered an exception. See console for details".to_string(),
-            )
-            .expect("alert should work");
+    // Offers whatever changed once the session is up: media sections, ICE
+    // restarts. The first offer travels in the connection code or through
+    // the signaling server instead.
+    fn get_on_negotiation_needed_closure(web_rtc_manager: Rc<RefCell<WebRTCManager>>) -> SingleArgClosure {
+        Closure::wrap(Box::new(move |_: JsValue| {
+            if !web_rtc_manager.borrow().is_channel_open() {
+                return;
+            }
+            if let Err(error) = WebRTCManager::negotiate(web_rtc_manager.clone(), RtcOfferOptions::new()) {
+                WebRTCManager::report(&web_rtc_manager, error);
+            }
         }) as SingleArgJsFn)
     }
 
+    // Makes a new offer on the connected session and sends it in-band.
+    // Either side may call this; a colliding offer from the peer is sorted
+    // out in `handle_remote_description`.
+    fn negotiate(
+        web_rtc_manager: Rc<RefCell<WebRTCManager>>,
+        options: RtcOfferOptions,
+    ) -> Result<(), WebRtcError> {
+        let rtc_peer_connection = WebRTCManager::peer_connection(&web_rtc_manager)?;
+
+        spawn_local(async move {
+            let result = async {
+                web_rtc_manager.borrow_mut().making_offer = true;
+                let offered = async {
+                    let offer =
+                        JsFuture::from(rtc_peer_connection.create_offer_with_rtc_offer_options(&options))
+                            .await?;
+                    // The peer's offer got in first; `negotiationneeded` fires
+                    // again once it is answered if ours is still needed
+                    if rtc_peer_connection.signaling_state() != RtcSignalingState::Stable {
+                        return Ok(false);
+                    }
+                    JsFuture::from(rtc_peer_connection.set_local_description(offer.unchecked_ref()))
+                        .await?;
+                    Ok::<bool, WebRtcError>(true)
+                }
+                .await;
+                web_rtc_manager.borrow_mut().making_offer = false;
+                if !offered? {
+                    return Ok(());
+                }
+                WebRTCManager::send_local_description(&web_rtc_manager, &rtc_peer_connection)?;
+
+                for _ in 0..ANSWER_MAX_POLLS {
+                    if rtc_peer_connection.signaling_state() != RtcSignalingState::HaveLocalOffer {
+                        return Ok(());
+                    }
+                    TimeoutFuture::new(ANSWER_POLL_MS).await;
+                }
+                let rollback = RtcSessionDescriptionInit::new(RtcSdpType::Rollback);
+                JsFuture::from(rtc_peer_connection.set_local_description(&rollback)).await?;
+                Err(WebRtcError::Timeout)
+            }
+            .await;
+
+            match result {
+                Ok(()) => {}
+                // Expected while reconnecting; the reconnection banner covers it
+                Err(WebRtcError::ChannelClosed) => console::log_1(&"Renegotiation skipped, channel closed".into()),
+                Err(error) => WebRTCManager::report(&web_rtc_manager, error),
+            }
+        });
+
+        Ok(())
+    }
+
+    // Collects the peer's tracks into one stream for the call view.
+    fn get_on_track_closure(web_rtc_manager: Rc<RefCell<WebRTCManager>>) -> SingleArgClosure {
+        Closure::wrap(Box::new(move |track_event: JsValue| {
+            let track_event = track_event.unchecked_into::<RtcTrackEvent>();
+
+            let remote_stream = {
+                let mut manager = web_rtc_manager.borrow_mut();
+                match &manager.remote_stream {
+                    Some(remote_stream) => remote_stream.clone(),
+                    None => match MediaStream::new() {
+                        Ok(remote_stream) => manager.remote_stream.insert(remote_stream).clone(),
+                        Err(error) => {
+                            console::log_2(&"Failed to create the remote stream".into(), &error);
+                            return;
+                        }
+                    },
+                }
+            };
+            remote_stream.add_track(&track_event.track());
+
+            let manager = web_rtc_manager.borrow();
+            manager
+                .parent_link
+                .send_message(Msg::RemoteMedia(manager.peer_id, remote_stream));
+        }) as SingleArgJsFn)
+    }
+
+    // Applies an offer or answer received over the data channel, answering
+    // offers the same way so the session renegotiates without a new code.
+    // When both sides offered at once the impolite host ignores the guest's
+    // offer, and the polite guest rolls its own back and answers the host's.
+    fn handle_remote_description(web_rtc_manager: Rc<RefCell<WebRTCManager>>, description: String) {
+        let rtc_peer_connection = match web_rtc_manager.borrow().rtc_peer_connection.clone() {
+            Some(rtc_peer_connection) => rtc_peer_connection,
+            None => return,
+        };
+
+        spawn_local(async move {
+            let result = async {
+                let remote_description = JSON::parse(&description)?;
+                let is_offer = Reflect::get(&remote_description, &"type".into())?
+                    .as_string()
+                    .is_some_and(|kind| kind == "offer");
+
+                let offer_collision = is_offer
+                    && (web_rtc_manager.borrow().making_offer
+                        || rtc_peer_connection.signaling_state() != RtcSignalingState::Stable);
+                let ignore_offer = offer_collision && !web_rtc_manager.borrow().is_polite();
+                web_rtc_manager.borrow_mut().ignore_offer = ignore_offer;
+                if ignore_offer {
+                    console::log_1(&"Ignoring the peer's offer, ours was first".into());
+                    return Ok(());
+                }
+
+                if offer_collision
+                    && rtc_peer_connection.signaling_state() == RtcSignalingState::HaveLocalOffer
+                {
+                    let rollback = RtcSessionDescriptionInit::new(RtcSdpType::Rollback);
+                    JsFuture::from(rtc_peer_connection.set_local_description(&rollback)).await?;
+                }
+                JsFuture::from(rtc_peer_connection.set_remote_description(
+                    remote_description.unchecked_ref::<RtcSessionDescriptionInit>(),
+                ))
+                .await?;
+                WebRTCManager::add_pending_candidates(&web_rtc_manager, &rtc_peer_connection);
+
+                if is_offer {
+                    let answer = JsFuture::from(rtc_peer_connection.create_answer()).await?;
+                    JsFuture::from(rtc_peer_connection.set_local_description(answer.unchecked_ref()))
+                        .await?;
+                    WebRTCManager::send_local_description(&web_rtc_manager, &rtc_peer_connection)?;
+                }
+
+                Ok::<(), WebRtcError>(())
+            }
+            .await;
+
+            if let Err(error) = result {
+                WebRTCManager::report(&web_rtc_manager, error);
+            }
+        });
+    }
+
+    // Sent right away; candidates gathered from here on trickle after it
+    // as `Frame::Candidate`.
+    fn send_local_description(
+        web_rtc_manager: &Rc<RefCell<WebRTCManager>>,
+        rtc_peer_connection: &RtcPeerConnection,
+    ) -> Result<(), WebRtcError> {
+        let local_description = rtc_peer_connection
+            .local_description()
+            .ok_or_else(|| WebRtcError::NegotiationFailed("no local description".into()))?;
+        let description = String::from(JSON::stringify(&local_description)?);
+
+        let manager = web_rtc_manager.borrow();
+        manager
+            .parent_link
+            .send_message(Msg::SendSecureFrame(manager.peer_id, Frame::Description { description }));
+        Ok(())
+    }
+
     fn set_data_channel(web_rtc_manager: Rc<RefCell<WebRTCManager>>, data_channel: RtcDataChannel) {
         let channel_status_change_closure =
             WebRTCManager::get_channel_status_change_closure(web_rtc_manager.clone());
@@ -667,6 +1051,19 @@ impl WebRTCManager {
 
         on_data_closure.forget();
 
+        let buffered_amount_low_closure = WebRTCManager::get_buffered_amount_low_closure(web_rtc_manager.clone());
+        data_channel.set_buffered_amount_low_threshold(BUFFERED_AMOUNT_LOW);
+        data_channel.set_onbufferedamountlow(Some(buffered_amount_low_closure.as_ref().unchecked_ref()));
+
+        buffered_amount_low_closure.forget();
+
         web_rtc_manager.borrow_mut().data_channel = Some(data_channel);
     }
-}
\ No newline at end of file
+}
+// Hands queued frames to the channel until its buffer is full.
+fn flush_send_queue(data_channel: &RtcDataChannel, send_queue: &mut SendQueue) -> Result<(), WebRtcError> {
+    send_queue.flush(
+        || data_channel.buffered_amount() < MAX_BUFFERED_AMOUNT,
+        |payload| data_channel.send_with_str(payload).map_err(|_| WebRtcError::ChannelClosed),
+    )
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeSet;
+
+use serde::{Deserialize, Serialize};
+
+/// Size of the drawing surface in board units. Every peer draws in these
+/// units whatever size the canvas is shown at.
+pub const BOARD_WIDTH: u16 = 960;
+pub const BOARD_HEIGHT: u16 = 600;
+
+/// Limits on what a peer may add, so one peer cannot grow everyone's board
+/// without bound. Items and actions stay counted after they are erased or
+/// undone. There is room for more actions than items, so a full board can
+/// still be erased.
+pub const MAX_BOARD_ITEMS: usize = 5_000;
+pub const MAX_BOARD_ACTIONS: usize = 2 * MAX_BOARD_ITEMS;
+pub const MAX_STROKE_POINTS: usize = 2_000;
+
+const ELLIPSE_SEGMENTS: usize = 32;
+
+pub type Point = [u16; 2];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Shape {
+    /// A freehand line through `points`; a single point is a dot.
+    Stroke { points: Vec<Point> },
+    Line { from: Point, to: Point },
+    /// Rectangle and ellipse span the box between two opposite corners.
+    Rect { from: Point, to: Point },
+    Ellipse { from: Point, to: Point },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Item {
+    pub id: String,
+    pub shape: Shape,
+    /// `0xRRGGBB`
+    pub color: u32,
+    pub width: u8,
+}
+
+/// One change to the board. Each is applied at most once, so replaying a
+/// board to a peer that already has part of it is harmless.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BoardOp {
+    Add { item: Item },
+    /// Hides `items` until the erase is undone.
+    Erase { id: String, items: Vec<String> },
+    /// Takes back one of the author's own adds or erases.
+    Undo { id: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ActionKind {
+    Add,
+    Erase(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Action {
+    id: String, // an add shares its item's id
+    author: String,
+    kind: ActionKind,
+    undone: bool,
+}
+
+/// The board as every peer rebuilds it from the ops it saw. An item shows
+/// while its add stands and no standing erase covers it, so peers that saw
+/// different authors' ops in a different order still agree.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Board {
+    items: Vec<Item>,
+    actions: Vec<Action>,
+}
+
+impl Board {
+    /// Returns whether the board changed. Undo is only accepted from the
+    /// author of what it takes back, ops past the limits are dropped, and so
+    /// are erases of items the board does not have.
+    pub fn apply(&mut self, author: &str, op: BoardOp) -> bool {
+        match op {
+            BoardOp::Add { item } => {
+                if self.has_action(&item.id) || self.is_full() || !item.shape.is_within_limits() {
+                    return false;
+                }
+                self.actions.push(Action {
+                    id: item.id.clone(),
+                    author: author.to_string(),
+                    kind: ActionKind::Add,
+                    undone: false,
+                });
+                self.items.push(item);
+                true
+            }
+            BoardOp::Erase { id, items } => {
+                if self.has_action(&id) || self.actions.len() >= MAX_BOARD_ACTIONS || !self.has_items(&items) {
+                    return false;
+                }
+                self.actions.push(Action {
+                    id,
+                    author: author.to_string(),
+                    kind: ActionKind::Erase(items),
+                    undone: false,
+                });
+                true
+            }
+            BoardOp::Undo { id } => {
+                match self.actions.iter_mut().find(|action| action.id == id && action.author == author) {
+                    Some(action) if !action.undone => {
+                        action.undone = true;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= MAX_BOARD_ITEMS || self.actions.len() >= MAX_BOARD_ACTIONS
+    }
+
+    /// Items on the board, oldest first so newer ones are drawn on top.
+    pub fn visible(&self) -> impl Iterator<Item = &Item> {
+        self.items.iter().filter(|item| self.is_visible(&item.id))
+    }
+
+    /// The author's latest add or erase that still stands.
+    pub fn last_action_of(&self, author: &str) -> Option<String> {
+        self.actions
+            .iter()
+            .rev()
+            .find(|action| action.author == author && !action.undone)
+            .map(|action| action.id.clone())
+    }
+
+    /// Visible items whose outline passes within `radius` of `at`.
+    pub fn hits(&self, at: Point, radius: f64) -> Vec<String> {
+        self.visible()
+            .filter(|item| item.shape.distance_to(at) <= radius + item.width as f64 / 2.0)
+            .map(|item| item.id.clone())
+            .collect()
+    }
+
+    /// Ops that rebuild this board, with the author of each, for a peer
+    /// that just joined.
+    pub fn replay(&self) -> Vec<(String, BoardOp)> {
+        let mut ops = vec![];
+        for action in &self.actions {
+            let op = match &action.kind {
+                ActionKind::Add => match self.items.iter().find(|item| item.id == action.id) {
+                    Some(item) => BoardOp::Add { item: item.clone() },
+                    None => continue,
+                },
+                ActionKind::Erase(items) => BoardOp::Erase { id: action.id.clone(), items: items.clone() },
+            };
+            ops.push((action.author.clone(), op));
+        }
+        ops.extend(
+            self.actions
+                .iter()
+                .filter(|action| action.undone)
+                .map(|action| (action.author.clone(), BoardOp::Undo { id: action.id.clone() })),
+        );
+        ops
+    }
+
+    fn has_action(&self, id: &str) -> bool {
+        self.actions.iter().any(|action| action.id == id)
+    }
+
+    // Whether `ids` names items on the board, each of them once. An erase
+    // is only sent after its author saw the adds, and the host relays ops
+    // in the order it got them, so every peer has the items by then.
+    fn has_items(&self, ids: &[String]) -> bool {
+        let known: BTreeSet<&str> = self.items.iter().map(|item| item.id.as_str()).collect();
+        let mut named = BTreeSet::new();
+        !ids.is_empty() && ids.iter().all(|id| known.contains(id.as_str()) && named.insert(id.as_str()))
+    }
+
+    fn is_visible(&self, item_id: &str) -> bool {
+        let added = self
+            .actions
+            .iter()
+            .any(|action| action.id == item_id && action.kind == ActionKind::Add && !action.undone);
+        let erased = self.actions.iter().any(|action| {
+            !action.undone && matches!(&action.kind, ActionKind::Erase(items) if items.iter().any(|id| id == item_id))
+        });
+        added && !erased
+    }
+}
+
+impl Shape {
+    /// The outline as line segments; an ellipse is approximated by a polygon.
+    pub fn segments(&self) -> Vec<(Point, Point)> {
+        match self {
+            Shape::Stroke { points } => match points.as_slice() {
+                [point] => vec![(*point, *point)],
+                points => points.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+            },
+            Shape::Line { from, to } => vec![(*from, *to)],
+            Shape::Rect { from, to } => {
+                let corners = [*from, [to[0], from[1]], *to, [from[0], to[1]]];
+                (0..4).map(|i| (corners[i], corners[(i + 1) % 4])).collect()
+            }
+            Shape::Ellipse { from, to } => {
+                let (cx, cy, rx, ry) = ellipse_box(*from, *to);
+                let points: Vec<Point> = (0..ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let angle = i as f64 * std::f64::consts::TAU / ELLIPSE_SEGMENTS as f64;
+                        [(cx + rx * angle.cos()).round() as u16, (cy + ry * angle.sin()).round() as u16]
+                    })
+                    .collect();
+                (0..ELLIPSE_SEGMENTS)
+                    .map(|i| (points[i], points[(i + 1) % ELLIPSE_SEGMENTS]))
+                    .collect()
+            }
+        }
+    }
+
+    fn is_within_limits(&self) -> bool {
+        match self {
+            Shape::Stroke { points } => !points.is_empty() && points.len() <= MAX_STROKE_POINTS,
+            Shape::Line { .. } | Shape::Rect { .. } | Shape::Ellipse { .. } => true,
+        }
+    }
+
+    fn distance_to(&self, at: Point) -> f64 {
+        self.segments()
+            .into_iter()
+            .map(|(from, to)| distance_to_segment(at, from, to))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// Centre and radii of the ellipse inside the box between two corners.
+pub fn ellipse_box(from: Point, to: Point) -> (f64, f64, f64, f64) {
+    let (x0, y0, x1, y1) = (from[0] as f64, from[1] as f64, to[0] as f64, to[1] as f64);
+    ((x0 + x1) / 2.0, (y0 + y1) / 2.0, (x1 - x0).abs() / 2.0, (y1 - y0).abs() / 2.0)
+}
+
+/// Keeps a point from the pointer inside the board.
+pub fn clamp_point(x: f64, y: f64) -> Point {
+    [x.clamp(0.0, BOARD_WIDTH as f64) as u16, y.clamp(0.0, BOARD_HEIGHT as f64) as u16]
+}
+
+fn distance_to_segment(at: Point, from: Point, to: Point) -> f64 {
+    let [px, py] = at.map(f64::from);
+    let [ax, ay] = from.map(f64::from);
+    let [bx, by] = to.map(f64::from);
+    let (dx, dy) = (bx - ax, by - ay);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0.0 {
+        0.0
+    } else {
+        (((px - ax) * dx + (py - ay) * dy) / length_squared).clamp(0.0, 1.0)
+    };
+    (px - (ax + t * dx)).hypot(py - (ay + t * dy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::webrtc_chat::protocol::{self, Frame};
+
+    fn item(id: &str, shape: Shape) -> Item {
+        Item { id: id.to_string(), shape, color: 0x111827, width: 4 }
+    }
+
+    fn line(id: &str) -> BoardOp {
+        BoardOp::Add { item: item(id, Shape::Line { from: [10, 10], to: [100, 10] }) }
+    }
+
+    fn visible_ids(board: &Board) -> Vec<&str> {
+        board.visible().map(|item| item.id.as_str()).collect()
+    }
+
+    #[test]
+    fn erasing_and_undo_agree_in_any_order() {
+        let ops = [
+            ("alice", line("a1")),
+            ("bob", line("b1")),
+            ("bob", BoardOp::Erase { id: "b2".to_string(), items: vec!["a1".to_string()] }),
+            ("alice", BoardOp::Undo { id: "a1".to_string() }),
+            ("bob", BoardOp::Undo { id: "b2".to_string() }),
+        ];
+
+        let mut in_order = Board::default();
+        for (author, op) in ops.clone() {
+            assert!(in_order.apply(author, op));
+        }
+        // Alice's ops and Bob's ops interleaved differently
+        let mut interleaved = Board::default();
+        for index in [1, 0, 2, 4, 3] {
+            let (author, op) = ops[index].clone();
+            interleaved.apply(author, op);
+        }
+
+        assert_eq!(visible_ids(&in_order), ["b1"]);
+        assert_eq!(visible_ids(&interleaved), ["b1"]);
+        assert!(!in_order.apply("alice", line("a1")), "ops apply once");
+    }
+
+    #[test]
+    fn undo_only_takes_back_your_own_actions() {
+        let mut board = Board::default();
+        board.apply("alice", line("a1"));
+        board.apply("alice", line("a2"));
+        board.apply("bob", line("b1"));
+
+        assert!(!board.apply("bob", BoardOp::Undo { id: "a2".to_string() }));
+        assert_eq!(board.last_action_of("alice").as_deref(), Some("a2"));
+
+        board.apply("alice", BoardOp::Undo { id: "a2".to_string() });
+        assert_eq!(board.last_action_of("alice").as_deref(), Some("a1"));
+        assert_eq!(visible_ids(&board), ["a1", "b1"]);
+    }
+
+    #[test]
+    fn replay_rebuilds_the_board() {
+        let mut board = Board::default();
+        board.apply("alice", line("a1"));
+        board.apply("bob", BoardOp::Erase { id: "b1".to_string(), items: vec!["a1".to_string()] });
+        board.apply("bob", BoardOp::Undo { id: "b1".to_string() });
+
+        let mut joined = Board::default();
+        for (author, op) in board.replay() {
+            let frame = Frame::Board { op, author: Some(author) };
+            let Ok(Frame::Board { op, author: Some(author) }) = protocol::decode(&protocol::encode(&frame)) else {
+                panic!("board frames round trip");
+            };
+            joined.apply(&author, op);
+        }
+
+        assert_eq!(joined, board);
+        assert_eq!(joined.last_action_of("bob"), None);
+    }
+
+    #[test]
+    fn adds_past_the_limits_are_dropped() {
+        let mut board = Board::default();
+        let stroke = |id: &str, len: usize| BoardOp::Add {
+            item: item(id, Shape::Stroke { points: vec![[1, 1]; len] }),
+        };
+
+        assert!(!board.apply("alice", stroke("empty", 0)));
+        assert!(!board.apply("alice", stroke("long", MAX_STROKE_POINTS + 1)));
+        assert!(board.apply("alice", stroke("ok", MAX_STROKE_POINTS)));
+
+        for i in 1..MAX_BOARD_ITEMS {
+            board.apply("alice", line(&i.to_string()));
+        }
+        assert!(board.is_full());
+        assert!(!board.apply("bob", line("one more")));
+        assert!(board.apply("bob", BoardOp::Erase { id: "e".to_string(), items: vec!["ok".to_string()] }));
+    }
+
+    #[test]
+    fn erases_are_bounded_too() {
+        let mut board = Board::default();
+        board.apply("alice", line("a1"));
+        let erase = |id: &str, items: &[&str]| BoardOp::Erase {
+            id: id.to_string(),
+            items: items.iter().map(|item| item.to_string()).collect(),
+        };
+
+        assert!(!board.apply("bob", erase("unknown", &["nowhere"])));
+        assert!(!board.apply("bob", erase("twice", &["a1", "a1"])));
+        assert!(!board.apply("bob", erase("nothing", &[])));
+
+        for i in 1..MAX_BOARD_ACTIONS {
+            assert!(board.apply("bob", erase(&i.to_string(), &["a1"])));
+        }
+        assert!(board.is_full());
+        assert!(!board.apply("bob", erase("one more", &["a1"])));
+    }
+
+    #[test]
+    fn hits_follow_the_outline() {
+        let mut board = Board::default();
+        board.apply("alice", BoardOp::Add { item: item("r", Shape::Rect { from: [100, 100], to: [200, 200] }) });
+        board.apply("alice", BoardOp::Add { item: item("dot", Shape::Stroke { points: vec![[400, 400]] }) });
+        board.apply("alice", BoardOp::Add { item: item("e", Shape::Ellipse { from: [500, 100], to: [700, 200] }) });
+
+        assert_eq!(board.hits([150, 103], 2.0), ["r"]);
+        assert!(board.hits([150, 150], 2.0).is_empty(), "the inside of a shape is not ink");
+        assert_eq!(board.hits([403, 400], 2.0), ["dot"]);
+        assert_eq!(board.hits([700, 150], 2.0), ["e"]);
+        assert!(board.hits([600, 150], 2.0).is_empty());
+        assert_eq!(clamp_point(-5.0, 10_000.0), [0, BOARD_HEIGHT]);
+    }
+}